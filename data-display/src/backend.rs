@@ -0,0 +1,664 @@
+//! Backend abstraction used by the display windows
+//!
+//! Windows never talk to a server directly. They call into a `Backend`,
+//!     which is picked at compile time:
+//! - `tcp-backend` talks to the Rust-Tcp server through client-api-lib
+//! - `mock-backend` (default) keeps everything in memory so the UI runs on its own
+//!
+
+#[cfg(feature = "mock-backend")]
+#[cfg_attr(feature = "tcp-backend", allow(dead_code))]
+mod mock;
+#[cfg(feature = "tcp-backend")]
+mod tcp;
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::time;
+
+#[cfg(not(any(feature = "mock-backend", feature = "tcp-backend")))]
+compile_error!("Enable at least one of the `mock-backend` or `tcp-backend` features");
+
+/// Future returned by every backend call. Futures own their arguments so
+///     they can be handed straight to `wasm_bindgen_futures::spawn_local`.
+pub type BackendFuture<T> = Pin<Box<dyn Future<Output = Result<T, BackendError>>>>;
+
+/// Reasons a backend call can fail
+#[derive(Debug, Clone)]
+pub enum BackendError {
+    /// The auth token is missing, invalid or expired and the user must log in again
+    Unauthorized,
+    /// The server answered with an unexpected status code
+    Status(u16),
+    /// The response could not be understood
+    #[allow(dead_code)]
+    Parse(String),
+    /// The backend does not offer this operation
+    #[allow(dead_code)]
+    Unsupported,
+}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackendError::Unauthorized => write!(f, "Not logged in or login expired"),
+            BackendError::Status(status) => write!(f, "Status: {}", status),
+            BackendError::Parse(e) => write!(f, "Failed to parse response: {}", e),
+            BackendError::Unsupported => write!(f, "Not supported by this backend"),
+        }
+    }
+}
+
+/// Token returned by a successful login and passed to every later call.
+///     Whether the backend can send it on is up to the backend, see `TcpBackend`.
+///
+/// Debug output is redacted so the token never ends up in logs.
+#[derive(Clone, PartialEq, Eq)]
+pub struct AuthToken(String);
+
+impl AuthToken {
+    pub fn new(token: String) -> Self {
+        AuthToken(token)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for AuthToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "AuthToken(..)")
+    }
+}
+
+/// Profile details of a user account
+#[derive(Deserialize, Clone)]
+pub struct Account {
+    pub username: String,
+    pub email: String,
+}
+
+/// Credential a data acquisition unit uses to upload to the server
+#[derive(Deserialize, Clone)]
+pub struct DeviceKey {
+    pub key_id: String,
+    /// Label given when the key was generated, e.g. which unit it is for
+    pub name: String,
+    pub created: String,
+    /// Time the device last authenticated with the key, if ever
+    pub last_used: Option<String>,
+    /// Set once the key has been rotated. The key stops working after this time.
+    pub expires: Option<String>,
+}
+
+/// A freshly generated device key together with its secret.
+///
+/// The secret is only ever returned here and cannot be fetched again.
+#[derive(Deserialize)]
+pub struct NewDeviceKey {
+    pub key: DeviceKey,
+    pub secret: String,
+}
+
+/// How the device reaches the client
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ConnectionType {
+    /// Straight to the device over the local network
+    Direct,
+    /// Relayed through the server
+    Proxy,
+}
+
+/// Latest status report from the user's data acquisition unit.
+///
+/// Metrics the device did not report are None.
+#[derive(Deserialize, Clone)]
+pub struct DeviceStatus {
+    /// Time of the device's last report, None if it has never reported
+    pub last_seen: Option<String>,
+    pub connection: Option<ConnectionType>,
+    pub ipv4: Option<String>,
+    pub ipv6: Option<String>,
+    /// dBm
+    pub cellular_dbm: Option<f64>,
+    /// °C
+    pub cpu_temp_c: Option<f64>,
+    /// Percent across all cores
+    pub cpu_load_pct: Option<f64>,
+    /// °C
+    pub battery_temp_c: Option<f64>,
+    /// Percent of full charge
+    pub battery_pct: Option<f64>,
+}
+
+/// Data blob keys the device can record, by sensor group
+pub const SENSOR_CHANNELS: [(&str, &[&str]); 4] = [
+    ("GPS", &["lat", "lon", "alt"]),
+    ("Accelerometer", &["accel_x", "accel_y", "accel_z"]),
+    ("Gyroscope", &["gyro_x", "gyro_y", "gyro_z"]),
+    ("DAC", &["dac_1", "dac_2", "dac_3", "dac_4"]),
+];
+
+/// Sample rates each sensor group supports, Hz
+pub const GPS_RATE_LIMITS_HZ: (f64, f64) = (0.1, 10.0);
+pub const ACCEL_RATE_LIMITS_HZ: (f64, f64) = (1.0, 1000.0);
+pub const GYRO_RATE_LIMITS_HZ: (f64, f64) = (1.0, 1000.0);
+pub const DAC_RATE_LIMITS_HZ: (f64, f64) = (1.0, 860.0);
+/// Full-scale ranges the accelerometer supports, ±g
+pub const ACCEL_RANGES_G: [u32; 4] = [2, 4, 8, 16];
+/// Full-scale ranges the gyroscope supports, ±°/s
+pub const GYRO_RANGES_DPS: [u32; 4] = [250, 500, 1000, 2000];
+/// DAC reference voltages the device supports, V
+pub const DAC_VREF_LIMITS_V: (f64, f64) = (1.0, 5.0);
+
+/// Acquisition settings on the device
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+pub struct DeviceConfig {
+    pub gps_rate_hz: f64,
+    pub accel_rate_hz: f64,
+    pub gyro_rate_hz: f64,
+    pub dac_rate_hz: f64,
+    /// Data blob keys the device records, from `SENSOR_CHANNELS`
+    pub enabled_channels: Vec<String>,
+    /// ±g
+    pub accel_range_g: u32,
+    /// ±°/s
+    pub gyro_range_dps: u32,
+    /// V
+    pub dac_vref: f64,
+}
+
+impl Default for DeviceConfig {
+    /// Settings the device ships with
+    fn default() -> Self {
+        DeviceConfig {
+            gps_rate_hz: 1.0,
+            accel_rate_hz: 10.0,
+            gyro_rate_hz: 10.0,
+            dac_rate_hz: 10.0,
+            enabled_channels: SENSOR_CHANNELS.iter()
+                .flat_map(|(_, channels)| channels.iter().map(|c| c.to_string()))
+                .collect(),
+            accel_range_g: 4,
+            gyro_range_dps: 500,
+            dac_vref: 3.3,
+        }
+    }
+}
+
+impl DeviceConfig {
+    /// Check every setting against what the device supports.
+    ///
+    /// Returns a description of each problem, empty if the config is valid.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        let rates = [
+            ("GPS", self.gps_rate_hz, GPS_RATE_LIMITS_HZ),
+            ("Accelerometer", self.accel_rate_hz, ACCEL_RATE_LIMITS_HZ),
+            ("Gyroscope", self.gyro_rate_hz, GYRO_RATE_LIMITS_HZ),
+            ("DAC", self.dac_rate_hz, DAC_RATE_LIMITS_HZ),
+        ];
+        for (group, rate, (min, max)) in rates {
+            if !(min..=max).contains(&rate) {
+                problems.push(format!("{} sample rate must be between {} and {} Hz", group, min, max));
+            }
+        }
+
+        for channel in &self.enabled_channels {
+            if !SENSOR_CHANNELS.iter().any(|(_, channels)| channels.contains(&channel.as_str())) {
+                problems.push(format!("Unknown channel {}", channel));
+            }
+        }
+        if self.enabled_channels.is_empty() {
+            problems.push("At least one channel must be enabled".to_string());
+        }
+
+        if !ACCEL_RANGES_G.contains(&self.accel_range_g) {
+            problems.push(format!("Accelerometer range must be one of {:?} g", ACCEL_RANGES_G));
+        }
+        if !GYRO_RANGES_DPS.contains(&self.gyro_range_dps) {
+            problems.push(format!("Gyroscope range must be one of {:?} °/s", GYRO_RANGES_DPS));
+        }
+
+        let (min, max) = DAC_VREF_LIMITS_V;
+        if !(min..=max).contains(&self.dac_vref) {
+            problems.push(format!("DAC reference must be between {} and {} V", min, max));
+        }
+
+        problems
+    }
+
+    /// Put the enabled channels in `SENSOR_CHANNELS` order and drop repeats,
+    ///     so configs enabling the same channels compare equal. Unknown
+    ///     channels go after the known ones in name order.
+    pub fn sort_channels(&mut self) -> () {
+        let position = |channel: &String| SENSOR_CHANNELS.iter()
+            .flat_map(|(_, channels)| channels.iter())
+            .position(|c| c == channel)
+            .unwrap_or(usize::MAX);
+
+        // Repeats are only adjacent once the order is total
+        self.enabled_channels.sort_by(|a, b| position(a).cmp(&position(b)).then_with(|| a.cmp(b)));
+        self.enabled_channels.dedup();
+    }
+}
+
+/// Largest radius a circular geofence may have, m
+pub const MAX_FENCE_RADIUS_M: f64 = 100_000.0;
+/// Most vertices a polygon geofence may have
+pub const MAX_FENCE_POINTS: usize = 64;
+
+/// Outline of a geofence, positions in degrees
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum FenceShape {
+    Circle { latitude: f64, longitude: f64, radius_m: f64 },
+    /// Vertices as (latitude, longitude), closed back to the first
+    Polygon { points: Vec<(f64, f64)> },
+}
+
+/// An area on the map that entering and leaving is reported for
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+pub struct Geofence {
+    /// Assigned by the server. Empty for a fence that has not been saved yet.
+    pub fence_id: String,
+    pub name: String,
+    pub shape: FenceShape,
+    /// Raise an alert when a live session enters or leaves the fence
+    pub alert: bool,
+}
+
+impl Geofence {
+    /// Check the name and outline.
+    ///
+    /// Returns a description of each problem, empty if the fence is valid.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if self.name.trim().is_empty() {
+            problems.push("Name cannot be empty".to_string());
+        }
+
+        let valid_position = |latitude: f64, longitude: f64| (-90.0..=90.0).contains(&latitude) && (-180.0..=180.0).contains(&longitude);
+        match &self.shape {
+            FenceShape::Circle { latitude, longitude, radius_m } => {
+                if !valid_position(*latitude, *longitude) {
+                    problems.push("Center is not a valid position".to_string());
+                }
+                if !(*radius_m > 0.0 && *radius_m <= MAX_FENCE_RADIUS_M) {
+                    problems.push(format!("Radius must be above 0 and at most {} m", MAX_FENCE_RADIUS_M));
+                }
+            }
+            FenceShape::Polygon { points } => {
+                if points.len() < 3 || points.len() > MAX_FENCE_POINTS {
+                    problems.push(format!("A polygon needs 3 to {} points", MAX_FENCE_POINTS));
+                }
+                if points.iter().any(|(latitude, longitude)| !valid_position(*latitude, *longitude)) {
+                    problems.push("Every point must be a valid position".to_string());
+                }
+            }
+        }
+
+        problems
+    }
+}
+
+/// What the user's device is doing with its samples
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordingState {
+    Idle,
+    Recording,
+    Paused,
+}
+
+/// Progress of the user's current recording
+#[derive(Deserialize, Clone)]
+pub struct RecordingStatus {
+    pub state: RecordingState,
+    /// Session being recorded into, None while idle
+    pub session_id: Option<i64>,
+    /// Seconds spent recording, pauses excluded
+    pub elapsed_secs: f64,
+    /// Datapoints in the session so far
+    pub sample_count: u64,
+}
+
+/// Whether a session can still receive datapoints
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionState {
+    /// The device is recording into it, paused or not
+    Recording,
+    /// Complete, its datapoints will not change
+    Finished,
+}
+
+impl Default for SessionState {
+    /// Sessions are only polled for new data when the server says they are recording
+    fn default() -> Self {
+        SessionState::Finished
+    }
+}
+
+/// A data recording session owned by a user
+#[allow(dead_code)]
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct Session {
+    pub session_id: i64,
+    pub username: String,
+    #[serde(default)]
+    pub state: SessionState,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Time of the first datapoint, None while the session is empty
+    #[serde(default)]
+    pub started: Option<String>,
+    /// Time of the last datapoint once the session is finished
+    #[serde(default)]
+    pub ended: Option<String>,
+    #[serde(default)]
+    pub sample_count: u64,
+    /// Unit that recorded the session, if known
+    #[serde(default)]
+    pub device_id: Option<String>,
+}
+
+impl Session {
+    /// Seconds from the first datapoint to the last, or to now while recording
+    pub fn duration_secs(&self) -> Option<f64> {
+        let started = time::parse_datetime(self.started.as_deref()?)?;
+        let ended = match (&self.ended, self.state) {
+            (Some(ended), _) => time::parse_datetime(ended)?,
+            (None, SessionState::Recording) => time::now_ms(),
+            (None, SessionState::Finished) => return None,
+        };
+
+        Some((ended - started).max(0.0) / 1000.0)
+    }
+
+    /// User facing metadata, for editing
+    pub fn metadata(&self) -> SessionMetadata {
+        SessionMetadata {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            tags: self.tags.clone(),
+        }
+    }
+}
+
+/// The parts of a session the user can edit
+#[derive(Clone, PartialEq, Default)]
+pub struct SessionMetadata {
+    pub name: String,
+    pub description: String,
+    pub tags: Vec<String>,
+}
+
+/// A change to the contents of finished sessions
+#[derive(Clone, PartialEq, Debug)]
+pub enum SessionOperation {
+    /// Remove a session along with all its datapoints
+    Delete { session_id: i64 },
+    /// Keep only the datapoints recorded from `start` to `end`, inclusive
+    Trim { session_id: i64, start: String, end: String },
+    /// Move the datapoints recorded at or after `at` into a new session
+    Split { session_id: i64, at: String },
+    /// Append `second` onto `first` and remove `second`. Both must come from
+    ///     the same device with no other session from it in between.
+    Merge { first: i64, second: i64 },
+}
+
+/// What a session operation changes, or would change
+#[derive(Deserialize, Clone, Debug)]
+pub struct OperationOutcome {
+    /// Datapoints removed, or moved from one session to another
+    pub affected: u64,
+    /// Datapoints left in the session operated on, or in `first` for a merge
+    pub remaining: u64,
+    /// Session a split moved its datapoints into, None for previews
+    pub created: Option<i64>,
+}
+
+/// A single recorded datapoint. `data_blob` holds the raw sensor values.
+#[allow(dead_code)]
+#[derive(Deserialize, Clone)]
+pub struct Datapoint {
+    pub datetime: String,
+    pub id: i64,
+    pub data_blob: Value,
+}
+
+/// Operations the UI needs from the data acquisition server
+pub trait Backend {
+    // Users
+
+    /// Register a new user account
+    fn create_user(&self, username: &str, password: &str) -> BackendFuture<()>;
+
+    /// Fetch the profile of the logged in user
+    fn account(&self, token: &AuthToken) -> BackendFuture<Account>;
+
+    /// Replace the logged in user's password. Fails with status 403 if `old_password` is wrong.
+    fn change_password(&self, token: &AuthToken, old_password: &str, new_password: &str) -> BackendFuture<()>;
+
+    /// Replace the logged in user's email. Fails with status 403 if `password` is wrong.
+    fn change_email(&self, token: &AuthToken, password: &str, email: &str) -> BackendFuture<()>;
+
+    /// Delete the logged in user along with all their sessions and data.
+    ///     Fails with status 403 if `password` is wrong.
+    fn delete_account(&self, token: &AuthToken, password: &str) -> BackendFuture<()>;
+
+    // Device keys
+
+    /// List the device keys of the logged in user, including rotated keys still in their grace period
+    fn device_keys(&self, token: &AuthToken) -> BackendFuture<Vec<DeviceKey>>;
+
+    /// Generate a new device key
+    fn create_device_key(&self, token: &AuthToken, name: &str) -> BackendFuture<NewDeviceKey>;
+
+    /// Revoke a device key immediately
+    fn revoke_device_key(&self, token: &AuthToken, key_id: &str) -> BackendFuture<()>;
+
+    /// Generate a replacement for a key. The old key keeps working for `grace_secs`
+    ///     so the device can be switched over without a gap in uploads.
+    fn rotate_device_key(&self, token: &AuthToken, key_id: &str, grace_secs: f64) -> BackendFuture<NewDeviceKey>;
+
+    // Device
+
+    /// Fetch the latest status report from the logged in user's device
+    fn device_status(&self, token: &AuthToken) -> BackendFuture<DeviceStatus>;
+
+    /// Read the acquisition settings from the logged in user's device
+    fn device_config(&self, token: &AuthToken) -> BackendFuture<DeviceConfig>;
+
+    /// Send new acquisition settings to the device. Fails with status 422 if
+    ///     `DeviceConfig::validate` finds a problem.
+    fn set_device_config(&self, token: &AuthToken, config: &DeviceConfig) -> BackendFuture<()>;
+
+    // Geofences
+
+    /// List the logged in user's geofences
+    fn geofences(&self, token: &AuthToken) -> BackendFuture<Vec<Geofence>>;
+
+    /// Create a geofence, or replace the one with the same `fence_id`. Fails with
+    ///     status 422 if `Geofence::validate` finds a problem.
+    ///
+    /// Returns the fence as stored, with its `fence_id` assigned.
+    fn save_geofence(&self, token: &AuthToken, fence: &Geofence) -> BackendFuture<Geofence>;
+
+    /// Remove a geofence
+    fn delete_geofence(&self, token: &AuthToken, fence_id: &str) -> BackendFuture<()>;
+
+    // Auth
+
+    /// Check a user's credentials and start an authenticated session.
+    ///
+    /// Every call below takes the returned token and fails with
+    ///     `BackendError::Unauthorized` once it has expired.
+    fn login(&self, username: &str, password: &str) -> BackendFuture<AuthToken>;
+
+    /// Check a token saved from an earlier login is still valid and return the username it belongs to
+    fn validate_token(&self, token: &AuthToken) -> BackendFuture<String>;
+
+    // Sessions
+
+    /// Create a new, empty session for a user. Nothing is recorded into it until `start_recording`.
+    fn create_session(&self, token: &AuthToken, username: &str) -> BackendFuture<()>;
+
+    /// List every session belonging to a user
+    fn sessions_by_user(&self, token: &AuthToken, username: &str) -> BackendFuture<Vec<Session>>;
+
+    /// Replace the name, description and tags of a session owned by the logged in user
+    fn update_session(&self, token: &AuthToken, session_id: &str, metadata: &SessionMetadata) -> BackendFuture<()>;
+
+    /// Work out what an operation would change, without changing anything
+    fn preview_operation(&self, token: &AuthToken, operation: &SessionOperation) -> BackendFuture<OperationOutcome>;
+
+    /// Run an operation on the logged in user's sessions. Fails with status 409 if
+    ///     a session involved is recording and 422 if the operation is not valid.
+    fn run_operation(&self, token: &AuthToken, operation: &SessionOperation) -> BackendFuture<OperationOutcome>;
+
+    // Recording
+
+    /// Fetch what the logged in user's device is recording
+    fn recording_status(&self, token: &AuthToken) -> BackendFuture<RecordingStatus>;
+
+    /// Start the device recording into a session, or into a new one if `session_id` is None.
+    ///     Fails with status 409 if it is already recording.
+    fn start_recording(&self, token: &AuthToken, session_id: Option<&str>) -> BackendFuture<RecordingStatus>;
+
+    /// Stop storing samples without ending the recording. Fails with status 409 unless recording.
+    fn pause_recording(&self, token: &AuthToken) -> BackendFuture<RecordingStatus>;
+
+    /// Continue a paused recording. Fails with status 409 unless paused.
+    fn resume_recording(&self, token: &AuthToken) -> BackendFuture<RecordingStatus>;
+
+    /// End the recording. Fails with status 409 if nothing is being recorded.
+    fn stop_recording(&self, token: &AuthToken) -> BackendFuture<RecordingStatus>;
+
+    // Datapoints
+
+    /// Fetch every datapoint recorded in a session
+    fn datapoints_by_session(&self, token: &AuthToken, session_id: &str) -> BackendFuture<Vec<Datapoint>>;
+
+    /// Fetch the datapoints in a session recorded after `datetime`
+    fn datapoints_since(&self, token: &AuthToken, session_id: &str, datetime: &str) -> BackendFuture<Vec<Datapoint>>;
+}
+
+/// Construct the backend selected by the enabled cargo features.
+///
+/// The tcp backend wins if both are enabled.
+pub fn connect() -> Rc<dyn Backend> {
+    #[cfg(feature = "tcp-backend")]
+    {
+        Rc::new(tcp::TcpBackend::new())
+    }
+
+    #[cfg(not(feature = "tcp-backend"))]
+    {
+        Rc::new(mock::MockBackend::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    fn default_device_config_is_valid() {
+        assert!(DeviceConfig::default().validate().is_empty());
+    }
+
+    #[wasm_bindgen_test]
+    fn device_config_limits_are_checked() {
+        let config = DeviceConfig {
+            gps_rate_hz: 20.0,
+            dac_rate_hz: 0.5,
+            enabled_channels: vec!["lat".to_string(), "humidity".to_string()],
+            accel_range_g: 3,
+            gyro_range_dps: 750,
+            dac_vref: 6.0,
+            ..Default::default()
+        };
+
+        assert_eq!(config.validate(), vec![
+            "GPS sample rate must be between 0.1 and 10 Hz".to_string(),
+            "DAC sample rate must be between 1 and 860 Hz".to_string(),
+            "Unknown channel humidity".to_string(),
+            "Accelerometer range must be one of [2, 4, 8, 16] g".to_string(),
+            "Gyroscope range must be one of [250, 500, 1000, 2000] °/s".to_string(),
+            "DAC reference must be between 1 and 5 V".to_string(),
+        ]);
+    }
+
+    #[wasm_bindgen_test]
+    fn device_config_needs_a_channel() {
+        let config = DeviceConfig { enabled_channels: Vec::new(), ..Default::default() };
+        assert_eq!(config.validate(), vec!["At least one channel must be enabled".to_string()]);
+    }
+
+    #[wasm_bindgen_test]
+    fn channel_order_does_not_matter() {
+        let mut sent = DeviceConfig {
+            enabled_channels: vec!["gyro_x".to_string(), "lat".to_string(), "dac_1".to_string()],
+            ..Default::default()
+        };
+        let mut applied = DeviceConfig {
+            enabled_channels: vec!["dac_1".to_string(), "gyro_x".to_string(), "lat".to_string(), "lat".to_string()],
+            ..Default::default()
+        };
+        sent.sort_channels();
+        applied.sort_channels();
+
+        assert_eq!(sent.enabled_channels, vec!["lat".to_string(), "gyro_x".to_string(), "dac_1".to_string()]);
+        assert_eq!(sent, applied);
+    }
+
+    #[wasm_bindgen_test]
+    fn unknown_channels_are_deduplicated() {
+        let mut config = DeviceConfig {
+            enabled_channels: ["humidity", "lat", "co2", "humidity", "co2"].map(String::from).to_vec(),
+            ..Default::default()
+        };
+        config.sort_channels();
+
+        assert_eq!(config.enabled_channels, ["lat", "co2", "humidity"].map(String::from).to_vec());
+    }
+
+    #[wasm_bindgen_test]
+    fn rate_limits_are_inclusive() {
+        let config = DeviceConfig {
+            gps_rate_hz: GPS_RATE_LIMITS_HZ.1,
+            accel_rate_hz: ACCEL_RATE_LIMITS_HZ.0,
+            gyro_rate_hz: GYRO_RATE_LIMITS_HZ.1,
+            dac_rate_hz: DAC_RATE_LIMITS_HZ.1,
+            ..Default::default()
+        };
+        assert!(config.validate().is_empty());
+    }
+
+    #[wasm_bindgen_test]
+    fn sessions_without_a_state_are_not_live() {
+        let session: Session = serde_json::from_str(r#"{"session_id": 1, "username": "demo"}"#).unwrap();
+        assert_eq!(session.state, SessionState::Finished);
+
+        let session: Session = serde_json::from_str(r#"{"session_id": 1, "username": "demo", "state": "recording"}"#).unwrap();
+        assert_eq!(session.state, SessionState::Recording);
+    }
+}
@@ -0,0 +1,1273 @@
+//! In-memory backend for running the UI without a server
+//!
+//! Every call is answered immediately from data held in the browser tab.
+//!     Only issued login tokens are kept across page reloads, in the browser's
+//!     localStorage, so remembered logins can be restored. Everything else
+//!     starts over from the demo data.
+//!
+//! Live sessions are fed by a simulated device. Samples are generated lazily:
+//!     whenever the backend is called it first catches every live session
+//!     up to the current time at the configured sample rate.
+//!
+
+mod sim;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::future::ready;
+
+use serde::{Deserialize, Serialize};
+
+use crate::time;
+
+use super::{
+    Account, AuthToken, Backend, BackendError, BackendFuture, ConnectionType, Datapoint, DeviceConfig, DeviceKey,
+    DeviceStatus, FenceShape, Geofence, NewDeviceKey, OperationOutcome, RecordingState, RecordingStatus, Session,
+    SessionMetadata, SessionOperation, SessionState,
+};
+use sim::{Rng, SimDevice, SimHardware};
+
+/// Account created on startup so there is something to look at right away
+const DEMO_USER: &str = "testuser";
+const DEMO_PASSWORD: &str = "testpassword";
+const DEMO_EMAIL: &str = "testuser@example.com";
+
+/// localStorage key issued tokens are kept under
+const TOKENS_KEY: &str = "mock_tokens";
+
+/// Id the simulated device reports for the sessions it records
+const SIM_DEVICE_ID: &str = "sim-0001";
+
+/// Length of the pre-recorded historical session in the demo account
+const DEMO_HISTORY_SECS: f64 = 600.0;
+
+/// Upper bound on how far a live session is caught up in one go, so a tab
+///     left in the background does not generate hours of samples at once
+const MAX_CATCH_UP_SECS: f64 = 300.0;
+
+/// Settings for the simulated server and device
+pub struct MockConfig {
+    /// Samples generated per second for each live session
+    pub sample_rate_hz: f64,
+    /// How long a login token stays valid
+    pub token_lifetime_secs: f64,
+}
+
+/// Read a positive number from a compile time environment variable
+fn env_f64(value: Option<&str>) -> Option<f64> {
+    value
+        .and_then(|value| value.parse::<f64>().ok())
+        .filter(|value| *value > 0.0)
+}
+
+impl Default for MockConfig {
+    /// Reads `MOCK_SAMPLE_RATE_HZ` and `MOCK_TOKEN_LIFETIME_SECS` at compile
+    ///     time, falling back to 10 Hz and 30 minutes
+    fn default() -> Self {
+        MockConfig {
+            sample_rate_hz: env_f64(option_env!("MOCK_SAMPLE_RATE_HZ")).unwrap_or(10.0),
+            token_lifetime_secs: env_f64(option_env!("MOCK_TOKEN_LIFETIME_SECS")).unwrap_or(1800.0),
+        }
+    }
+}
+
+/// A token handed out by `login`
+#[derive(Serialize, Deserialize)]
+struct IssuedToken {
+    username: String,
+    /// ms since the unix epoch
+    expires_ms: f64,
+}
+
+thread_local! {
+    /// Stands in for localStorage where there is none, e.g. when testing under Node
+    static MEMORY_TOKENS: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// The page's localStorage, if it has one
+fn local_storage() -> Option<web_sys::Storage> {
+    #[cfg(target_arch = "wasm32")]
+    {
+        web_sys::window().and_then(|window| window.local_storage().ok().flatten())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        None
+    }
+}
+
+/// Tokens kept by an earlier page load
+fn load_tokens() -> HashMap<String, IssuedToken> {
+    let saved = match local_storage() {
+        Some(storage) => storage.get_item(TOKENS_KEY).ok().flatten(),
+        None => MEMORY_TOKENS.with(|saved| saved.borrow().clone()),
+    };
+
+    saved.and_then(|json| serde_json::from_str(&json).ok()).unwrap_or_default()
+}
+
+/// Keep the issued tokens for the next page load
+fn save_tokens(tokens: &HashMap<String, IssuedToken>) -> () {
+    let json = match serde_json::to_string(tokens) {
+        Ok(json) => json,
+        Err(_) => return,
+    };
+
+    match local_storage() {
+        Some(storage) => {
+            let _ = storage.set_item(TOKENS_KEY, &json);
+        }
+        None => MEMORY_TOKENS.with(|saved| *saved.borrow_mut() = Some(json)),
+    }
+}
+
+/// A device key and the user it belongs to. Secrets are not kept.
+struct StoredKey {
+    owner: String,
+    key: DeviceKey,
+    /// ms since the unix epoch, mirrors `key.expires`
+    expires_ms: Option<f64>,
+}
+
+/// A geofence and the user it belongs to
+struct StoredFence {
+    owner: String,
+    fence: Geofence,
+}
+
+/// Addresses the simulated device reports
+const DEVICE_IPV4: &str = "10.42.0.17";
+const DEVICE_IPV6: &str = "fd00:42::17";
+
+/// A session currently being fed by a simulated device
+struct LiveSession {
+    device: SimDevice,
+    hardware: SimHardware,
+    /// Time of the most recent generated sample, ms since the unix epoch
+    last_sample_ms: f64,
+    /// When recording started, ms since the unix epoch
+    started_ms: f64,
+    /// Total time spent paused before the current pause, ms
+    paused_ms: f64,
+    /// When the current pause began, None while recording
+    paused_since: Option<f64>,
+}
+
+/// Everything the mock server knows about
+#[derive(Default)]
+struct MockState {
+    /// username -> password
+    users: HashMap<String, String>,
+    /// username -> email. Users without an entry have not set one.
+    emails: HashMap<String, String>,
+    sessions: Vec<Session>,
+    /// session id -> datapoints, oldest first
+    datapoints: HashMap<i64, Vec<Datapoint>>,
+    /// session id -> simulated device recording into it
+    live: HashMap<i64, LiveSession>,
+    /// token -> owner
+    tokens: HashMap<String, IssuedToken>,
+    device_keys: Vec<StoredKey>,
+    /// username -> settings of their device. Users without an entry have the defaults.
+    device_configs: HashMap<String, DeviceConfig>,
+    geofences: Vec<StoredFence>,
+    next_session_id: i64,
+    next_datapoint_id: i64,
+    next_key_id: i64,
+    next_fence_id: i64,
+}
+
+impl MockState {
+    /// Look up the user a token belongs to, dropping it if it has expired
+    fn authorize(&mut self, token: &AuthToken) -> Result<String, BackendError> {
+        let now = time::now_ms();
+
+        match self.tokens.get(token.as_str()) {
+            Some(issued) if issued.expires_ms > now => Ok(issued.username.clone()),
+            Some(_) => {
+                self.tokens.remove(token.as_str());
+                Err(BackendError::Unauthorized)
+            }
+            None => Err(BackendError::Unauthorized),
+        }
+    }
+
+    /// Look up the user a token belongs to and check their password
+    fn authorize_with_password(&mut self, token: &AuthToken, password: &str) -> Result<String, BackendError> {
+        let username = self.authorize(token)?;
+
+        match self.users.get(&username) {
+            Some(stored) if stored == password => Ok(username),
+            _ => Err(BackendError::Status(403)),
+        }
+    }
+
+    /// Drop rotated keys whose grace period has run out
+    fn expire_keys(&mut self, now_ms: f64) -> () {
+        self.device_keys.retain(|stored| stored.expires_ms.map_or(true, |expires| expires > now_ms));
+    }
+
+    /// Generate a key for a user and return it with its secret
+    fn add_device_key(&mut self, owner: &str, name: &str, now_ms: f64) -> NewDeviceKey {
+        let key_id = format!("dk-{}", self.next_key_id);
+        self.next_key_id += 1;
+
+        let mut rng = Rng::new(now_ms.to_bits() ^ (self.next_key_id as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+        let secret: String = (0..2).map(|_| format!("{:016x}", rng.next_u64())).collect();
+
+        let key = DeviceKey {
+            key_id,
+            name: name.to_string(),
+            created: time::format_datetime(now_ms),
+            last_used: None,
+            expires: None,
+        };
+        self.device_keys.push(StoredKey {
+            owner: owner.to_string(),
+            key: key.clone(),
+            expires_ms: None,
+        });
+
+        NewDeviceKey { key, secret }
+    }
+
+    /// Find a device key and check it belongs to `username`
+    fn owned_key(&self, username: &str, key_id: &str) -> Result<usize, BackendError> {
+        let index = self.device_keys.iter()
+            .position(|stored| stored.key.key_id == key_id)
+            .ok_or(BackendError::Status(404))?;
+
+        if self.device_keys[index].owner != username {
+            return Err(BackendError::Status(403));
+        }
+
+        Ok(index)
+    }
+
+    /// Store a new geofence under a fresh id
+    fn add_geofence(&mut self, owner: &str, mut fence: Geofence) -> Geofence {
+        fence.fence_id = format!("fence-{}", self.next_fence_id);
+        self.next_fence_id += 1;
+
+        self.geofences.push(StoredFence { owner: owner.to_string(), fence: fence.clone() });
+        fence
+    }
+
+    /// Find a session and check it belongs to `username`
+    fn owned_session(&self, username: &str, session_id: &str) -> Result<i64, BackendError> {
+        let session = session_id.parse::<i64>().ok()
+            .and_then(|id| self.sessions.iter().find(|s| s.session_id == id))
+            .ok_or(BackendError::Status(404))?;
+
+        if session.username != username {
+            return Err(BackendError::Status(403));
+        }
+
+        Ok(session.session_id)
+    }
+
+    /// Add an empty session for a user and return its id
+    fn add_session(&mut self, username: &str) -> i64 {
+        let session_id = self.next_session_id;
+        self.next_session_id += 1;
+
+        self.sessions.push(Session {
+            session_id,
+            username: username.to_string(),
+            state: SessionState::Finished,
+            ..Default::default()
+        });
+        self.datapoints.insert(session_id, Vec::new());
+
+        session_id
+    }
+
+    /// Run a simulated device from `start_ms` to `end_ms`, appending samples to a session
+    fn record(&mut self, session_id: i64, device: &mut SimDevice, start_ms: f64, end_ms: f64, sample_rate_hz: f64) -> f64 {
+        let interval_ms = 1000.0 / sample_rate_hz;
+        let mut sample_ms = start_ms;
+
+        while sample_ms + interval_ms <= end_ms {
+            sample_ms += interval_ms;
+
+            let datapoint = Datapoint {
+                datetime: time::format_datetime(sample_ms),
+                id: self.next_datapoint_id,
+                data_blob: device.step(interval_ms / 1000.0),
+            };
+            self.next_datapoint_id += 1;
+
+            self.datapoints.entry(session_id).or_default().push(datapoint);
+        }
+
+        sample_ms
+    }
+
+    /// Find a session of `username` that is not being recorded into
+    fn finished_session(&self, username: &str, session_id: i64) -> Result<i64, BackendError> {
+        let session_id = self.owned_session(username, &session_id.to_string())?;
+        if self.live.contains_key(&session_id) {
+            return Err(BackendError::Status(409));
+        }
+
+        Ok(session_id)
+    }
+
+    /// Time of the first and last datapoint in a session, ms since the unix epoch
+    fn session_span(&self, session_id: i64) -> Option<(f64, f64)> {
+        let datapoints = self.datapoints.get(&session_id)?;
+        let first = time::parse_datetime(&datapoints.first()?.datetime)?;
+        let last = time::parse_datetime(&datapoints.last()?.datetime)?;
+        Some((first, last))
+    }
+
+    /// Check and count an operation, and carry it out if `apply` is set
+    fn operate(&mut self, username: &str, operation: &SessionOperation, apply: bool) -> Result<OperationOutcome, BackendError> {
+        let recorded_at = |d: &Datapoint| time::parse_datetime(&d.datetime).unwrap_or(0.0);
+
+        match operation {
+            SessionOperation::Delete { session_id } => {
+                let session_id = self.finished_session(username, *session_id)?;
+                let affected = self.datapoints.get(&session_id).map_or(0, Vec::len) as u64;
+
+                if apply {
+                    self.datapoints.remove(&session_id);
+                    self.sessions.retain(|s| s.session_id != session_id);
+                }
+                Ok(OperationOutcome { affected, remaining: 0, created: None })
+            }
+            SessionOperation::Trim { session_id, start, end } => {
+                let session_id = self.finished_session(username, *session_id)?;
+                let (start, end) = match (time::parse_datetime(start), time::parse_datetime(end)) {
+                    (Some(start), Some(end)) if start <= end => (start, end),
+                    _ => return Err(BackendError::Status(422)),
+                };
+
+                let keep = |d: &Datapoint| (start..=end).contains(&recorded_at(d));
+                let datapoints = self.datapoints.entry(session_id).or_default();
+                let remaining = datapoints.iter().filter(|d| keep(d)).count() as u64;
+                let affected = datapoints.len() as u64 - remaining;
+
+                if apply {
+                    datapoints.retain(keep);
+                }
+                Ok(OperationOutcome { affected, remaining, created: None })
+            }
+            SessionOperation::Split { session_id, at } => {
+                let session_id = self.finished_session(username, *session_id)?;
+                let at = time::parse_datetime(at).ok_or(BackendError::Status(422))?;
+
+                let datapoints = self.datapoints.get(&session_id).map(Vec::as_slice).unwrap_or_default();
+                let affected = datapoints.iter().filter(|d| recorded_at(d) >= at).count() as u64;
+                let remaining = datapoints.len() as u64 - affected;
+                // Both halves must keep something
+                if affected == 0 || remaining == 0 {
+                    return Err(BackendError::Status(422));
+                }
+
+                let created = if apply {
+                    let original = self.sessions.iter().find(|s| s.session_id == session_id).cloned().unwrap_or_default();
+                    let new_id = self.add_session(username);
+                    self.describe_session(new_id, SessionMetadata {
+                        name: if original.name.is_empty() { String::new() } else { format!("{} (part 2)", original.name) },
+                        ..original.metadata()
+                    });
+                    if let Some(session) = self.sessions.iter_mut().find(|s| s.session_id == new_id) {
+                        session.device_id = original.device_id;
+                    }
+
+                    let datapoints = self.datapoints.remove(&session_id).unwrap_or_default();
+                    let (moved, kept) = datapoints.into_iter().partition(|d| recorded_at(d) >= at);
+                    self.datapoints.insert(session_id, kept);
+                    self.datapoints.insert(new_id, moved);
+                    Some(new_id)
+                } else {
+                    None
+                };
+                Ok(OperationOutcome { affected, remaining, created })
+            }
+            SessionOperation::Merge { first, second } => {
+                let first = self.finished_session(username, *first)?;
+                let second = self.finished_session(username, *second)?;
+                let device_of = |session_id: i64| self.sessions.iter()
+                    .find(|s| s.session_id == session_id)
+                    .and_then(|s| s.device_id.clone());
+                // Sessions from an unknown device cannot be shown to be from the same one
+                let device = match (device_of(first), device_of(second)) {
+                    (Some(a), Some(b)) if first != second && a == b => Some(a),
+                    _ => return Err(BackendError::Status(422)),
+                };
+
+                // Empty sessions fit anywhere. Otherwise `second` must follow `first`
+                //     with nothing else from the device starting in between.
+                if let (Some((_, first_end)), Some((second_start, _))) = (self.session_span(first), self.session_span(second)) {
+                    let between = self.sessions.iter()
+                        .filter(|s| s.username == username && s.session_id != first && s.session_id != second)
+                        .filter(|s| s.device_id == device)
+                        .filter_map(|s| self.session_span(s.session_id))
+                        .any(|(start, _)| start >= first_end && start <= second_start);
+                    if first_end > second_start || between {
+                        return Err(BackendError::Status(422));
+                    }
+                }
+
+                let affected = self.datapoints.get(&second).map_or(0, Vec::len) as u64;
+                let remaining = self.datapoints.get(&first).map_or(0, Vec::len) as u64 + affected;
+
+                if apply {
+                    let moved = self.datapoints.remove(&second).unwrap_or_default();
+                    self.datapoints.entry(first).or_default().extend(moved);
+                    self.sessions.retain(|s| s.session_id != second);
+                }
+                Ok(OperationOutcome { affected, remaining, created: None })
+            }
+        }
+    }
+
+    /// Set the metadata of a session
+    fn describe_session(&mut self, session_id: i64, metadata: SessionMetadata) {
+        if let Some(session) = self.sessions.iter_mut().find(|s| s.session_id == session_id) {
+            session.name = metadata.name;
+            session.description = metadata.description;
+            session.tags = metadata.tags;
+        }
+    }
+
+    /// Begin feeding a session from a new simulated device
+    fn start_live(&mut self, session_id: i64, now_ms: f64) {
+        if let Some(session) = self.sessions.iter_mut().find(|s| s.session_id == session_id) {
+            session.device_id = Some(SIM_DEVICE_ID.to_string());
+        }
+
+        let seed = (now_ms as u64) ^ (session_id as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+
+        self.live.insert(session_id, LiveSession {
+            device: SimDevice::new(seed),
+            hardware: SimHardware::new(seed),
+            last_sample_ms: now_ms,
+            started_ms: now_ms,
+            paused_ms: 0.0,
+            paused_since: None,
+        });
+    }
+
+    /// The session a user's device is recording into, paused or not
+    fn live_session_of(&self, username: &str) -> Option<i64> {
+        self.sessions.iter()
+            .filter(|s| s.username == username)
+            .map(|s| s.session_id)
+            .find(|session_id| self.live.contains_key(session_id))
+    }
+
+    /// Describe what a user's device is recording
+    fn recording_status(&self, username: &str, now_ms: f64) -> RecordingStatus {
+        let session_id = match self.live_session_of(username) {
+            Some(session_id) => session_id,
+            None => return RecordingStatus {
+                state: RecordingState::Idle,
+                session_id: None,
+                elapsed_secs: 0.0,
+                sample_count: 0,
+            },
+        };
+        let live = &self.live[&session_id];
+
+        let current_pause = live.paused_since.map_or(0.0, |since| now_ms - since);
+        RecordingStatus {
+            state: if live.paused_since.is_some() { RecordingState::Paused } else { RecordingState::Recording },
+            session_id: Some(session_id),
+            elapsed_secs: ((now_ms - live.started_ms - live.paused_ms - current_pause) / 1000.0).max(0.0),
+            sample_count: self.datapoints.get(&session_id).map_or(0, |d| d.len() as u64),
+        }
+    }
+}
+
+/// Backend answering every call from memory
+pub struct MockBackend {
+    config: MockConfig,
+    state: RefCell<MockState>,
+}
+
+impl MockBackend {
+    pub fn new() -> Self {
+        MockBackend::with_config(MockConfig::default())
+    }
+
+    /// Create a mock backend seeded with the demo account: one live session
+    ///     and one finished historical session
+    pub fn with_config(config: MockConfig) -> Self {
+        let now = time::now_ms();
+        let mut state = MockState {
+            next_session_id: 1,
+            next_datapoint_id: 1,
+            next_key_id: 1,
+            next_fence_id: 1,
+            ..Default::default()
+        };
+
+        state.users.insert(DEMO_USER.to_string(), DEMO_PASSWORD.to_string());
+        state.emails.insert(DEMO_USER.to_string(), DEMO_EMAIL.to_string());
+
+        state.add_device_key(DEMO_USER, "Demo unit", now - (DEMO_HISTORY_SECS + 7200.0) * 1000.0);
+
+        // Around where the simulated tracks start
+        state.add_geofence(DEMO_USER, Geofence {
+            fence_id: String::new(),
+            name: "Kelley Engineering Center".to_string(),
+            shape: FenceShape::Circle { latitude: 44.5672, longitude: -123.2786, radius_m: 40.0 },
+            alert: false,
+        });
+
+        let live_id = state.add_session(DEMO_USER);
+        state.start_live(live_id, now);
+        state.describe_session(live_id, SessionMetadata {
+            name: "Live demo".to_string(),
+            description: "Fed by the simulated device while the app is open".to_string(),
+            tags: vec!["demo".to_string()],
+        });
+
+        let history_id = state.add_session(DEMO_USER);
+        state.describe_session(history_id, SessionMetadata {
+            name: "Campus loop".to_string(),
+            description: "Pre-recorded 10 minute walk".to_string(),
+            tags: vec!["demo".to_string(), "walk".to_string()],
+        });
+        if let Some(session) = state.sessions.iter_mut().find(|s| s.session_id == history_id) {
+            session.device_id = Some(SIM_DEVICE_ID.to_string());
+        }
+        let mut device = SimDevice::new(history_id as u64);
+        let history_start = now - (DEMO_HISTORY_SECS + 3600.0) * 1000.0;
+        state.record(history_id, &mut device, history_start, history_start + DEMO_HISTORY_SECS * 1000.0, config.sample_rate_hz);
+
+        // Tokens of users that no longer exist, e.g. accounts created before the reload, are dropped
+        state.tokens = load_tokens();
+        state.tokens.retain(|_, issued| issued.expires_ms > now && state.users.contains_key(&issued.username));
+
+        MockBackend {
+            config,
+            state: RefCell::new(state),
+        }
+    }
+
+    /// Generate every sample live sessions should have recorded by now
+    fn catch_up(&self) {
+        let now = time::now_ms();
+        let mut state = self.state.borrow_mut();
+
+        let mut live = std::mem::take(&mut state.live);
+        for (session_id, session) in live.iter_mut() {
+            // The device keeps running while paused, nothing is stored
+            if session.paused_since.is_some() {
+                session.hardware.step((now - session.last_sample_ms).min(MAX_CATCH_UP_SECS * 1000.0) / 1000.0);
+                session.last_sample_ms = now;
+                continue;
+            }
+
+            let start = session.last_sample_ms.max(now - MAX_CATCH_UP_SECS * 1000.0);
+            let end = state.record(*session_id, &mut session.device, start, now, self.config.sample_rate_hz);
+            session.hardware.step((end - start) / 1000.0);
+            session.last_sample_ms = end;
+
+            // Devices upload with the oldest key their owner has that is not being rotated out
+            let owner = state.sessions.iter()
+                .find(|s| s.session_id == *session_id)
+                .map(|s| s.username.clone());
+            if let Some(stored) = state.device_keys.iter_mut()
+                .find(|stored| Some(&stored.owner) == owner.as_ref() && stored.expires_ms.is_none())
+            {
+                stored.key.last_used = Some(time::format_datetime(session.last_sample_ms));
+            }
+        }
+        state.live = live;
+    }
+}
+
+/// Wrap an already computed result in a backend future
+fn respond<T: 'static>(result: Result<T, BackendError>) -> BackendFuture<T> {
+    Box::pin(ready(result))
+}
+
+impl Backend for MockBackend {
+    fn create_user(&self, username: &str, password: &str) -> BackendFuture<()> {
+        let mut state = self.state.borrow_mut();
+
+        if state.users.contains_key(username) {
+            return respond(Err(BackendError::Status(409)));
+        }
+
+        state.users.insert(username.to_string(), password.to_string());
+        respond(Ok(()))
+    }
+
+    fn account(&self, token: &AuthToken) -> BackendFuture<Account> {
+        let mut state = self.state.borrow_mut();
+
+        let username = match state.authorize(token) {
+            Ok(username) => username,
+            Err(e) => return respond(Err(e)),
+        };
+
+        let email = state.emails.get(&username).cloned().unwrap_or_default();
+        respond(Ok(Account { username, email }))
+    }
+
+    fn change_password(&self, token: &AuthToken, old_password: &str, new_password: &str) -> BackendFuture<()> {
+        let mut state = self.state.borrow_mut();
+
+        let username = match state.authorize_with_password(token, old_password) {
+            Ok(username) => username,
+            Err(e) => return respond(Err(e)),
+        };
+
+        state.users.insert(username, new_password.to_string());
+        respond(Ok(()))
+    }
+
+    fn change_email(&self, token: &AuthToken, password: &str, email: &str) -> BackendFuture<()> {
+        let mut state = self.state.borrow_mut();
+
+        let username = match state.authorize_with_password(token, password) {
+            Ok(username) => username,
+            Err(e) => return respond(Err(e)),
+        };
+
+        state.emails.insert(username, email.to_string());
+        respond(Ok(()))
+    }
+
+    /// Removes the user's sessions, datapoints and every token issued to them
+    fn delete_account(&self, token: &AuthToken, password: &str) -> BackendFuture<()> {
+        let mut state = self.state.borrow_mut();
+
+        let username = match state.authorize_with_password(token, password) {
+            Ok(username) => username,
+            Err(e) => return respond(Err(e)),
+        };
+
+        let owned: Vec<i64> = state.sessions.iter()
+            .filter(|s| s.username == username)
+            .map(|s| s.session_id)
+            .collect();
+        for session_id in &owned {
+            state.datapoints.remove(session_id);
+            state.live.remove(session_id);
+        }
+        state.sessions.retain(|s| s.username != username);
+        state.tokens.retain(|_, issued| issued.username != username);
+        save_tokens(&state.tokens);
+        state.device_keys.retain(|stored| stored.owner != username);
+        state.device_configs.remove(&username);
+        state.geofences.retain(|stored| stored.owner != username);
+        state.emails.remove(&username);
+        state.users.remove(&username);
+
+        respond(Ok(()))
+    }
+
+    fn device_keys(&self, token: &AuthToken) -> BackendFuture<Vec<DeviceKey>> {
+        self.catch_up();
+        let mut state = self.state.borrow_mut();
+
+        let username = match state.authorize(token) {
+            Ok(username) => username,
+            Err(e) => return respond(Err(e)),
+        };
+
+        state.expire_keys(time::now_ms());
+        let keys = state.device_keys.iter()
+            .filter(|stored| stored.owner == username)
+            .map(|stored| stored.key.clone())
+            .collect();
+
+        respond(Ok(keys))
+    }
+
+    fn create_device_key(&self, token: &AuthToken, name: &str) -> BackendFuture<NewDeviceKey> {
+        let mut state = self.state.borrow_mut();
+
+        let username = match state.authorize(token) {
+            Ok(username) => username,
+            Err(e) => return respond(Err(e)),
+        };
+
+        respond(Ok(state.add_device_key(&username, name, time::now_ms())))
+    }
+
+    fn revoke_device_key(&self, token: &AuthToken, key_id: &str) -> BackendFuture<()> {
+        let mut state = self.state.borrow_mut();
+
+        let index = match state.authorize(token).and_then(|owner| state.owned_key(&owner, key_id)) {
+            Ok(index) => index,
+            Err(e) => return respond(Err(e)),
+        };
+
+        state.device_keys.remove(index);
+        respond(Ok(()))
+    }
+
+    /// The replacement keeps the old key's name
+    fn rotate_device_key(&self, token: &AuthToken, key_id: &str, grace_secs: f64) -> BackendFuture<NewDeviceKey> {
+        let mut state = self.state.borrow_mut();
+
+        let (username, index) = match state.authorize(token)
+            .and_then(|owner| state.owned_key(&owner, key_id).map(|index| (owner, index)))
+        {
+            Ok(found) => found,
+            Err(e) => return respond(Err(e)),
+        };
+
+        // A key already being rotated out has a replacement
+        if state.device_keys[index].expires_ms.is_some() {
+            return respond(Err(BackendError::Status(409)));
+        }
+
+        let now = time::now_ms();
+        let expires_ms = now + grace_secs.max(0.0) * 1000.0;
+        let old = &mut state.device_keys[index];
+        old.expires_ms = Some(expires_ms);
+        old.key.expires = Some(time::format_datetime(expires_ms));
+        let name = old.key.name.clone();
+
+        respond(Ok(state.add_device_key(&username, &name, now)))
+    }
+
+    /// The user's device is online while it records into a live session.
+    ///     Otherwise it is reported offline, last seen at its latest datapoint.
+    fn device_status(&self, token: &AuthToken) -> BackendFuture<DeviceStatus> {
+        self.catch_up();
+        let mut state = self.state.borrow_mut();
+
+        let username = match state.authorize(token) {
+            Ok(username) => username,
+            Err(e) => return respond(Err(e)),
+        };
+
+        let owned: Vec<i64> = state.sessions.iter()
+            .filter(|s| s.username == username)
+            .map(|s| s.session_id)
+            .collect();
+
+        let live = owned.iter().find_map(|session_id| state.live.get(session_id));
+        let status = match live {
+            Some(live) => DeviceStatus {
+                last_seen: Some(time::format_datetime(live.last_sample_ms)),
+                connection: Some(ConnectionType::Proxy),
+                ipv4: Some(DEVICE_IPV4.to_string()),
+                ipv6: Some(DEVICE_IPV6.to_string()),
+                cellular_dbm: Some(live.hardware.cellular_dbm),
+                cpu_temp_c: Some(live.hardware.cpu_temp_c),
+                cpu_load_pct: Some(live.hardware.cpu_load_pct),
+                battery_temp_c: Some(live.hardware.battery_temp_c),
+                battery_pct: Some(live.hardware.battery_pct),
+            },
+            None => DeviceStatus {
+                last_seen: owned.iter()
+                    .filter_map(|session_id| state.datapoints.get(session_id).and_then(|d| d.last()))
+                    .map(|d| d.datetime.clone())
+                    .max(),
+                connection: None,
+                ipv4: None,
+                ipv6: None,
+                cellular_dbm: None,
+                cpu_temp_c: None,
+                cpu_load_pct: None,
+                battery_temp_c: None,
+                battery_pct: None,
+            },
+        };
+
+        respond(Ok(status))
+    }
+
+    fn device_config(&self, token: &AuthToken) -> BackendFuture<DeviceConfig> {
+        let mut state = self.state.borrow_mut();
+
+        let username = match state.authorize(token) {
+            Ok(username) => username,
+            Err(e) => return respond(Err(e)),
+        };
+
+        respond(Ok(state.device_configs.get(&username).cloned().unwrap_or_default()))
+    }
+
+    /// Stored as sent. The simulated device keeps sampling at the configured mock rate.
+    fn set_device_config(&self, token: &AuthToken, config: &DeviceConfig) -> BackendFuture<()> {
+        let mut state = self.state.borrow_mut();
+
+        let username = match state.authorize(token) {
+            Ok(username) => username,
+            Err(e) => return respond(Err(e)),
+        };
+        if !config.validate().is_empty() {
+            return respond(Err(BackendError::Status(422)));
+        }
+
+        state.device_configs.insert(username, config.clone());
+        respond(Ok(()))
+    }
+
+    fn geofences(&self, token: &AuthToken) -> BackendFuture<Vec<Geofence>> {
+        let mut state = self.state.borrow_mut();
+
+        let username = match state.authorize(token) {
+            Ok(username) => username,
+            Err(e) => return respond(Err(e)),
+        };
+
+        let fences = state.geofences.iter()
+            .filter(|stored| stored.owner == username)
+            .map(|stored| stored.fence.clone())
+            .collect();
+
+        respond(Ok(fences))
+    }
+
+    fn save_geofence(&self, token: &AuthToken, fence: &Geofence) -> BackendFuture<Geofence> {
+        let mut state = self.state.borrow_mut();
+
+        let username = match state.authorize(token) {
+            Ok(username) => username,
+            Err(e) => return respond(Err(e)),
+        };
+        if !fence.validate().is_empty() {
+            return respond(Err(BackendError::Status(422)));
+        }
+
+        if fence.fence_id.is_empty() {
+            return respond(Ok(state.add_geofence(&username, fence.clone())));
+        }
+
+        match state.geofences.iter_mut().find(|stored| stored.fence.fence_id == fence.fence_id) {
+            Some(stored) if stored.owner == username => {
+                stored.fence = fence.clone();
+                respond(Ok(fence.clone()))
+            }
+            Some(_) => respond(Err(BackendError::Status(403))),
+            None => respond(Err(BackendError::Status(404))),
+        }
+    }
+
+    fn delete_geofence(&self, token: &AuthToken, fence_id: &str) -> BackendFuture<()> {
+        let mut state = self.state.borrow_mut();
+
+        let username = match state.authorize(token) {
+            Ok(username) => username,
+            Err(e) => return respond(Err(e)),
+        };
+
+        match state.geofences.iter().position(|stored| stored.fence.fence_id == fence_id) {
+            Some(index) if state.geofences[index].owner == username => {
+                state.geofences.remove(index);
+                respond(Ok(()))
+            }
+            Some(_) => respond(Err(BackendError::Status(403))),
+            None => respond(Err(BackendError::Status(404))),
+        }
+    }
+
+    fn login(&self, username: &str, password: &str) -> BackendFuture<AuthToken> {
+        let mut state = self.state.borrow_mut();
+
+        match state.users.get(username) {
+            Some(stored) if stored == password => {}
+            _ => return respond(Err(BackendError::Status(401))),
+        }
+
+        let now = time::now_ms();
+        let mut rng = Rng::new(now.to_bits() ^ state.tokens.len() as u64);
+        let token: String = (0..4).map(|_| format!("{:016x}", rng.next_u64())).collect();
+
+        state.tokens.retain(|_, issued| issued.expires_ms > now);
+        state.tokens.insert(token.clone(), IssuedToken {
+            username: username.to_string(),
+            expires_ms: now + self.config.token_lifetime_secs * 1000.0,
+        });
+        save_tokens(&state.tokens);
+
+        respond(Ok(AuthToken::new(token)))
+    }
+
+    fn validate_token(&self, token: &AuthToken) -> BackendFuture<String> {
+        respond(self.state.borrow_mut().authorize(token))
+    }
+
+    fn create_session(&self, token: &AuthToken, username: &str) -> BackendFuture<()> {
+        let mut state = self.state.borrow_mut();
+
+        match state.authorize(token) {
+            Ok(owner) if owner == username => {}
+            Ok(_) => return respond(Err(BackendError::Status(403))),
+            Err(e) => return respond(Err(e)),
+        }
+
+        state.add_session(username);
+        respond(Ok(()))
+    }
+
+    fn sessions_by_user(&self, token: &AuthToken, username: &str) -> BackendFuture<Vec<Session>> {
+        self.catch_up();
+        let mut state = self.state.borrow_mut();
+
+        match state.authorize(token) {
+            Ok(owner) if owner == username => {}
+            Ok(_) => return respond(Err(BackendError::Status(403))),
+            Err(e) => return respond(Err(e)),
+        }
+
+        // A session is recording for as long as a simulated device feeds it.
+        //     Times and counts come from its datapoints.
+        let sessions = state.sessions.iter()
+            .filter(|s| s.username == username)
+            .map(|s| {
+                let datapoints = state.datapoints.get(&s.session_id).map(Vec::as_slice).unwrap_or_default();
+                let live = state.live.contains_key(&s.session_id);
+
+                Session {
+                    state: if live { SessionState::Recording } else { SessionState::Finished },
+                    started: datapoints.first().map(|d| d.datetime.clone()),
+                    ended: if live { None } else { datapoints.last().map(|d| d.datetime.clone()) },
+                    sample_count: datapoints.len() as u64,
+                    ..s.clone()
+                }
+            })
+            .collect();
+
+        respond(Ok(sessions))
+    }
+
+    fn update_session(&self, token: &AuthToken, session_id: &str, metadata: &SessionMetadata) -> BackendFuture<()> {
+        let mut state = self.state.borrow_mut();
+
+        let session_id = match state.authorize(token).and_then(|username| state.owned_session(&username, session_id)) {
+            Ok(session_id) => session_id,
+            Err(e) => return respond(Err(e)),
+        };
+
+        state.describe_session(session_id, metadata.clone());
+        respond(Ok(()))
+    }
+
+    fn preview_operation(&self, token: &AuthToken, operation: &SessionOperation) -> BackendFuture<OperationOutcome> {
+        self.catch_up();
+        let mut state = self.state.borrow_mut();
+
+        respond(state.authorize(token).and_then(|username| state.operate(&username, operation, false)))
+    }
+
+    fn run_operation(&self, token: &AuthToken, operation: &SessionOperation) -> BackendFuture<OperationOutcome> {
+        self.catch_up();
+        let mut state = self.state.borrow_mut();
+
+        respond(state.authorize(token).and_then(|username| state.operate(&username, operation, true)))
+    }
+
+    fn recording_status(&self, token: &AuthToken) -> BackendFuture<RecordingStatus> {
+        self.catch_up();
+        let mut state = self.state.borrow_mut();
+
+        let username = match state.authorize(token) {
+            Ok(username) => username,
+            Err(e) => return respond(Err(e)),
+        };
+
+        respond(Ok(state.recording_status(&username, time::now_ms())))
+    }
+
+    fn start_recording(&self, token: &AuthToken, session_id: Option<&str>) -> BackendFuture<RecordingStatus> {
+        self.catch_up();
+        let mut state = self.state.borrow_mut();
+
+        let username = match state.authorize(token) {
+            Ok(username) => username,
+            Err(e) => return respond(Err(e)),
+        };
+        if state.live_session_of(&username).is_some() {
+            return respond(Err(BackendError::Status(409)));
+        }
+
+        let session_id = match session_id {
+            Some(session_id) => match state.owned_session(&username, session_id) {
+                Ok(session_id) => session_id,
+                Err(e) => return respond(Err(e)),
+            },
+            None => state.add_session(&username),
+        };
+
+        let now = time::now_ms();
+        state.start_live(session_id, now);
+        respond(Ok(state.recording_status(&username, now)))
+    }
+
+    fn pause_recording(&self, token: &AuthToken) -> BackendFuture<RecordingStatus> {
+        self.catch_up();
+        let mut state = self.state.borrow_mut();
+
+        let username = match state.authorize(token) {
+            Ok(username) => username,
+            Err(e) => return respond(Err(e)),
+        };
+
+        let now = time::now_ms();
+        match state.live_session_of(&username).and_then(|session_id| state.live.get_mut(&session_id)) {
+            Some(live) if live.paused_since.is_none() => live.paused_since = Some(now),
+            _ => return respond(Err(BackendError::Status(409))),
+        }
+
+        respond(Ok(state.recording_status(&username, now)))
+    }
+
+    fn resume_recording(&self, token: &AuthToken) -> BackendFuture<RecordingStatus> {
+        self.catch_up();
+        let mut state = self.state.borrow_mut();
+
+        let username = match state.authorize(token) {
+            Ok(username) => username,
+            Err(e) => return respond(Err(e)),
+        };
+
+        let now = time::now_ms();
+        match state.live_session_of(&username).and_then(|session_id| state.live.get_mut(&session_id)) {
+            Some(live) => match live.paused_since.take() {
+                Some(since) => {
+                    live.paused_ms += now - since;
+                    live.last_sample_ms = now;
+                }
+                None => return respond(Err(BackendError::Status(409))),
+            },
+            None => return respond(Err(BackendError::Status(409))),
+        }
+
+        respond(Ok(state.recording_status(&username, now)))
+    }
+
+    fn stop_recording(&self, token: &AuthToken) -> BackendFuture<RecordingStatus> {
+        self.catch_up();
+        let mut state = self.state.borrow_mut();
+
+        let username = match state.authorize(token) {
+            Ok(username) => username,
+            Err(e) => return respond(Err(e)),
+        };
+
+        match state.live_session_of(&username) {
+            Some(session_id) => state.live.remove(&session_id),
+            None => return respond(Err(BackendError::Status(409))),
+        };
+
+        respond(Ok(state.recording_status(&username, time::now_ms())))
+    }
+
+    fn datapoints_by_session(&self, token: &AuthToken, session_id: &str) -> BackendFuture<Vec<Datapoint>> {
+        self.catch_up();
+        let mut state = self.state.borrow_mut();
+
+        let session_id = match state.authorize(token).and_then(|owner| state.owned_session(&owner, session_id)) {
+            Ok(session_id) => session_id,
+            Err(e) => return respond(Err(e)),
+        };
+
+        respond(Ok(state.datapoints.get(&session_id).cloned().unwrap_or_default()))
+    }
+
+    fn datapoints_since(&self, token: &AuthToken, session_id: &str, datetime: &str) -> BackendFuture<Vec<Datapoint>> {
+        self.catch_up();
+        let mut state = self.state.borrow_mut();
+
+        let session_id = match state.authorize(token).and_then(|owner| state.owned_session(&owner, session_id)) {
+            Ok(session_id) => session_id,
+            Err(e) => return respond(Err(e)),
+        };
+
+        // Timestamps share one ISO 8601 format, so string order is time order
+        let datapoints = state.datapoints.get(&session_id)
+            .map(|datapoints| datapoints.iter()
+                .filter(|d| d.datetime.as_str() > datetime)
+                .cloned()
+                .collect())
+            .unwrap_or_default();
+
+        respond(Ok(datapoints))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+    use crate::display::testing::DAY_MS;
+
+    const USER: &str = "alice";
+
+    /// An empty state with the ids the backend starts from
+    fn state() -> MockState {
+        MockState {
+            next_session_id: 1,
+            next_datapoint_id: 1,
+            next_key_id: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Add a finished session from `device` holding one datapoint every 100 ms
+    fn recorded(state: &mut MockState, device: &str, start_ms: f64, count: usize) -> i64 {
+        let session_id = state.add_session(USER);
+        if let Some(session) = state.sessions.iter_mut().find(|s| s.session_id == session_id) {
+            session.device_id = Some(device.to_string());
+        }
+
+        for i in 0..count {
+            let datapoint = Datapoint {
+                datetime: time::format_datetime(start_ms + i as f64 * 100.0),
+                id: state.next_datapoint_id,
+                data_blob: Value::Null,
+            };
+            state.next_datapoint_id += 1;
+            state.datapoints.entry(session_id).or_default().push(datapoint);
+        }
+
+        session_id
+    }
+
+    fn times(state: &MockState, session_id: i64) -> Vec<String> {
+        state.datapoints[&session_id].iter().map(|d| d.datetime.clone()).collect()
+    }
+
+    fn status(result: Result<OperationOutcome, BackendError>) -> Option<u16> {
+        match result {
+            Err(BackendError::Status(status)) => Some(status),
+            _ => None,
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn trim_keeps_the_inclusive_range() {
+        let mut state = state();
+        let session_id = recorded(&mut state, "dev", DAY_MS, 10);
+        let all = times(&state, session_id);
+
+        let trim = SessionOperation::Trim { session_id, start: all[2].clone(), end: all[6].clone() };
+        let preview = state.operate(USER, &trim, false).unwrap();
+        assert_eq!((preview.affected, preview.remaining), (5, 5));
+        assert_eq!(times(&state, session_id), all, "previews must not change anything");
+
+        let outcome = state.operate(USER, &trim, true).unwrap();
+        assert_eq!((outcome.affected, outcome.remaining), (5, 5));
+        assert_eq!(times(&state, session_id), all[2..=6].to_vec());
+    }
+
+    #[wasm_bindgen_test]
+    fn trim_rejects_a_reversed_range() {
+        let mut state = state();
+        let session_id = recorded(&mut state, "dev", DAY_MS, 10);
+        let all = times(&state, session_id);
+
+        let trim = SessionOperation::Trim { session_id, start: all[6].clone(), end: all[2].clone() };
+        assert_eq!(status(state.operate(USER, &trim, true)), Some(422));
+        assert_eq!(times(&state, session_id), all);
+    }
+
+    #[wasm_bindgen_test]
+    fn live_sessions_cannot_be_changed() {
+        let mut state = state();
+        let session_id = recorded(&mut state, "dev", DAY_MS, 10);
+        state.start_live(session_id, DAY_MS + 1000.0);
+
+        let delete = SessionOperation::Delete { session_id };
+        assert_eq!(status(state.operate(USER, &delete, true)), Some(409));
+    }
+
+    #[wasm_bindgen_test]
+    fn other_users_sessions_cannot_be_changed() {
+        let mut state = state();
+        let session_id = recorded(&mut state, "dev", DAY_MS, 10);
+
+        let delete = SessionOperation::Delete { session_id };
+        assert_eq!(status(state.operate("mallory", &delete, true)), Some(403));
+        assert_eq!(state.datapoints[&session_id].len(), 10);
+    }
+
+    #[wasm_bindgen_test]
+    fn split_moves_the_rest_into_a_new_session() {
+        let mut state = state();
+        let session_id = recorded(&mut state, "dev", DAY_MS, 10);
+        state.describe_session(session_id, SessionMetadata { name: "Walk".to_string(), ..Default::default() });
+        let all = times(&state, session_id);
+
+        let split = SessionOperation::Split { session_id, at: all[4].clone() };
+        let outcome = state.operate(USER, &split, true).unwrap();
+        assert_eq!((outcome.affected, outcome.remaining), (6, 4));
+
+        let created = outcome.created.unwrap();
+        assert_eq!(times(&state, session_id), all[..4].to_vec());
+        assert_eq!(times(&state, created), all[4..].to_vec());
+
+        let new_session = state.sessions.iter().find(|s| s.session_id == created).unwrap();
+        assert_eq!(new_session.name, "Walk (part 2)");
+        assert_eq!(new_session.device_id.as_deref(), Some("dev"));
+    }
+
+    #[wasm_bindgen_test]
+    fn split_must_leave_both_halves_something() {
+        let mut state = state();
+        let session_id = recorded(&mut state, "dev", DAY_MS, 10);
+        let all = times(&state, session_id);
+
+        let at_start = SessionOperation::Split { session_id, at: all[0].clone() };
+        assert_eq!(status(state.operate(USER, &at_start, true)), Some(422));
+
+        let past_end = SessionOperation::Split { session_id, at: time::format_datetime(DAY_MS + 5000.0) };
+        assert_eq!(status(state.operate(USER, &past_end, true)), Some(422));
+        assert_eq!(state.sessions.len(), 1);
+    }
+
+    #[wasm_bindgen_test]
+    fn merge_appends_the_second_session() {
+        let mut state = state();
+        let first = recorded(&mut state, "dev", DAY_MS, 5);
+        let second = recorded(&mut state, "dev", DAY_MS + 60_000.0, 3);
+        let expected: Vec<String> = times(&state, first).into_iter().chain(times(&state, second)).collect();
+
+        let merge = SessionOperation::Merge { first, second };
+        let outcome = state.operate(USER, &merge, true).unwrap();
+        assert_eq!((outcome.affected, outcome.remaining), (3, 8));
+
+        assert_eq!(times(&state, first), expected);
+        assert!(!state.datapoints.contains_key(&second));
+        assert!(state.sessions.iter().all(|s| s.session_id != second));
+    }
+
+    #[wasm_bindgen_test]
+    fn merge_needs_consecutive_sessions_from_one_device() {
+        let mut state = state();
+        let first = recorded(&mut state, "dev", DAY_MS, 5);
+        let between = recorded(&mut state, "dev", DAY_MS + 30_000.0, 5);
+        let second = recorded(&mut state, "dev", DAY_MS + 60_000.0, 5);
+        let other_device = recorded(&mut state, "other", DAY_MS + 90_000.0, 5);
+
+        // Something else from the device was recorded in between
+        assert_eq!(status(state.operate(USER, &SessionOperation::Merge { first, second }, true)), Some(422));
+        // Wrong way round
+        assert_eq!(status(state.operate(USER, &SessionOperation::Merge { first: second, second: between }, true)), Some(422));
+        assert_eq!(status(state.operate(USER, &SessionOperation::Merge { first: second, second: other_device }, true)), Some(422));
+        assert_eq!(status(state.operate(USER, &SessionOperation::Merge { first, second: first }, true)), Some(422));
+
+        assert!(state.operate(USER, &SessionOperation::Merge { first, second: between }, true).is_ok());
+        assert_eq!(state.sessions.len(), 3);
+    }
+
+    #[wasm_bindgen_test]
+    fn merge_needs_a_known_device() {
+        let mut state = state();
+        let first = recorded(&mut state, "dev", DAY_MS, 5);
+        let second = recorded(&mut state, "dev", DAY_MS + 60_000.0, 5);
+        for session in state.sessions.iter_mut() {
+            session.device_id = None;
+        }
+
+        assert_eq!(status(state.operate(USER, &SessionOperation::Merge { first, second }, false)), Some(422));
+        assert_eq!(state.sessions.len(), 2);
+    }
+}
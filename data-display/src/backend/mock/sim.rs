@@ -0,0 +1,194 @@
+//! Simulated data acquisition device for the mock backend
+//!
+//! Produces GPS, accelerometer, gyroscope and DAC samples that move and
+//!     drift the way a unit carried around on foot would, plus the unit's
+//!     own hardware telemetry.
+//!
+
+use serde_json::json;
+use serde_json::Value;
+
+/// Starting point for simulated tracks (Kelley Engineering Center, Corvallis)
+const START_LAT: f64 = 44.5672;
+const START_LON: f64 = -123.2786;
+const START_ALT: f64 = 72.0;
+
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+/// Battery percent drained per second at idle and per percent of cpu load
+const BATTERY_DRAIN_IDLE: f64 = 0.006;
+const BATTERY_DRAIN_LOAD: f64 = 0.000_08;
+const GRAVITY: f64 = 9.80665;
+
+/// Small xorshift generator. Good enough for noise, and keeps the crate free of an rng dependency.
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng {
+            state: seed.max(1),
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    /// Uniform sample in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Normally distributed sample with mean 0 and the given standard deviation
+    pub fn gaussian(&mut self, std_dev: f64) -> f64 {
+        // Box-Muller transform
+        let u1 = self.next_f64().max(f64::MIN_POSITIVE);
+        let u2 = self.next_f64();
+        (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos() * std_dev
+    }
+}
+
+/// State of one simulated device between samples
+pub struct SimDevice {
+    rng: Rng,
+    lat: f64,
+    lon: f64,
+    alt: f64,
+    /// Direction of travel in radians, clockwise from north
+    heading: f64,
+    /// Ground speed in m/s
+    speed: f64,
+    /// Seconds since the simulation started, drives the slow DAC waveforms
+    elapsed: f64,
+}
+
+impl SimDevice {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = Rng::new(seed);
+        let heading = rng.next_f64() * 2.0 * std::f64::consts::PI;
+
+        SimDevice {
+            rng,
+            lat: START_LAT,
+            lon: START_LON,
+            alt: START_ALT,
+            heading,
+            speed: 1.4,
+            elapsed: 0.0,
+        }
+    }
+
+    /// Advance the simulation by `dt` seconds and return the new sample as a datapoint blob
+    pub fn step(&mut self, dt: f64) -> Value {
+        self.elapsed += dt;
+
+        // Wander: slowly varying heading and walking pace, with the odd stop
+        let turn_rate = self.rng.gaussian(0.15);
+        self.heading = (self.heading + turn_rate * dt).rem_euclid(2.0 * std::f64::consts::PI);
+        let target_speed = if (self.elapsed / 90.0).sin() > 0.85 { 0.0 } else { 1.4 };
+        self.speed += (target_speed - self.speed) * (dt / 5.0).min(1.0) + self.rng.gaussian(0.05) * dt.sqrt();
+        self.speed = self.speed.max(0.0);
+
+        let distance = self.speed * dt;
+        let d_north = distance * self.heading.cos();
+        let d_east = distance * self.heading.sin();
+        self.lat += (d_north / EARTH_RADIUS_M).to_degrees();
+        self.lon += (d_east / (EARTH_RADIUS_M * self.lat.to_radians().cos())).to_degrees();
+        self.alt += self.rng.gaussian(0.05) * dt.sqrt() + 0.02 * distance * (self.elapsed / 60.0).sin();
+
+        // Footsteps show up as vibration when moving
+        let step_phase = self.elapsed * 2.0 * std::f64::consts::PI * 1.8;
+        let vibration = if self.speed > 0.2 { 1.5 * step_phase.sin() } else { 0.0 };
+        let accel_x = self.rng.gaussian(0.08) + 0.3 * vibration;
+        let accel_y = self.rng.gaussian(0.08) + self.speed * turn_rate;
+        let accel_z = GRAVITY + vibration + self.rng.gaussian(0.1);
+
+        // deg/s, yaw follows the heading changes
+        let gyro_x = self.rng.gaussian(1.5) + 4.0 * vibration;
+        let gyro_y = self.rng.gaussian(1.5);
+        let gyro_z = turn_rate.to_degrees() + self.rng.gaussian(0.5);
+
+        // 0-5 V channels with slow waveforms
+        let dac_1 = 2.5 + 2.0 * (self.elapsed / 30.0).sin() + self.rng.gaussian(0.02);
+        let dac_2 = 1.0 + 0.5 * (self.elapsed / 7.0).cos() + self.rng.gaussian(0.02);
+        let dac_3 = (self.elapsed / 120.0 % 1.0) * 5.0;
+        let dac_4 = 3.3 + self.rng.gaussian(0.01);
+
+        json!({
+            "lat": self.lat,
+            "lon": self.lon,
+            "alt": self.alt,
+            "accel_x": accel_x,
+            "accel_y": accel_y,
+            "accel_z": accel_z,
+            "gyro_x": gyro_x,
+            "gyro_y": gyro_y,
+            "gyro_z": gyro_z,
+            "dac_1": dac_1.clamp(0.0, 5.0),
+            "dac_2": dac_2.clamp(0.0, 5.0),
+            "dac_3": dac_3,
+            "dac_4": dac_4,
+        })
+    }
+}
+
+/// Move `value` towards `target` as a first order system with time constant `tau` seconds
+fn approach(value: f64, target: f64, dt: f64, tau: f64) -> f64 {
+    value + (target - value) * (1.0 - (-dt / tau).exp())
+}
+
+/// Hardware telemetry of one simulated device: cpu, battery and cellular modem
+pub struct SimHardware {
+    rng: Rng,
+    /// Seconds since the simulation started
+    elapsed: f64,
+    pub cpu_temp_c: f64,
+    pub cpu_load_pct: f64,
+    pub battery_temp_c: f64,
+    pub battery_pct: f64,
+    pub cellular_dbm: f64,
+}
+
+impl SimHardware {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = Rng::new(seed ^ 0xA5A5_A5A5_A5A5_A5A5);
+        let battery_pct = 85.0 + rng.next_f64() * 15.0;
+
+        SimHardware {
+            rng,
+            elapsed: 0.0,
+            cpu_temp_c: 45.0,
+            cpu_load_pct: 30.0,
+            battery_temp_c: 25.0,
+            battery_pct,
+            cellular_dbm: -85.0,
+        }
+    }
+
+    /// Advance the hardware by `dt` seconds
+    pub fn step(&mut self, dt: f64) -> () {
+        if dt <= 0.0 {
+            return;
+        }
+        self.elapsed += dt;
+
+        // Load swings slowly between light and heavy work, heat follows with a lag
+        let target_load = 40.0 + 30.0 * (self.elapsed / 240.0).sin() + self.rng.gaussian(5.0);
+        self.cpu_load_pct = approach(self.cpu_load_pct, target_load, dt, 10.0).clamp(0.0, 100.0);
+        self.cpu_temp_c = approach(self.cpu_temp_c, 40.0 + 0.45 * self.cpu_load_pct, dt, 60.0)
+            + self.rng.gaussian(0.2);
+        self.battery_temp_c = approach(self.battery_temp_c, 24.0 + 0.15 * self.cpu_load_pct, dt, 300.0);
+
+        let drain = (BATTERY_DRAIN_IDLE + BATTERY_DRAIN_LOAD * self.cpu_load_pct) * dt;
+        self.battery_pct = (self.battery_pct - drain).max(0.0);
+
+        // Signal drifts as the unit moves around
+        self.cellular_dbm = (self.cellular_dbm + self.rng.gaussian(1.0) * dt.sqrt().min(5.0)
+            + (-85.0 - self.cellular_dbm) * (dt / 120.0).min(1.0))
+            .clamp(-120.0, -50.0);
+    }
+}
@@ -0,0 +1,235 @@
+//! Backend talking to the Rust-Tcp server through client-api-lib
+//!
+
+extern crate client;
+use client::api::{auth, session, session_sensor_data, user};
+
+use serde::Deserialize;
+use serde_json::Value;
+use zeroize::Zeroizing;
+
+use super::{
+    Account, AuthToken, Backend, BackendError, BackendFuture, Datapoint, DeviceConfig, DeviceKey, DeviceStatus,
+    Geofence, NewDeviceKey, OperationOutcome, RecordingStatus, Session, SessionMetadata, SessionOperation,
+};
+
+/// Session list response body
+#[derive(Deserialize)]
+struct SessionResponse {
+    sessions: Vec<Session>,
+}
+
+/// Datapoint list response body
+#[derive(Deserialize)]
+struct DataResponse {
+    datapoints: Vec<Datapoint>,
+}
+
+/// Stateless wrapper around the client api. A fresh client is taken for every call.
+///
+/// Requests to the server are not authenticated. client-api-lib calls take no
+///     credentials, so there is nothing to attach a token to, and the server
+///     answers data requests for whichever username it is given. The
+///     `AuthToken` this backend hands out only records that the server
+///     accepted the password. Sending it is blocked on client-api-lib taking a
+///     token, until then do not expose this backend on a shared network.
+pub struct TcpBackend {}
+
+impl TcpBackend {
+    pub fn new() -> Self {
+        TcpBackend {}
+    }
+}
+
+/// Turn a status code and optional body into a parsed response
+fn parse_body<T: for<'de> Deserialize<'de>>(status: u16, expected: u16, val: Option<Value>) -> Result<T, BackendError> {
+    check_status(status, expected)?;
+
+    let val = val.ok_or_else(|| BackendError::Parse("empty response".to_string()))?;
+    serde_json::from_value::<T>(val).map_err(|e| BackendError::Parse(e.to_string()))
+}
+
+/// Check a status code for calls whose response body is not used
+fn check_status(status: u16, expected: u16) -> Result<(), BackendError> {
+    match status {
+        s if s == expected => Ok(()),
+        401 => Err(BackendError::Unauthorized),
+        s => Err(BackendError::Status(s)),
+    }
+}
+
+impl Backend for TcpBackend {
+    fn create_user(&self, username: &str, password: &str) -> BackendFuture<()> {
+        let username = username.to_string();
+        let password = Zeroizing::new(password.to_string());
+
+        Box::pin(async move {
+            let client = client::get_client();
+            let (status, _val) = user::create_user(&client, &username, &password).await;
+            check_status(status, 201)
+        })
+    }
+
+    /// The server does not expose account details yet
+    fn account(&self, _token: &AuthToken) -> BackendFuture<Account> {
+        Box::pin(async { Err(BackendError::Unsupported) })
+    }
+
+    fn change_password(&self, _token: &AuthToken, _old_password: &str, _new_password: &str) -> BackendFuture<()> {
+        Box::pin(async { Err(BackendError::Unsupported) })
+    }
+
+    fn change_email(&self, _token: &AuthToken, _password: &str, _email: &str) -> BackendFuture<()> {
+        Box::pin(async { Err(BackendError::Unsupported) })
+    }
+
+    fn delete_account(&self, _token: &AuthToken, _password: &str) -> BackendFuture<()> {
+        Box::pin(async { Err(BackendError::Unsupported) })
+    }
+
+    /// Devices are not issued their own credentials by the server yet
+    fn device_keys(&self, _token: &AuthToken) -> BackendFuture<Vec<DeviceKey>> {
+        Box::pin(async { Err(BackendError::Unsupported) })
+    }
+
+    fn create_device_key(&self, _token: &AuthToken, _name: &str) -> BackendFuture<NewDeviceKey> {
+        Box::pin(async { Err(BackendError::Unsupported) })
+    }
+
+    fn revoke_device_key(&self, _token: &AuthToken, _key_id: &str) -> BackendFuture<()> {
+        Box::pin(async { Err(BackendError::Unsupported) })
+    }
+
+    fn rotate_device_key(&self, _token: &AuthToken, _key_id: &str, _grace_secs: f64) -> BackendFuture<NewDeviceKey> {
+        Box::pin(async { Err(BackendError::Unsupported) })
+    }
+
+    /// The server does not relay device status yet
+    fn device_status(&self, _token: &AuthToken) -> BackendFuture<DeviceStatus> {
+        Box::pin(async { Err(BackendError::Unsupported) })
+    }
+
+    /// Configuration is still changed on the device itself
+    fn device_config(&self, _token: &AuthToken) -> BackendFuture<DeviceConfig> {
+        Box::pin(async { Err(BackendError::Unsupported) })
+    }
+
+    fn set_device_config(&self, _token: &AuthToken, _config: &DeviceConfig) -> BackendFuture<()> {
+        Box::pin(async { Err(BackendError::Unsupported) })
+    }
+
+    fn geofences(&self, _token: &AuthToken) -> BackendFuture<Vec<Geofence>> {
+        Box::pin(async { Err(BackendError::Unsupported) })
+    }
+
+    fn save_geofence(&self, _token: &AuthToken, _fence: &Geofence) -> BackendFuture<Geofence> {
+        Box::pin(async { Err(BackendError::Unsupported) })
+    }
+
+    fn delete_geofence(&self, _token: &AuthToken, _fence_id: &str) -> BackendFuture<()> {
+        Box::pin(async { Err(BackendError::Unsupported) })
+    }
+
+    /// Only checks the password. See `TcpBackend` for why the token is not the server's.
+    fn login(&self, username: &str, password: &str) -> BackendFuture<AuthToken> {
+        let username = username.to_string();
+        let password = Zeroizing::new(password.to_string());
+
+        Box::pin(async move {
+            let client = client::get_client();
+            let (status, _val, _val2) = auth::user_login(&client, &username, &password).await;
+
+            // A rejected login is a credential problem, not an expired session
+            if status == 401 {
+                return Err(BackendError::Status(401));
+            }
+            check_status(status, 201)?;
+
+            Ok(AuthToken::new(username))
+        })
+    }
+
+    /// Blocked on the server, which has no endpoint to check a token. Remembered
+    ///     logins are never restored with this backend.
+    fn validate_token(&self, _token: &AuthToken) -> BackendFuture<String> {
+        Box::pin(async { Err(BackendError::Unsupported) })
+    }
+
+    fn create_session(&self, _token: &AuthToken, username: &str) -> BackendFuture<()> {
+        let username = username.to_string();
+
+        Box::pin(async move {
+            let client = client::get_client();
+            let (status, _val) = session::create_session(&client, &username).await;
+            check_status(status, 201)
+        })
+    }
+
+    /// The server does not check that `username` is the one logged in
+    fn sessions_by_user(&self, _token: &AuthToken, username: &str) -> BackendFuture<Vec<Session>> {
+        let username = username.to_string();
+
+        Box::pin(async move {
+            let client = client::get_client();
+            let (status, val) = session::view_sessions_by_user(&client, &username).await;
+            parse_body::<SessionResponse>(status, 200, val).map(|parsed| parsed.sessions)
+        })
+    }
+
+    /// The server does not store session metadata yet
+    fn update_session(&self, _token: &AuthToken, _session_id: &str, _metadata: &SessionMetadata) -> BackendFuture<()> {
+        Box::pin(async { Err(BackendError::Unsupported) })
+    }
+
+    /// The server cannot change session contents yet
+    fn preview_operation(&self, _token: &AuthToken, _operation: &SessionOperation) -> BackendFuture<OperationOutcome> {
+        Box::pin(async { Err(BackendError::Unsupported) })
+    }
+
+    fn run_operation(&self, _token: &AuthToken, _operation: &SessionOperation) -> BackendFuture<OperationOutcome> {
+        Box::pin(async { Err(BackendError::Unsupported) })
+    }
+
+    /// The server has no recording commands yet, the device records on its own
+    fn recording_status(&self, _token: &AuthToken) -> BackendFuture<RecordingStatus> {
+        Box::pin(async { Err(BackendError::Unsupported) })
+    }
+
+    fn start_recording(&self, _token: &AuthToken, _session_id: Option<&str>) -> BackendFuture<RecordingStatus> {
+        Box::pin(async { Err(BackendError::Unsupported) })
+    }
+
+    fn pause_recording(&self, _token: &AuthToken) -> BackendFuture<RecordingStatus> {
+        Box::pin(async { Err(BackendError::Unsupported) })
+    }
+
+    fn resume_recording(&self, _token: &AuthToken) -> BackendFuture<RecordingStatus> {
+        Box::pin(async { Err(BackendError::Unsupported) })
+    }
+
+    fn stop_recording(&self, _token: &AuthToken) -> BackendFuture<RecordingStatus> {
+        Box::pin(async { Err(BackendError::Unsupported) })
+    }
+
+    fn datapoints_by_session(&self, _token: &AuthToken, session_id: &str) -> BackendFuture<Vec<Datapoint>> {
+        let session_id = session_id.to_string();
+
+        Box::pin(async move {
+            let client = client::get_client();
+            let (status, val) = session_sensor_data::view_datapoints_by_session_id(&client, &session_id).await;
+            parse_body::<DataResponse>(status, 200, val).map(|parsed| parsed.datapoints)
+        })
+    }
+
+    fn datapoints_since(&self, _token: &AuthToken, session_id: &str, datetime: &str) -> BackendFuture<Vec<Datapoint>> {
+        let session_id = session_id.to_string();
+        let datetime = datetime.to_string();
+
+        Box::pin(async move {
+            let client = client::get_client();
+            let (status, val) =
+                session_sensor_data::view_all_datapoints_by_id_datetime(&client, &session_id, &datetime).await;
+            parse_body::<DataResponse>(status, 200, val).map(|parsed| parsed.datapoints)
+        })
+    }
+}
@@ -8,15 +8,19 @@ mod device;
 mod login;
 mod sessions;
 mod data;
+mod request;
+mod store;
+#[cfg(test)]
+pub mod testing;
 
 use eframe::egui;
+use store::AppStore;
 
-/// Container for all window-controlling structs. Manages shared resources and calls draw() functions.
-#[allow(dead_code)]
+use crate::backend;
+
+/// Container for all window-controlling structs. Owns the shared state store and calls draw() functions.
 pub struct DisplayApp {
-    logged_in: Box<bool>,
-    username: Box<String>,
-    current_session: Box<String>,
+    store: AppStore,
 
     window_login: login::LoginDisplay,
     window_account: account::AccountDisplay,
@@ -27,23 +31,15 @@ pub struct DisplayApp {
 
 impl Default for DisplayApp {
     fn default() -> Self {
-        // This allocates a box and unwraps the heap pointer
-        let logged_in_ptr: *mut bool = Box::into_raw(Box::new(false));
-        let username_ptr: *mut String = Box::into_raw(Box::new(String::new()));
-        let current_session_ptr: *mut String = Box::into_raw(Box::new(String::new()));
-
         Self {
-            // This puts the box back together to avoid leaks
-            logged_in: unsafe { Box::from_raw(logged_in_ptr) },
-            username: unsafe { Box::from_raw(username_ptr) },
-            current_session: unsafe { Box::from_raw(current_session_ptr) },
+            store: AppStore::new(backend::connect()),
 
-            // Declare windows we will draw and provide pointers to shared resources
-            window_login: login::LoginDisplay::new(logged_in_ptr, username_ptr),
+            // Declare windows we will draw. Shared resources are handed to them through the store each frame
+            window_login: login::LoginDisplay::new(),
             window_account: account::AccountDisplay::new(),
-            window_sessions: sessions::SessionDisplay::new(username_ptr, current_session_ptr),
+            window_sessions: sessions::SessionDisplay::new(),
             window_device: device::DeviceDisplay::new(),
-            window_data: data::DataWindow::new(current_session_ptr),
+            window_data: data::DataWindow::new(),
         }
     }
 }
@@ -57,7 +53,24 @@ impl DisplayApp {
             ..Default::default()
         });
 
-        Default::default()
+        let mut app: DisplayApp = Default::default();
+
+        // Pick up a login remembered from an earlier visit
+        if let Some(saved) = cc.storage.and_then(AppStore::load_login) {
+            app.window_login.restore(&cc.egui_ctx, &app.store, saved);
+        }
+
+        app
+    }
+
+    /// Cancel in-flight requests and drop everything cached for the previous user
+    fn reset_windows(&mut self) -> () {
+        self.store.link().disconnect();
+        self.window_login.reset();
+        self.window_account.reset();
+        self.window_sessions.reset();
+        self.window_device.reset();
+        self.window_data.reset();
     }
 
     /// Helper function to draw the top EGUI Bar
@@ -86,7 +99,7 @@ impl DisplayApp {
 
 /// This Implementation initiates the draw step for everything in the app.
 impl eframe::App for DisplayApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
         // Draw the top bar
         DisplayApp::draw_top_bar(ctx);
 
@@ -94,12 +107,33 @@ impl eframe::App for DisplayApp {
         DisplayApp::draw_bottom_bar(ctx);
 
         // Draw windows
-        self.window_login.draw(ctx);
-        if *self.logged_in {
-            self.window_account.draw(ctx);
-            self.window_sessions.draw(ctx);
-            self.window_device.draw(ctx);
-            self.window_data.draw(ctx);
+        self.window_login.draw(ctx, &self.store);
+        if self.store.state().logged_in {
+            self.store.link().tick(ctx);
+            self.window_account.draw(ctx, &self.store);
+            self.window_sessions.draw(ctx, &self.store);
+            self.window_device.draw(ctx, &self.store);
+            self.window_data.draw(ctx, &self.store);
+        }
+
+        // Apply any state changes the windows requested this frame and redraw with them
+        let was_logged_in = self.store.state().logged_in;
+        if self.store.process_events() {
+            // Logged out or expired: wipe every window before the login window shows again
+            if was_logged_in && !self.store.state().logged_in {
+                self.reset_windows();
+            }
+
+            // Write logins and logouts through right away rather than on eframe's autosave timer
+            if let Some(storage) = frame.storage_mut() {
+                self.store.save_login(storage);
+            }
+            ctx.request_repaint();
         }
     }
+
+    /// Called by eframe on its autosave timer and on shutdown
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        self.store.save_login(storage);
+    }
 }
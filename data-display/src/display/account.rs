@@ -1,6 +1,25 @@
 //! Account information / settings display and appearance controller
 //!
 
+mod keys;
+
+use eframe::egui::{Color32, RichText};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::backend::{Account, BackendError};
+
+use super::login::{password_checklist, password_field, password_rules};
+use super::request::RequestChannel;
+use super::store::{AppEvent, AppStore};
+
+/// Sub-dialog currently shown in place of the account details
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum AccountDialog {
+    ChangePassword,
+    ChangeEmail,
+    DeleteAccount,
+}
+
 /// Handles functions superficial to the interface
 ///
 /// Including:
@@ -8,36 +27,344 @@
 /// - Changing account credentials
 /// - Changing appearance settings
 pub struct AccountDisplay {
-    _account_id: String, // Example
+    /// Profile fetched from the backend
+    account: Option<Account>,
+    loaded: bool,
+
+    /// Open sub-dialog, if any
+    dialog: Option<AccountDialog>,
+    /// Device key management, shown in place of the account details while open
+    keys: keys::KeyPanel,
+    keys_open: bool,
+    /// Outcome of the last change, and whether it succeeded
+    message: Option<(bool, String)>,
+
+    /// Current password, asked for by every dialog. Zeroized once submitted.
+    password_str: String,
+    /// New password and its confirmation. Zeroized once submitted.
+    new_password_str: String,
+    confirm_str: String,
+    /// Show the password fields in plain text
+    reveal_password: bool,
+    /// New email and its confirmation
+    email_str: String,
+    confirm_email_str: String,
+    /// Username typed to confirm account deletion
+    delete_confirm_str: String,
+
+    /// Results of profile fetches
+    account_requests: RequestChannel<Result<Account, BackendError>>,
+    /// Results of dialog submissions, tagged with the dialog that sent them
+    change_requests: RequestChannel<(AccountDialog, Result<(), BackendError>)>,
 }
 
 impl AccountDisplay {
     pub fn new() -> Self {
         AccountDisplay {
-            _account_id: String::from(""),
+            account: None,
+            loaded: false,
+
+            dialog: None,
+            keys: keys::KeyPanel::new(),
+            keys_open: false,
+            message: None,
+
+            password_str: String::from(""),
+            new_password_str: String::from(""),
+            confirm_str: String::from(""),
+            reveal_password: false,
+            email_str: String::from(""),
+            confirm_email_str: String::from(""),
+            delete_confirm_str: String::from(""),
+
+            account_requests: RequestChannel::new(),
+            change_requests: RequestChannel::new(),
+        }
+    }
+
+    /// Clear anything held for the previous user
+    pub fn reset(&mut self) -> () {
+        self.account_requests.cancel();
+        self.change_requests.cancel();
+
+        self.account = None;
+        self.loaded = false;
+        self.message = None;
+        self.close_dialog();
+        self.keys.reset();
+        self.keys_open = false;
+    }
+
+    /// Request the profile of the logged in user. The response is picked up by `receive_account`
+    fn load_account(&mut self, ctx: &eframe::egui::Context, store: &AppStore) -> () {
+        let token = match &store.state().token {
+            Some(token) => token.clone(),
+            None => return,
+        };
+
+        self.loaded = true;
+        let responder = self.account_requests.issue(ctx);
+        let request = store.backend().account(&token);
+
+        wasm_bindgen_futures::spawn_local(async move {
+            responder.respond(request.await);
+        });
+    }
+
+    fn receive_account(&mut self, store: &AppStore) -> () {
+        match self.account_requests.poll() {
+            Some(Ok(account)) => self.account = Some(account),
+            Some(Err(e)) => store.report_error("Account fetch", &e),
+            None => {}
+        }
+    }
+
+    /// Send the open dialog to the backend. The result is handled by `receive_change`
+    fn submit(&mut self, ctx: &eframe::egui::Context, store: &AppStore, dialog: AccountDialog) -> () {
+        let token = match &store.state().token {
+            Some(token) => token.clone(),
+            None => return,
+        };
+
+        let password = Zeroizing::new(self.password_str.clone());
+        let backend = store.backend();
+        let request = match dialog {
+            AccountDialog::ChangePassword => {
+                let new_password = Zeroizing::new(self.new_password_str.clone());
+                backend.change_password(&token, &password, &new_password)
+            }
+            AccountDialog::ChangeEmail => backend.change_email(&token, &password, self.email_str.trim()),
+            AccountDialog::DeleteAccount => backend.delete_account(&token, &password),
+        };
+        self.clear_secrets();
+        self.message = None;
+
+        let responder = self.change_requests.issue(ctx);
+        wasm_bindgen_futures::spawn_local(async move {
+            responder.respond((dialog, request.await));
+        });
+    }
+
+    /// Close the dialog on success, or tell the user what went wrong
+    fn receive_change(&mut self, store: &AppStore) -> () {
+        let (dialog, result) = match self.change_requests.poll() {
+            Some(response) => response,
+            None => return,
+        };
+
+        match result {
+            Ok(()) => {
+                self.close_dialog();
+                match dialog {
+                    AccountDialog::ChangePassword => {
+                        self.message = Some((true, "Password changed.".to_string()));
+                    }
+                    AccountDialog::ChangeEmail => {
+                        self.message = Some((true, "Email changed.".to_string()));
+                        self.loaded = false;
+                    }
+                    AccountDialog::DeleteAccount => {
+                        web_sys::console::log_1( &"Account deleted.".into() );
+                        store.publish(AppEvent::LoggedOut);
+                    }
+                }
+            }
+            Err(BackendError::Status(403)) => {
+                self.message = Some((false, "Current password is incorrect.".to_string()));
+            }
+            Err(e) => {
+                store.report_error("Account change", &e);
+                self.message = Some((false, format!("Could not save the change. {}", e)));
+            }
         }
     }
 
+    /// Overwrite the password fields in memory
+    fn clear_secrets(&mut self) -> () {
+        self.password_str.zeroize();
+        self.new_password_str.zeroize();
+        self.confirm_str.zeroize();
+        self.reveal_password = false;
+    }
+
+    /// Leave the open dialog and clear its fields
+    fn close_dialog(&mut self) -> () {
+        self.clear_secrets();
+        self.email_str.clear();
+        self.confirm_email_str.clear();
+        self.delete_confirm_str.clear();
+        self.dialog = None;
+    }
+
     /// Helper function to assign window contents
-    fn show_account_info(&mut self, ui: &mut eframe::egui::Ui) -> () {
-        ui.label("User: PLACEHOLDER");
-        ui.label("Email: EXAMPLE@DOMAIN.COM");
+    fn show_account_info(&mut self, ui: &mut eframe::egui::Ui, store: &AppStore) -> () {
+        match &self.account {
+            Some(account) => {
+                ui.label(format!("User: {}", account.username));
+                ui.label(format!("Email: {}", if account.email.is_empty() { "---" } else { &account.email }));
+            }
+            // Backends without account details still know who logged in
+            None => {
+                ui.label(format!("User: {}", store.state().username));
+                ui.label("Email: ---");
+            }
+        }
+
+        if let Some((success, message)) = &self.message {
+            ui.label(RichText::new(message).color(if *success { Color32::GREEN } else { Color32::RED }));
+        }
+
         if ui.link("Change Password").clicked() {
-            // TODO: Implement
+            self.message = None;
+            self.dialog = Some(AccountDialog::ChangePassword);
         }
-        if ui.link("Regenerate Device Keys").clicked() {
-            // TODO: Implement
+        if ui.link("Change Email").clicked() {
+            self.message = None;
+            self.dialog = Some(AccountDialog::ChangeEmail);
+        }
+        if ui.link("Device Keys").clicked() {
+            self.message = None;
+            self.keys_open = true;
         }
         if ui.link("Appearance Settings").clicked() {
 
             // TODO: Implement
         }
+        if ui.link(RichText::new("Delete Account").color(Color32::RED)).clicked() {
+            self.message = None;
+            self.dialog = Some(AccountDialog::DeleteAccount);
+        }
+    }
+
+    /// Helper function to draw the password change dialog
+    fn show_change_password(&mut self, ui: &mut eframe::egui::Ui, store: &AppStore) -> bool {
+        ui.label(RichText::new("Change Password").heading());
+
+        ui.label("Current Password:");
+        password_field(ui, &mut self.password_str, &mut self.reveal_password);
+
+        ui.label("New Password:");
+        ui.add(eframe::egui::TextEdit::singleline(&mut self.new_password_str).password(!self.reveal_password));
+
+        ui.label("Confirm New Password:");
+        ui.add(eframe::egui::TextEdit::singleline(&mut self.confirm_str).password(!self.reveal_password));
+
+        // Strength checklist
+        let username = self.account.as_ref().map(|a| a.username.clone()).unwrap_or_else(|| store.state().username.clone());
+        let rules = password_rules(&username, &self.new_password_str);
+        password_checklist(ui, &rules);
+
+        let matches = !self.new_password_str.is_empty() && self.new_password_str == self.confirm_str;
+        if !self.confirm_str.is_empty() && !matches {
+            ui.label(RichText::new("Passwords do not match").color(Color32::RED).small());
+        }
+
+        !self.password_str.is_empty() && rules.iter().all(|(_, met)| *met) && matches
+    }
+
+    /// Helper function to draw the email change dialog
+    fn show_change_email(&mut self, ui: &mut eframe::egui::Ui) -> bool {
+        ui.label(RichText::new("Change Email").heading());
+
+        ui.label("New Email:");
+        ui.text_edit_singleline(&mut self.email_str);
+
+        ui.label("Confirm New Email:");
+        ui.text_edit_singleline(&mut self.confirm_email_str);
+
+        ui.label("Current Password:");
+        password_field(ui, &mut self.password_str, &mut self.reveal_password);
+
+        let email = self.email_str.trim();
+        let valid = email.split_once('@').is_some_and(|(user, domain)| !user.is_empty() && domain.contains('.'));
+        if !email.is_empty() && !valid {
+            ui.label(RichText::new("Not a valid email address").color(Color32::RED).small());
+        }
+
+        let matches = email == self.confirm_email_str.trim();
+        if !self.confirm_email_str.is_empty() && !matches {
+            ui.label(RichText::new("Emails do not match").color(Color32::RED).small());
+        }
+
+        valid && matches && !self.password_str.is_empty()
+    }
+
+    /// Helper function to draw the account deletion dialog
+    fn show_delete_account(&mut self, ui: &mut eframe::egui::Ui, store: &AppStore) -> bool {
+        let username = self.account.as_ref().map(|a| a.username.clone()).unwrap_or_else(|| store.state().username.clone());
+
+        ui.label(RichText::new("Delete Account").heading());
+        ui.label(RichText::new("This permanently deletes your account and every recorded session.")
+            .color(Color32::RED));
+
+        ui.label(format!("Type \"{}\" to confirm:", username));
+        ui.text_edit_singleline(&mut self.delete_confirm_str);
+
+        ui.label("Current Password:");
+        password_field(ui, &mut self.password_str, &mut self.reveal_password);
+
+        !username.is_empty() && self.delete_confirm_str.trim() == username && !self.password_str.is_empty()
+    }
+
+    /// Draw the open sub-dialog with its submit and cancel buttons
+    fn show_dialog(&mut self, ui: &mut eframe::egui::Ui, store: &AppStore, dialog: AccountDialog) -> () {
+        let ready = match dialog {
+            AccountDialog::ChangePassword => self.show_change_password(ui, store),
+            AccountDialog::ChangeEmail => self.show_change_email(ui),
+            AccountDialog::DeleteAccount => self.show_delete_account(ui, store),
+        };
+
+        if let Some((false, message)) = &self.message {
+            ui.label(RichText::new(message).color(Color32::RED));
+        }
+
+        let submit_text = match dialog {
+            AccountDialog::DeleteAccount => RichText::new("Delete Account").color(Color32::RED),
+            _ => RichText::new("Save"),
+        };
+        let can_submit = ready && !self.change_requests.is_pending();
+
+        ui.horizontal(|ui| {
+            if ui.add_enabled(can_submit, eframe::egui::Button::new(submit_text)).clicked() {
+                self.submit(ui.ctx(), store, dialog);
+            }
+
+            if ui.button("Cancel").clicked() {
+                self.change_requests.cancel();
+                self.message = None;
+                self.close_dialog();
+            }
+        });
     }
 
     /// Performs the draw step for the account window
-    pub fn draw(&mut self, ctx: &eframe::egui::Context) -> () {
+    pub fn draw(&mut self, ctx: &eframe::egui::Context, store: &AppStore) -> () {
+        if !self.loaded {
+            self.load_account(ctx, store);
+        }
+        self.receive_account(store);
+        self.receive_change(store);
+
         eframe::egui::Window::new("Account").show(ctx, |ui| {
-            self.show_account_info(ui);
+            if self.keys_open {
+                self.keys.show(ui, store);
+                if ui.button("Back").clicked() {
+                    self.keys_open = false;
+                }
+                return;
+            }
+
+            match self.dialog {
+                Some(dialog) => self.show_dialog(ui, store, dialog),
+                None => self.show_account_info(ui, store),
+            }
         });
     }
 }
+
+/// Don't leave credentials behind in freed memory
+impl Drop for AccountDisplay {
+    fn drop(&mut self) {
+        self.clear_secrets();
+    }
+}
@@ -0,0 +1,285 @@
+//! Device key management panel shown inside the Account window
+//!
+//! Every data acquisition unit uploads with its own key, so one lost unit
+//!     can be revoked without re-provisioning the rest.
+//!
+
+use eframe::egui::{Color32, RichText};
+use zeroize::Zeroize;
+
+use crate::backend::{BackendError, DeviceKey, NewDeviceKey};
+use crate::time::short_datetime;
+
+use crate::display::request::RequestChannel;
+use crate::display::store::AppStore;
+
+/// Default time a rotated key keeps working, in hours
+const DEFAULT_GRACE_HOURS: f64 = 24.0;
+
+/// Lists, generates, revokes and rotates the logged in user's device keys
+pub struct KeyPanel {
+    keys: Vec<DeviceKey>,
+    loaded: bool,
+
+    /// Name for the next generated key
+    name_str: String,
+    /// How long a rotated key keeps working
+    grace_hours: f64,
+    /// Key awaiting a second click before it is revoked
+    confirm_revoke: Option<String>,
+    /// Name and secret of a key that was just generated. Zeroized once dismissed.
+    new_secret: Option<(String, String)>,
+    /// Outcome of the last change, and whether it succeeded
+    message: Option<(bool, String)>,
+
+    /// Results of key list fetches
+    list_requests: RequestChannel<Result<Vec<DeviceKey>, BackendError>>,
+    /// Results of generating or rotating a key
+    create_requests: RequestChannel<Result<NewDeviceKey, BackendError>>,
+    /// Results of revoking a key
+    revoke_requests: RequestChannel<Result<(), BackendError>>,
+}
+
+impl KeyPanel {
+    pub fn new() -> Self {
+        KeyPanel {
+            keys: Vec::new(),
+            loaded: false,
+
+            name_str: String::from(""),
+            grace_hours: DEFAULT_GRACE_HOURS,
+            confirm_revoke: None,
+            new_secret: None,
+            message: None,
+
+            list_requests: RequestChannel::new(),
+            create_requests: RequestChannel::new(),
+            revoke_requests: RequestChannel::new(),
+        }
+    }
+
+    /// Cancel pending requests and forget the previous user's keys
+    pub fn reset(&mut self) -> () {
+        self.list_requests.cancel();
+        self.create_requests.cancel();
+        self.revoke_requests.cancel();
+
+        self.keys.clear();
+        self.loaded = false;
+        self.name_str.clear();
+        self.grace_hours = DEFAULT_GRACE_HOURS;
+        self.confirm_revoke = None;
+        self.message = None;
+        self.dismiss_secret();
+    }
+
+    /// Overwrite the secret of a just generated key
+    fn dismiss_secret(&mut self) -> () {
+        if let Some((_, secret)) = &mut self.new_secret {
+            secret.zeroize();
+        }
+        self.new_secret = None;
+    }
+
+    /// Request the key list. The response is picked up by `receive`
+    fn load_keys(&mut self, ctx: &eframe::egui::Context, store: &AppStore) -> () {
+        let token = match &store.state().token {
+            Some(token) => token.clone(),
+            None => return,
+        };
+
+        self.loaded = true;
+        let responder = self.list_requests.issue(ctx);
+        let request = store.backend().device_keys(&token);
+
+        wasm_bindgen_futures::spawn_local(async move {
+            responder.respond(request.await);
+        });
+    }
+
+    /// Generate a new key, or a replacement for `rotate` if given
+    fn create_key(&mut self, ctx: &eframe::egui::Context, store: &AppStore, rotate: Option<String>) -> () {
+        let token = match &store.state().token {
+            Some(token) => token.clone(),
+            None => return,
+        };
+
+        let request = match rotate {
+            Some(key_id) => store.backend().rotate_device_key(&token, &key_id, self.grace_hours * 3600.0),
+            None => store.backend().create_device_key(&token, self.name_str.trim()),
+        };
+        self.message = None;
+        self.dismiss_secret();
+
+        let responder = self.create_requests.issue(ctx);
+        wasm_bindgen_futures::spawn_local(async move {
+            responder.respond(request.await);
+        });
+    }
+
+    fn revoke_key(&mut self, ctx: &eframe::egui::Context, store: &AppStore, key_id: &str) -> () {
+        let token = match &store.state().token {
+            Some(token) => token.clone(),
+            None => return,
+        };
+
+        self.message = None;
+        let responder = self.revoke_requests.issue(ctx);
+        let request = store.backend().revoke_device_key(&token, key_id);
+
+        wasm_bindgen_futures::spawn_local(async move {
+            responder.respond(request.await);
+        });
+    }
+
+    /// Apply any responses that have arrived
+    fn receive(&mut self, store: &AppStore) -> () {
+        match self.list_requests.poll() {
+            Some(Ok(keys)) => self.keys = keys,
+            Some(Err(e)) => {
+                store.report_error("Device key fetch", &e);
+                self.message = Some((false, format!("Could not load device keys. {}", e)));
+            }
+            None => {}
+        }
+
+        match self.create_requests.poll() {
+            Some(Ok(created)) => {
+                self.name_str.clear();
+                self.new_secret = Some((created.key.name, created.secret));
+                self.loaded = false;
+            }
+            Some(Err(e)) => {
+                store.report_error("Device key generation", &e);
+                self.message = Some((false, format!("Could not generate a key. {}", e)));
+            }
+            None => {}
+        }
+
+        match self.revoke_requests.poll() {
+            Some(Ok(())) => {
+                self.message = Some((true, "Key revoked.".to_string()));
+                self.loaded = false;
+            }
+            Some(Err(e)) => {
+                store.report_error("Device key revocation", &e);
+                self.message = Some((false, format!("Could not revoke the key. {}", e)));
+            }
+            None => {}
+        }
+    }
+
+    /// Draw the panel contents
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui, store: &AppStore) -> () {
+        if !self.loaded {
+            self.load_keys(ui.ctx(), store);
+        }
+        self.receive(store);
+
+        ui.label(RichText::new("Device Keys").heading());
+
+        // A new secret is only shown once, so keep it up until dismissed
+        let mut dismissed = false;
+        if let Some((name, secret)) = &self.new_secret {
+            ui.group(|ui| {
+                ui.label(RichText::new(format!("New key for \"{}\"", name)).strong());
+                ui.label(RichText::new("Copy it to the device now. It will not be shown again.").color(Color32::YELLOW));
+                ui.horizontal(|ui| {
+                    ui.label(RichText::new(secret.as_str()).monospace());
+                    if ui.button("Copy").clicked() {
+                        ui.ctx().copy_text(secret.clone());
+                    }
+                });
+                dismissed = ui.button("Done").clicked();
+            });
+        }
+        if dismissed {
+            self.dismiss_secret();
+        }
+
+        if let Some((success, message)) = &self.message {
+            ui.label(RichText::new(message).color(if *success { Color32::GREEN } else { Color32::RED }));
+        }
+
+        let busy = self.create_requests.is_pending() || self.revoke_requests.is_pending();
+        let mut rotate = None;
+        let mut revoke = None;
+
+        if self.keys.is_empty() {
+            ui.label("No device keys");
+        } else {
+            eframe::egui::Grid::new("device_keys").striped(true).show(ui, |ui| {
+                ui.label(RichText::new("Name").strong());
+                ui.label(RichText::new("Created").strong());
+                ui.label(RichText::new("Last Used").strong());
+                ui.label(RichText::new("Status").strong());
+                ui.end_row();
+
+                for key in &self.keys {
+                    ui.label(&key.name);
+                    ui.label(short_datetime(&key.created));
+                    ui.label(key.last_used.as_deref().map(short_datetime).unwrap_or_else(|| "Never".to_string()));
+
+                    match &key.expires {
+                        Some(expires) => {
+                            ui.label(RichText::new(format!("Expires {}", short_datetime(expires))).color(Color32::YELLOW));
+                        }
+                        None => {
+                            ui.label(RichText::new("Active").color(Color32::GREEN));
+                        }
+                    }
+
+                    ui.add_enabled_ui(!busy, |ui| {
+                        if key.expires.is_none() && ui.link("Rotate").clicked() {
+                            rotate = Some(key.key_id.clone());
+                        }
+                    });
+
+                    ui.add_enabled_ui(!busy, |ui| {
+                        if self.confirm_revoke.as_deref() == Some(key.key_id.as_str()) {
+                            if ui.link(RichText::new("Confirm Revoke").color(Color32::RED)).clicked() {
+                                revoke = Some(key.key_id.clone());
+                            }
+                        } else if ui.link("Revoke").clicked() {
+                            self.confirm_revoke = Some(key.key_id.clone());
+                        }
+                    });
+                    ui.end_row();
+                }
+            });
+        }
+
+        if let Some(key_id) = rotate {
+            self.confirm_revoke = None;
+            self.create_key(ui.ctx(), store, Some(key_id));
+        }
+        if let Some(key_id) = revoke {
+            self.confirm_revoke = None;
+            self.revoke_key(ui.ctx(), store, &key_id);
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Rotated keys keep working for");
+            ui.add(eframe::egui::DragValue::new(&mut self.grace_hours).range(0.0..=168.0).suffix(" h"));
+        });
+
+        ui.separator();
+
+        ui.horizontal(|ui| {
+            ui.label("Name:");
+            ui.text_edit_singleline(&mut self.name_str);
+
+            let can_create = !busy && !self.name_str.trim().is_empty();
+            if ui.add_enabled(can_create, eframe::egui::Button::new("Generate Key")).clicked() {
+                self.create_key(ui.ctx(), store, None);
+            }
+        });
+    }
+}
+
+/// Don't leave an undismissed secret behind in freed memory
+impl Drop for KeyPanel {
+    fn drop(&mut self) {
+        self.dismiss_secret();
+    }
+}
@@ -1,34 +1,71 @@
 //! Main data display window
 //!
 
-extern crate client;
-use client::api::{session_sensor_data};
+use std::collections::HashMap;
 
 use eframe::egui::{ComboBox, Frame};
 use egui_extras::{TableBuilder, Column};
-use egui_plot::{Plot, Line, PlotPoints, Legend};
-use serde::Deserialize;
-use serde_json::Value;
+use egui_plot::{Plot, Line, PlotPoints, Points, Legend};
+use serde::{Deserialize, Serialize};
 use web_sys::window;
 
-/// Row object for table data
-#[derive(Deserialize)]
+use crate::backend::{BackendError, ConnectionType, Datapoint, SessionState};
+use crate::link::DeviceLink;
+use crate::time;
+
+use super::request::RequestChannel;
+use super::store::AppStore;
+
+mod coloring;
+mod geofence;
+mod gps;
+mod map;
+mod profile;
+mod tiles;
+
+/// Row object for table data. Session exports use the same columns.
+#[derive(Deserialize, Serialize, Default)]
 pub struct Row {
-    id: u32,
-    timestamp: String,
-    latitude: f64,
-    longitude: f64,
-    altitude: f64,
-    accel_x: f64,
-    accel_y: f64,
-    accel_z: f64,
-    gyro_x: f64,
-    gyro_y: f64,
-    gyro_z: f64,
-    dac_1: f64,
-    dac_2: f64,
-    dac_3: f64,
-    dac_4: f64,
+    pub id: u32,
+    pub timestamp: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude: f64,
+    pub accel_x: f64,
+    pub accel_y: f64,
+    pub accel_z: f64,
+    pub gyro_x: f64,
+    pub gyro_y: f64,
+    pub gyro_z: f64,
+    pub dac_1: f64,
+    pub dac_2: f64,
+    pub dac_3: f64,
+    pub dac_4: f64,
+}
+
+impl Row {
+    /// Flatten a datapoint's blob into a row. `id` is the row's position in the session.
+    pub fn from_datapoint(id: u32, datapoint: &Datapoint) -> Result<Row, serde_json::Error> {
+        let parsed = serde_json::from_value::<Blob>(datapoint.data_blob.clone())?;
+
+        Ok(Row {
+            id,
+            timestamp: datapoint.datetime.clone(),
+            latitude: parsed.lat,
+            longitude: parsed.lon,
+            altitude: parsed.alt,
+            accel_x: parsed.accel_x,
+            accel_y: parsed.accel_y,
+            accel_z: parsed.accel_z,
+            gyro_x: parsed.gyro_x,
+            gyro_y: parsed.gyro_y,
+            gyro_z: parsed.gyro_z,
+            dac_1: parsed.dac_1,
+            dac_2: parsed.dac_2,
+            dac_3: parsed.dac_3,
+            dac_4: parsed.dac_4,
+        })
+    }
 }
 
 /// Blob object for mapping data_blob in response
@@ -49,19 +86,12 @@ pub struct Blob {
     dac_4: f64,
 }
 
-/// Individual response object 
-#[allow(dead_code)]
-#[derive(Deserialize, Clone)]
-pub struct Row2 {
-    datetime: String,
-    id: i64,
-    data_blob: Value,
-}
-
-/// Response vector
-#[derive(Deserialize)]
-pub struct DataResponse {
-    pub datapoints: Vec<Row2>,
+/// Result of a datapoint request delivered back to the window
+pub enum DataUpdate {
+    /// Full session fetch, replaces everything held for the session
+    Replace(Vec<Datapoint>),
+    /// Refresh containing only datapoints newer than the last one held
+    Append(Vec<Datapoint>),
 }
 
 /// Theme dropdown/toggle
@@ -76,6 +106,7 @@ pub enum Theme {
 pub enum Selection {
     SensorData,
     LocData,
+    MotionData,
     AccelData,
     GyroData,
     DacData,
@@ -94,7 +125,7 @@ pub enum DisplayType {
 pub struct DataWindow {
     table_headers: Vec<String>,
     table_data: Vec<Row>,
-    datapoints: Vec<Row2>,
+    datapoints: Vec<Datapoint>,
 
     dropdown: Selection,
     theme_dropdown: Theme,
@@ -107,15 +138,30 @@ pub struct DataWindow {
     loaded: bool,
     formatted: bool,
     prev_session: String,
-    current_session: *mut String,
     last_refresh: f64,
     last_row: usize,
     last_datetime: Option<String>,
     first_fetch: bool,
+    /// Whether the direct link was up last frame
+    was_direct: bool,
+    /// Whether the current session was recording last frame
+    was_live: bool,
+    /// `sessions_revision` the held data was fetched at
+    sessions_revision: u64,
+    data_requests: RequestChannel<Result<DataUpdate, BackendError>>,
+
+    /// Values derived from GPS fixes, by row id
+    motion: HashMap<u32, gps::Kinematics>,
+    /// Carries the kinematics on as rows are appended
+    motion_tracker: gps::Tracker,
+    map: map::MapView,
+    /// Row hovered on the map last frame, marked on the location profile
+    map_hover: Option<u32>,
+    geofences: geofence::GeofencePanel,
 }
 
 impl DataWindow {
-    pub fn new(current_session: *mut String) -> Self {
+    pub fn new() -> Self {
 
         // Hardcoded headers
         let headers = vec![
@@ -153,103 +199,159 @@ impl DataWindow {
             loaded: false,
             formatted: false,
             prev_session: String::new(),
-            current_session,
             last_refresh: 0.0,
             last_row: 0,
             last_datetime: None,
             first_fetch: true,
+            was_direct: false,
+            was_live: false,
+            sessions_revision: 0,
+            data_requests: RequestChannel::new(),
+
+            motion: HashMap::new(),
+            motion_tracker: gps::Tracker::default(),
+            map: map::MapView::new(),
+            map_hover: None,
+            geofences: geofence::GeofencePanel::new(),
         }
     }
 
-    /// Function to issue request and handle response from tcp server
-    pub fn load_data(&mut self) {
-        let current_session_string = unsafe { (*self.current_session).clone() };
+    /// Cancel the pending fetch and drop all buffered data.
+    ///
+    /// Display preferences such as the theme and graph selection are kept.
+    pub fn reset(&mut self) -> () {
+        self.data_requests.cancel();
+
+        self.datapoints.clear();
+        self.table_data.clear();
+        self.motion.clear();
+        self.motion_tracker = gps::Tracker::default();
+        self.current_page = 0;
+        self.loaded = false;
+        self.formatted = false;
+        self.prev_session.clear();
+        self.last_refresh = 0.0;
+        self.last_row = 0;
+        self.last_datetime = None;
+        self.first_fetch = true;
+        self.was_direct = false;
+        self.was_live = false;
+        self.sessions_revision = 0;
+        self.geofences.reset();
+    }
+
+    /// Drop everything held for the current session so the next load fetches it from scratch
+    fn forget_session(&mut self) -> () {
+        self.loaded = false;
+        self.first_fetch = true;
+        self.last_datetime = None;
+        self.last_row = 0;
+        self.datapoints.clear();
+        self.table_data.clear();
+        self.motion.clear();
+        self.motion_tracker = gps::Tracker::default();
+        self.current_page = 0;
+        self.map.reset_view();
+        self.geofences.forget_session();
+
+        // Anything still in flight belongs to the old data
+        self.data_requests.cancel();
+    }
+
+    /// Function to issue request to the backend. The response is picked up by `receive_data`
+    pub fn load_data(&mut self, ctx: &eframe::egui::Context, store: &AppStore) {
+        let current_session_string = store.state().current_session.clone();
 
         // Clear flags and saved values for new session
         if current_session_string != self.prev_session {
             self.prev_session = current_session_string.clone();
-            self.loaded = false;
-            self.first_fetch = true;
-            self.last_datetime = None; 
-            self.last_row = 0;   
-            self.datapoints.clear();  
-            self.table_data.clear(); 
+            self.forget_session();
+        }
+
+        // Wait for the previous refresh to land before asking for more
+        else if self.data_requests.is_pending() {
+            return;
+        }
+
+        // Nothing to load until a session is picked
+        if current_session_string.is_empty() {
+            return;
         }
     
         self.loaded = true; 
 
         let current_datetime_string = self.last_datetime.clone().unwrap_or("2025-01-01T00:00:00.000".to_string());
     
-        // Used to get data out of async blocks 
-        let formatted_ptr: *mut bool = &mut self.formatted;
-        let data_ptr: *mut Vec<Row2> = &mut self.datapoints;
-        let last_datetime_ptr: *mut Option<String> = &mut self.last_datetime;
-    
-        let client = client::get_client();
+        let token = match &store.state().token {
+            Some(token) => token.clone(),
+            None => return,
+        };
+        let responder = self.data_requests.issue(ctx);
+        let backend = store.backend();
     
         // Only runs on the first fetch when loading a session
         if self.first_fetch {
+            let request = backend.datapoints_by_session(&token, &current_session_string);
+
             wasm_bindgen_futures::spawn_local(async move {
-                let (status, val) =
-                    session_sensor_data::view_datapoints_by_session_id(&client, &current_session_string).await;
-    
-                if status == 200 {
-                    web_sys::console::log_1(&format!("First data loaded. Status: {}", status).into());
-    
-                    if let Some(val) = val {
-                        match serde_json::from_value::<DataResponse>(val) {
-                            Ok(parsed) => {
-                                let datapoints = parsed.datapoints;
-                                let last_datetiime = datapoints.last().map(|row| row.datetime.clone());
-                                unsafe {
-                                    *data_ptr = datapoints;
-                                    *last_datetime_ptr = last_datetiime;
-                                    *formatted_ptr = true;
-                                }
-                            }
-                            Err(e) => {
-                                web_sys::console::log_1(&format!("Failed to parse data response: {}", e).into(),);
-                            }
-                        }
-                    }
-                } else {
-                    web_sys::console::log_1( &format!("Data fetch failed. Status: {}", status).into());
-                }
+                responder.respond(request.await.map(DataUpdate::Replace));
             });
         } 
 
         // Refreshes to fetch new data
         else {
+            let request = backend.datapoints_since(&token, &current_session_string, &current_datetime_string);
+
             wasm_bindgen_futures::spawn_local(async move {
-                let (status, val) = session_sensor_data::view_all_datapoints_by_id_datetime(&client, &current_session_string, &current_datetime_string,).await;
-            
-                if status == 200 {
-                    web_sys::console::log_1(&format!("Data loaded. Status: {}", status).into());
-            
-                    if let Some(val) = val {
-                        match serde_json::from_value::<DataResponse>(val) {
-                            Ok(parsed) => {
-                                let new_datapoints = parsed.datapoints;
-                                unsafe {
-                                    (*data_ptr).extend(new_datapoints);
-                                    *last_datetime_ptr = (*data_ptr).last().map(|row| row.datetime.clone());
-                                    *formatted_ptr = true;
-                                }
-                            }
-                            Err(e) => {
-                                web_sys::console::log_1(&format!("Failed to parse data response: {}", e).into());
-                            }
-                        }
-                    }
-                } else {
-                    web_sys::console::log_1(&format!("Data fetch failed. Status: {}", status).into());
-                }
+                responder.respond(request.await.map(DataUpdate::Append));
             });
         }
     
         self.first_fetch = false;
     }
 
+    /// Function to take a finished response off the request channel and store its datapoints
+    fn receive_data(&mut self, store: &AppStore) {
+        match self.data_requests.poll() {
+            Some(Ok(DataUpdate::Replace(datapoints))) => {
+                web_sys::console::log_1(&"First data loaded.".into());
+                self.datapoints = datapoints;
+            }
+            Some(Ok(DataUpdate::Append(datapoints))) => {
+                web_sys::console::log_1(&"Data loaded.".into());
+                self.datapoints.extend(datapoints);
+            }
+            Some(Err(e)) => {
+                store.report_error("Data fetch", &e);
+                return;
+            }
+            None => return,
+        }
+
+        self.last_datetime = self.datapoints.last().map(|row| row.datetime.clone());
+        self.formatted = true;
+    }
+
+    /// Append datapoints that came over the direct link, skipping any a fetch already returned
+    fn receive_direct(&mut self, link: &DeviceLink, current_session: &str) {
+        let session_id = match current_session.parse::<i64>() {
+            Ok(session_id) => session_id,
+            Err(_) => return,
+        };
+
+        let last_datetime = self.last_datetime.clone().unwrap_or_default();
+        let datapoints: Vec<Datapoint> = link.take_datapoints(session_id).into_iter()
+            .filter(|d| d.datetime > last_datetime)
+            .collect();
+        if datapoints.is_empty() {
+            return;
+        }
+
+        self.datapoints.extend(datapoints);
+        self.last_datetime = self.datapoints.last().map(|row| row.datetime.clone());
+        self.formatted = true;
+    }
+
     /// Function to format the JSON response and insert it into table data
     pub fn format_data(&mut self) {
         web_sys::console::log_1(&format!("Formatting").into());
@@ -258,6 +360,8 @@ impl DataWindow {
         if self.last_row > self.datapoints.len() {
             self.last_row = 0;
             self.table_data.clear();
+            self.motion.clear();
+            self.motion_tracker = gps::Tracker::default();
         }
 
         // Only gather new datapoints to format 
@@ -269,27 +373,11 @@ impl DataWindow {
         }
 
         // Iterate over new datapoints as Blobs and push to table data
+        let first_new = self.table_data.len();
         for (i, row) in new_datapoints.iter().enumerate() {
-            match serde_json::from_value::<Blob>(row.data_blob.clone()) {
-                Ok(parsed) => {
-                    self.table_data.push(Row {
-                        id: (self.last_row + i) as u32,         // Used for readability since the actual id will always be the same
-                        timestamp: row.datetime.clone(),
-                        latitude: parsed.lat,
-                        longitude: parsed.lon,
-                        altitude: parsed.alt,
-                        accel_x: parsed.accel_x,
-                        accel_y: parsed.accel_y,
-                        accel_z: parsed.accel_z,
-                        gyro_x: parsed.gyro_x,
-                        gyro_y: parsed.gyro_y,
-                        gyro_z: parsed.gyro_z,
-                        dac_1: parsed.dac_1,
-                        dac_2: parsed.dac_2,
-                        dac_3: parsed.dac_3,
-                        dac_4: parsed.dac_4,
-                    });
-                }
+            // Used for readability since the actual id will always be the same
+            match Row::from_datapoint((self.last_row + i) as u32, row) {
+                Ok(parsed) => self.table_data.push(parsed),
                 Err(e) => {
                     web_sys::console::log_1(&format!("Failed to format data: {}", e).into());
                 }
@@ -299,6 +387,11 @@ impl DataWindow {
         // Save last row for performance
         self.last_row = self.datapoints.len();
 
+        // Speed and distance carry on from the last row derived
+        for row in &self.table_data[first_new..] {
+            self.motion.insert(row.id, self.motion_tracker.push(row));
+        }
+
         // Change back 
         if self.direction == false {
             self.table_data.reverse();
@@ -306,7 +399,8 @@ impl DataWindow {
     }
 
     /// Draw the data window
-    pub fn draw(&mut self, ctx: &eframe::egui::Context, ) -> () {
+    pub fn draw(&mut self, ctx: &eframe::egui::Context, store: &AppStore) -> () {
+        let current_session = &store.state().current_session;
 
         // Get current time from window
         let current_time = match window() {
@@ -317,18 +411,51 @@ impl DataWindow {
             None => 0.0,
         };
 
-        // Load data every 1 second OR every time current session changes
-        if current_time - self.last_refresh >= 1000.0 || !self.loaded {
-            self.load_data();
+        // Sessions were trimmed, split or merged, so fetch the current one again from scratch
+        if store.state().sessions_revision != self.sessions_revision {
+            self.sessions_revision = store.state().sessions_revision;
+            self.forget_session();
+        }
+
+        // Only recording sessions get new data. Finished ones are fetched once when selected,
+        //     and a session that just finished gets one last fetch for its tail.
+        let session_state = store.state().session_state(current_session);
+        let session_changed = *current_session != self.prev_session;
+        let live = session_state == Some(SessionState::Recording);
+        let just_finished = !session_changed && self.was_live && session_state == Some(SessionState::Finished);
+        self.was_live = live;
+
+        // While the direct link is up new datapoints arrive through it instead of polling.
+        //     One fetch when it comes up fills any gap since the last poll.
+        let link = store.link();
+        let direct = link.mode() == ConnectionType::Direct;
+        let link_opened = live && direct && !self.was_direct;
+        self.was_direct = direct;
+
+        // Load data every 1 second while live OR every time current session changes
+        let poll_due = live && !direct && current_time - self.last_refresh >= 1000.0;
+        if poll_due || link_opened || just_finished || !self.loaded || session_changed {
+            self.load_data(ctx, store);
             self.last_refresh = current_time;
         }
 
+        // Collect any response that arrived since the last frame
+        self.receive_data(store);
+
+        // Hold direct datapoints until any fetch has landed so they append in order
+        if live && direct && !self.first_fetch && !self.data_requests.is_pending() {
+            self.receive_direct(&link, current_session);
+        }
+
         // Format data if unformatted
         if self.formatted {
             self.format_data();
             self.formatted = false;
         }
 
+        // Geofence crossings follow the data as it arrives, whatever is on screen
+        self.geofences.update(ctx, store, &self.table_data, !self.direction, live);
+
         ctx.request_repaint();
 
         eframe::egui::Window::new("Data Window")
@@ -337,8 +464,26 @@ impl DataWindow {
         .movable(!self.fullscreen)
         .show(ctx, |ui| {
 
-            unsafe {
-                ui.label( format!("Current session: {}", *self.current_session));
+            let state_text = match session_state {
+                Some(SessionState::Recording) => " (live)",
+                Some(SessionState::Finished) => " (finished)",
+                None => "",
+            };
+            ui.label( format!("Current session: {}{}", current_session, state_text));
+
+            // Session totals from the GPS track
+            let summary = self.motion_tracker.summary();
+            if summary.fixes > 1 {
+                ui.label(format!(
+                    "Distance: {:.2} km   Moving: {}   Stopped: {}   Avg moving speed: {:.2} m/s   Max speed: {:.2} m/s   Elevation: +{:.1} m / -{:.1} m",
+                    summary.distance / 1000.0,
+                    time::format_duration(summary.moving_secs),
+                    time::format_duration(summary.stopped_secs),
+                    summary.average_moving_speed,
+                    summary.max_speed,
+                    summary.elevation_gain,
+                    summary.elevation_loss,
+                ));
             }
 
             // Set fullscreen size
@@ -388,6 +533,7 @@ impl DataWindow {
                             .selected_text(match self.dropdown {
                                 Selection::SensorData => "All Data",
                                 Selection::LocData => "Location Data",
+                                Selection::MotionData => "Motion Data",
                                 Selection::AccelData => "Acceleration Data",
                                 Selection::GyroData => "Gyroscopic Data",
                                 Selection::DacData => "Dac Data",
@@ -395,6 +541,7 @@ impl DataWindow {
                             .show_ui(ui, |ui| {
                                 ui.selectable_value(&mut self.dropdown, Selection::SensorData, "All Data");
                                 ui.selectable_value(&mut self.dropdown, Selection::LocData, "Location Data");
+                                ui.selectable_value(&mut self.dropdown, Selection::MotionData, "Motion Data");
                                 ui.selectable_value(&mut self.dropdown, Selection::AccelData, "Acceleration Data");
                                 ui.selectable_value(&mut self.dropdown, Selection::GyroData, "Gyroscopic Data");
                                 ui.selectable_value(&mut self.dropdown, Selection::DacData, "Dac Data");
@@ -488,6 +635,16 @@ impl DataWindow {
                         }
                     }
     
+                    // Rows in time order whatever the table sort, for the profile and map
+                    let mut track: Vec<&Row> = self.table_data.iter().collect();
+                    if self.direction == false {
+                        track.reverse();
+                    }
+
+                    // Samples hovered on the map last frame and on the profile this frame
+                    let map_hover = self.map_hover.take();
+                    let mut profile_hover = None;
+
                     // Table drawing for each data type
                     match self.dropdown {
                         Selection::SensorData => {
@@ -559,6 +716,104 @@ impl DataWindow {
                                     });
                                 ui.separator();  
                             }
+
+                            // Profile drawing
+                            if show_graph == true {
+                                ui.add_space(10.0);
+                                ui.heading("Location Profile:");
+                                profile_hover = profile::show(ui, &track, &self.motion, map_hover);
+                            }
+                        }
+                        Selection::MotionData => {
+                            if show_table == true {
+                                ui.heading("Sensor Data:");
+                                let headers = ["id", "timestamp", "speed (m/s)", "heading (deg)", "distance (m)", "elevation gain (m)", "elevation loss (m)", "moving"];
+                                TableBuilder::new(ui)
+                                    .striped(true)
+                                    .resizable(true)
+                                    .columns(Column::auto(), headers.len())
+                                    .header(30.0, |mut header| {
+                                        for h in headers {
+                                            header.col(|ui| {
+                                                ui.heading(h);
+                                            });
+                                        }
+                                    })
+                                    .body(|mut body| {
+                                        let start_row = self.current_page * 10;
+                                        let end_row = (start_row + 10).min(self.table_data.len());
+                                        for r in &self.table_data[start_row..end_row] {
+                                            let k = self.motion.get(&r.id).copied().unwrap_or_default();
+                                            let optional = |value: Option<f64>| value.map(|v| format!("{:.2}", v)).unwrap_or_else(|| "-".to_string());
+                                            body.row(20.0, |mut row_ui| {
+                                                row_ui.col(|ui| { ui.label(r.id.to_string()); });
+                                                row_ui.col(|ui| { ui.label(format!("{:.24}", r.timestamp.clone())); });
+                                                row_ui.col(|ui| { ui.label(optional(k.speed)); });
+                                                row_ui.col(|ui| { ui.label(optional(k.heading)); });
+                                                row_ui.col(|ui| { ui.label(format!("{:.1}", k.distance)); });
+                                                row_ui.col(|ui| { ui.label(format!("{:.1}", k.elevation_gain)); });
+                                                row_ui.col(|ui| { ui.label(format!("{:.1}", k.elevation_loss)); });
+                                                row_ui.col(|ui| { ui.label(if k.moving { "yes" } else { "no" }); });
+                                            });
+                                        }  
+                                    });
+                                ui.separator();  
+                            }
+
+                            // Graph drawing
+                            if show_graph == true {
+                                ui.add_space(10.0);
+                                ui.heading("Sensor Graph:");
+
+                                let speed: PlotPoints = self.table_data.iter().enumerate()
+                                    .filter_map(|(i, row)| self.motion.get(&row.id)?.speed.map(|v| [(i + 1) as f64, v])).collect();
+
+                                let heading: PlotPoints = self.table_data.iter().enumerate()
+                                    .filter_map(|(i, row)| self.motion.get(&row.id)?.heading.map(|v| [(i + 1) as f64, v])).collect();
+
+                                Plot::new("speed_graph")
+                                    .legend(Legend::default())
+                                    .x_axis_label("ID")
+                                    .y_axis_label("Speed (m/s)")
+                                    .width(800.0)
+                                    .height(300.0)
+                                    .show(ui, |ui| {
+                                        ui.line(Line::new(speed).name("Speed").color(egui::Color32::RED));
+                                    });
+
+                                Plot::new("heading_graph")
+                                    .legend(Legend::default())
+                                    .x_axis_label("ID")
+                                    .y_axis_label("Heading (deg)")
+                                    .include_y(0.0)
+                                    .include_y(360.0)
+                                    .width(800.0)
+                                    .height(300.0)
+                                    .show(ui, |ui| {
+                                        ui.points(Points::new(heading).name("Heading").color(egui::Color32::BLUE).radius(1.5));
+                                    });
+
+                                let distance: PlotPoints = self.table_data.iter().enumerate()
+                                    .filter_map(|(i, row)| self.motion.get(&row.id).map(|k| [(i + 1) as f64, k.distance])).collect();
+
+                                let gain: PlotPoints = self.table_data.iter().enumerate()
+                                    .filter_map(|(i, row)| self.motion.get(&row.id).map(|k| [(i + 1) as f64, k.elevation_gain])).collect();
+
+                                let loss: PlotPoints = self.table_data.iter().enumerate()
+                                    .filter_map(|(i, row)| self.motion.get(&row.id).map(|k| [(i + 1) as f64, k.elevation_loss])).collect();
+
+                                Plot::new("distance_graph")
+                                    .legend(Legend::default())
+                                    .x_axis_label("ID")
+                                    .y_axis_label("Metres")
+                                    .width(800.0)
+                                    .height(300.0)
+                                    .show(ui, |ui| {
+                                        ui.line(Line::new(distance).name("Distance").color(egui::Color32::GREEN));
+                                        ui.line(Line::new(gain).name("Elevation gain").color(egui::Color32::RED));
+                                        ui.line(Line::new(loss).name("Elevation loss").color(egui::Color32::BLUE));
+                                    });
+                            }
                         }
                         Selection::AccelData => {
                             if show_table == true {
@@ -781,6 +1036,21 @@ impl DataWindow {
                             }    
                         }
                     }
+
+                    // Map drawing, the same for every data type
+                    if show_map == true {
+                        ui.add_space(10.0);
+                        ui.heading("Map:");
+
+                        let marked = profile_hover.and_then(|id| track.iter().position(|row| row.id == id));
+                        let response = self.map.show(ui, &track, &self.motion, live, marked, &self.geofences.overlays());
+                        self.map_hover = response.hovered.map(|i| track[i].id);
+                        if let Some((latitude, longitude)) = response.clicked {
+                            self.geofences.click(latitude, longitude);
+                        }
+
+                        self.geofences.show(ui, store);
+                    }
                 });        
         });
     }
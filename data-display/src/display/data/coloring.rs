@@ -0,0 +1,278 @@
+//! Track coloring by sensor channel for the map view
+//!
+//! Each segment of the track takes the color of the highest value among the
+//!     samples it covers, so spikes in a channel show up where on the route
+//!     they happened even when the track is zoomed out.
+//!
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use eframe::egui::{Align2, Color32, ComboBox, FontId, Painter, Pos2, Rect, Stroke, Vec2};
+
+use super::gps::Kinematics;
+use super::Row;
+
+/// Steps the legend's color bar is drawn in
+const LEGEND_STEPS: usize = 48;
+/// Color of segments with no value, e.g. speed before the second fix
+pub const NO_VALUE_COLOR: Color32 = Color32::from_gray(128);
+
+/// Channels a track can be colored by
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColorChannel {
+    Solid,
+    Altitude,
+    AccelMagnitude,
+    GyroRate,
+    Dac1,
+    Dac2,
+    Dac3,
+    Dac4,
+    Speed,
+}
+
+impl ColorChannel {
+    const ALL: [ColorChannel; 9] = [
+        ColorChannel::Solid,
+        ColorChannel::Altitude,
+        ColorChannel::AccelMagnitude,
+        ColorChannel::GyroRate,
+        ColorChannel::Dac1,
+        ColorChannel::Dac2,
+        ColorChannel::Dac3,
+        ColorChannel::Dac4,
+        ColorChannel::Speed,
+    ];
+
+    fn label(&self) -> &'static str {
+        match self {
+            ColorChannel::Solid => "Single color",
+            ColorChannel::Altitude => "Altitude (m)",
+            ColorChannel::AccelMagnitude => "Acceleration magnitude",
+            ColorChannel::GyroRate => "Gyro rate",
+            ColorChannel::Dac1 => "Dac 1",
+            ColorChannel::Dac2 => "Dac 2",
+            ColorChannel::Dac3 => "Dac 3",
+            ColorChannel::Dac4 => "Dac 4",
+            ColorChannel::Speed => "Speed (m/s)",
+        }
+    }
+
+    /// Value of the channel at each row, None where it cannot be worked out.
+    ///     Speed is looked up in `motion`, the kinematics already derived for each row id.
+    fn values(&self, rows: &[&Row], motion: &HashMap<u32, Kinematics>) -> Vec<Option<f64>> {
+        if *self == ColorChannel::Speed {
+            return rows.iter().map(|row| motion.get(&row.id).and_then(|kinematics| kinematics.speed)).collect();
+        }
+
+        let value = |row: &Row| -> f64 {
+            match self {
+                ColorChannel::Solid | ColorChannel::Speed => 0.0,
+                ColorChannel::Altitude => row.altitude,
+                ColorChannel::AccelMagnitude => (row.accel_x.powi(2) + row.accel_y.powi(2) + row.accel_z.powi(2)).sqrt(),
+                ColorChannel::GyroRate => (row.gyro_x.powi(2) + row.gyro_y.powi(2) + row.gyro_z.powi(2)).sqrt(),
+                ColorChannel::Dac1 => row.dac_1,
+                ColorChannel::Dac2 => row.dac_2,
+                ColorChannel::Dac3 => row.dac_3,
+                ColorChannel::Dac4 => row.dac_4,
+            }
+        };
+
+        rows.iter().map(|row| Some(value(row)).filter(|v| v.is_finite())).collect()
+    }
+}
+
+/// Gradients a channel's values are mapped onto
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColorScale {
+    Viridis,
+    Turbo,
+    Heat,
+    BlueRed,
+}
+
+impl ColorScale {
+    const ALL: [ColorScale; 4] = [ColorScale::Viridis, ColorScale::Turbo, ColorScale::Heat, ColorScale::BlueRed];
+
+    fn label(&self) -> &'static str {
+        match self {
+            ColorScale::Viridis => "Viridis",
+            ColorScale::Turbo => "Turbo",
+            ColorScale::Heat => "Heat",
+            ColorScale::BlueRed => "Blue-red",
+        }
+    }
+
+    /// Evenly spaced colors from the low end of the scale to the high end
+    fn stops(&self) -> &'static [(u8, u8, u8)] {
+        match self {
+            ColorScale::Viridis => &[(68, 1, 84), (59, 82, 139), (33, 145, 140), (94, 201, 98), (253, 231, 37)],
+            ColorScale::Turbo => &[(48, 18, 59), (70, 134, 251), (26, 228, 182), (164, 252, 60), (251, 185, 56), (228, 70, 10), (122, 4, 3)],
+            ColorScale::Heat => &[(0, 0, 0), (180, 0, 0), (255, 120, 0), (255, 230, 60), (255, 255, 255)],
+            ColorScale::BlueRed => &[(33, 102, 172), (146, 197, 222), (247, 247, 247), (244, 165, 130), (178, 24, 43)],
+        }
+    }
+
+    /// Color at `t`, 0 for the low end and 1 for the high end
+    fn color(&self, t: f64) -> Color32 {
+        let stops = self.stops();
+        let position = t.clamp(0.0, 1.0) * (stops.len() - 1) as f64;
+        let i = (position.floor() as usize).min(stops.len() - 2);
+        let f = position - i as f64;
+
+        let mix = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * f).round() as u8;
+        let (low, high) = (stops[i], stops[i + 1]);
+        Color32::from_rgb(mix(low.0, high.0), mix(low.1, high.1), mix(low.2, high.2))
+    }
+}
+
+/// Channel values for each row of a track, along with the range the scale spans
+pub struct TrackColors {
+    values: Vec<Option<f64>>,
+    range: (f64, f64),
+    scale: ColorScale,
+}
+
+impl TrackColors {
+    /// Color of a segment covering `rows`, taken from the highest value among them
+    pub fn segment_color(&self, rows: Range<usize>) -> Color32 {
+        let (min, max) = self.range;
+        let peak = self.values[rows].iter()
+            .flatten()
+            .fold(None, |peak: Option<f64>, v| Some(peak.map_or(*v, |peak| peak.max(*v))));
+
+        match peak {
+            // A flat channel sits in the middle of the scale
+            Some(v) if max > min => self.scale.color((v - min) / (max - min)),
+            Some(_) => self.scale.color(0.5),
+            None => NO_VALUE_COLOR,
+        }
+    }
+}
+
+/// Channel and color scale picked for the track
+pub struct TrackColoring {
+    channel: ColorChannel,
+    scale: ColorScale,
+}
+
+impl TrackColoring {
+    pub fn new() -> Self {
+        TrackColoring {
+            channel: ColorChannel::Solid,
+            scale: ColorScale::Viridis,
+        }
+    }
+
+    /// Draw the channel and color scale pickers
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui) -> () {
+        ui.label("Color by:");
+        ComboBox::from_id_salt("map_color_channel")
+            .selected_text(self.channel.label())
+            .show_ui(ui, |ui| {
+                for channel in ColorChannel::ALL {
+                    ui.selectable_value(&mut self.channel, channel, channel.label());
+                }
+            });
+
+        if self.channel != ColorChannel::Solid {
+            ComboBox::from_id_salt("map_color_scale")
+                .selected_text(self.scale.label())
+                .show_ui(ui, |ui| {
+                    for scale in ColorScale::ALL {
+                        ui.selectable_value(&mut self.scale, scale, scale.label());
+                    }
+                });
+        }
+    }
+
+    /// Value of each row, scaled between the lowest and highest value in the track.
+    ///     None when the track is drawn in a single color.
+    pub fn colors(&self, rows: &[&Row], motion: &HashMap<u32, Kinematics>) -> Option<TrackColors> {
+        if self.channel == ColorChannel::Solid {
+            return None;
+        }
+
+        let values = self.channel.values(rows, motion);
+        let (min, max) = values.iter()
+            .flatten()
+            .fold((f64::MAX, f64::MIN), |(min, max), v| (min.min(*v), max.max(*v)));
+        let range = if min > max { (0.0, 0.0) } else { (min, max) };
+
+        Some(TrackColors { values, range, scale: self.scale })
+    }
+
+    /// Draw the color bar with the channel's range in the top left of the map
+    pub fn draw_legend(&self, painter: &Painter, rect: Rect, colors: &TrackColors) -> () {
+        let origin = rect.left_top() + Vec2::new(8.0, 8.0);
+        let background = Rect::from_min_size(origin, Vec2::new(176.0, 54.0));
+        painter.rect_filled(background, 4.0, Color32::from_black_alpha(180));
+
+        let text = Color32::WHITE;
+        let font = FontId::proportional(11.0);
+        painter.text(origin + Vec2::new(8.0, 6.0), Align2::LEFT_TOP, self.channel.label(), font.clone(), text);
+
+        let bar = Rect::from_min_size(origin + Vec2::new(8.0, 22.0), Vec2::new(160.0, 10.0));
+        let step = bar.width() / LEGEND_STEPS as f32;
+        for i in 0..LEGEND_STEPS {
+            let min = Pos2::new(bar.left() + i as f32 * step, bar.top());
+            let color = self.scale.color(i as f64 / (LEGEND_STEPS - 1) as f64);
+            painter.rect_filled(Rect::from_min_size(min, Vec2::new(step + 0.5, bar.height())), 0.0, color);
+        }
+        painter.rect_stroke(bar, 0.0, Stroke::new(1.0, Color32::from_gray(200)));
+
+        let (min, max) = colors.range;
+        painter.text(bar.left_bottom() + Vec2::new(0.0, 3.0), Align2::LEFT_TOP, format!("{:.2}", min), font.clone(), text);
+        painter.text(bar.right_bottom() + Vec2::new(0.0, 3.0), Align2::RIGHT_TOP, format!("{:.2}", max), font, text);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    fn coloring(channel: ColorChannel) -> TrackColoring {
+        TrackColoring { channel, scale: ColorScale::Viridis }
+    }
+
+    #[wasm_bindgen_test]
+    fn segments_show_the_peak_they_cover() {
+        let rows: Vec<Row> = [0.0, 0.0, 10.0, 0.0, 5.0].iter()
+            .map(|dac_1| Row { dac_1: *dac_1, ..Default::default() })
+            .collect();
+        let rows: Vec<&Row> = rows.iter().collect();
+        let colors = coloring(ColorChannel::Dac1).colors(&rows, &HashMap::new()).unwrap();
+
+        // The spike at row 2 is between the points drawn but still colors the segment
+        assert_eq!(colors.segment_color(1..4), ColorScale::Viridis.color(1.0));
+        assert_eq!(colors.segment_color(3..5), ColorScale::Viridis.color(0.5));
+        assert_eq!(colors.segment_color(0..1), ColorScale::Viridis.color(0.0));
+    }
+
+    #[wasm_bindgen_test]
+    fn segments_without_values_are_grey() {
+        let rows = [Row::default(), Row::default()];
+        let rows: Vec<&Row> = rows.iter().collect();
+
+        assert!(coloring(ColorChannel::Solid).colors(&rows, &HashMap::new()).is_none());
+        assert_eq!(coloring(ColorChannel::Speed).colors(&rows, &HashMap::new()).unwrap().segment_color(1..2), NO_VALUE_COLOR);
+    }
+
+    #[wasm_bindgen_test]
+    fn speed_comes_from_the_derived_kinematics() {
+        let rows: Vec<Row> = (0..3).map(|id| Row { id, ..Default::default() }).collect();
+        let rows: Vec<&Row> = rows.iter().collect();
+        let motion = HashMap::from([
+            (1, Kinematics { speed: Some(2.0), ..Default::default() }),
+            (2, Kinematics { speed: Some(4.0), ..Default::default() }),
+        ]);
+        let colors = coloring(ColorChannel::Speed).colors(&rows, &motion).unwrap();
+
+        assert_eq!(colors.segment_color(0..1), NO_VALUE_COLOR);
+        assert_eq!(colors.segment_color(1..2), ColorScale::Viridis.color(0.0));
+        assert_eq!(colors.segment_color(2..3), ColorScale::Viridis.color(1.0));
+    }
+}
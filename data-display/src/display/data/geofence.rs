@@ -0,0 +1,790 @@
+//! Geofences drawn on the map, with entry and exit events
+//!
+//! Fences are stored per user on the backend. Crossings are worked out as rows
+//!     arrive, and over the whole track again when the fences change, so finished
+//!     sessions get the same event list and time inside each zone as live ones.
+//!     New crossings in a live session raise an alert for fences that ask for one.
+//!
+
+use eframe::egui::{Color32, DragValue, RichText, ScrollArea};
+
+use crate::backend::{BackendError, FenceShape, Geofence, MAX_FENCE_POINTS, MAX_FENCE_RADIUS_M};
+use crate::time;
+
+use crate::display::request::RequestChannel;
+use crate::display::store::AppStore;
+
+use super::gps::{self, has_fix};
+use super::map::MapOverlay;
+use super::Row;
+
+/// Colors saved fences cycle through on the map
+const FENCE_COLORS: [Color32; 5] = [
+    Color32::from_rgb(186, 85, 211),
+    Color32::from_rgb(0, 191, 255),
+    Color32::from_rgb(255, 99, 71),
+    Color32::from_rgb(60, 179, 113),
+    Color32::from_rgb(255, 215, 0),
+];
+/// Color of the fence being drawn
+const DRAFT_COLOR: Color32 = Color32::WHITE;
+const DEFAULT_RADIUS_M: f64 = 50.0;
+
+/// Whether a position in degrees lies inside a fence.
+///
+/// Polygons are treated as flat in latitude and longitude, which is close
+///     enough for fences a few kilometres across.
+pub fn contains(shape: &FenceShape, latitude: f64, longitude: f64) -> bool {
+    match shape {
+        FenceShape::Circle { latitude: center_lat, longitude: center_lon, radius_m } => {
+            gps::haversine_m(*center_lat, *center_lon, latitude, longitude) <= *radius_m
+        }
+        FenceShape::Polygon { points } => {
+            // Count the edges a ray heading east from the position crosses
+            let mut inside = false;
+            for (i, (lat_i, lon_i)) in points.iter().enumerate() {
+                let (lat_j, lon_j) = points[(i + points.len() - 1) % points.len()];
+                if (*lat_i > latitude) != (lat_j > latitude)
+                    && longitude < (lon_j - lon_i) * (latitude - lat_i) / (lat_j - lat_i) + lon_i
+                {
+                    inside = !inside;
+                }
+            }
+            inside
+        }
+    }
+}
+
+/// Which way a track crossed a fence
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Crossing {
+    Entered,
+    Exited,
+}
+
+/// A track crossing a fence
+#[derive(Clone, Debug)]
+pub struct FenceEvent {
+    pub fence_id: String,
+    pub fence_name: String,
+    pub crossing: Crossing,
+    /// Timestamp of the first sample on the new side
+    pub timestamp: String,
+    pub row_id: u32,
+}
+
+/// How a track used one fence
+#[derive(Clone, Debug)]
+pub struct ZoneTime {
+    /// s
+    pub inside_secs: f64,
+    /// Times the track entered, counting a start inside the fence
+    pub visits: u32,
+}
+
+/// Crossings of every fence along a track and the time spent inside each,
+///     built up as rows are added
+#[derive(Default)]
+struct Analysis {
+    /// In time order
+    events: Vec<FenceEvent>,
+    /// In the same order as the fences
+    zones: Vec<ZoneTime>,
+    /// Which fences the last fix was inside, and its time
+    last_fix: Option<(Vec<bool>, f64)>,
+}
+
+impl Analysis {
+    fn new(fences: &[Geofence]) -> Self {
+        Analysis {
+            events: Vec::new(),
+            zones: vec![ZoneTime { inside_secs: 0.0, visits: 0 }; fences.len()],
+            last_fix: None,
+        }
+    }
+
+    /// Add rows following the ones already added, in time order
+    fn extend<'a>(&mut self, fences: &[Geofence], rows: impl Iterator<Item = &'a Row>) -> () {
+        for row in rows {
+            let ms = match time::parse_datetime(&row.timestamp).filter(|_| has_fix(row)) {
+                Some(ms) => ms,
+                None => continue,
+            };
+            let inside: Vec<bool> = fences.iter().map(|fence| contains(&fence.shape, row.latitude, row.longitude)).collect();
+
+            for (f, fence) in fences.iter().enumerate() {
+                let zone = &mut self.zones[f];
+                match &self.last_fix {
+                    None if inside[f] => zone.visits += 1,
+                    None => {}
+                    Some((was_inside, last_ms)) => {
+                        let was_inside = was_inside[f];
+
+                        // The time between two fixes is counted for the side of the earlier one
+                        let secs = (ms - last_ms) / 1000.0;
+                        if was_inside && secs <= gps::MAX_GAP_SECS {
+                            zone.inside_secs += secs;
+                        }
+
+                        if inside[f] != was_inside {
+                            if inside[f] {
+                                zone.visits += 1;
+                            }
+                            self.events.push(FenceEvent {
+                                fence_id: fence.fence_id.clone(),
+                                fence_name: fence.name.clone(),
+                                crossing: if inside[f] { Crossing::Entered } else { Crossing::Exited },
+                                timestamp: row.timestamp.clone(),
+                                row_id: row.id,
+                            });
+                        }
+                    }
+                }
+            }
+
+            self.last_fix = Some((inside, ms));
+        }
+    }
+}
+
+/// Kinds of fence that can be drawn
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum DrawTool {
+    Circle,
+    Polygon,
+}
+
+/// A fence being drawn or edited
+struct Draft {
+    tool: DrawTool,
+    /// Empty for a new fence
+    fence_id: String,
+    name: String,
+    alert: bool,
+    /// Circle center, or polygon vertices, as (latitude, longitude)
+    points: Vec<(f64, f64)>,
+    radius_m: f64,
+}
+
+impl Draft {
+    fn new(tool: DrawTool) -> Self {
+        Draft {
+            tool,
+            fence_id: String::new(),
+            name: String::new(),
+            alert: false,
+            points: Vec::new(),
+            radius_m: DEFAULT_RADIUS_M,
+        }
+    }
+
+    fn edit(fence: &Geofence) -> Self {
+        let (tool, points, radius_m) = match &fence.shape {
+            FenceShape::Circle { latitude, longitude, radius_m } => (DrawTool::Circle, vec![(*latitude, *longitude)], *radius_m),
+            FenceShape::Polygon { points } => (DrawTool::Polygon, points.clone(), DEFAULT_RADIUS_M),
+        };
+
+        Draft {
+            tool,
+            fence_id: fence.fence_id.clone(),
+            name: fence.name.clone(),
+            alert: fence.alert,
+            points,
+            radius_m,
+        }
+    }
+
+    /// Outline so far. None for a circle without a center.
+    fn shape(&self) -> Option<FenceShape> {
+        match self.tool {
+            DrawTool::Circle => self.points.first().map(|(latitude, longitude)| FenceShape::Circle {
+                latitude: *latitude,
+                longitude: *longitude,
+                radius_m: self.radius_m,
+            }),
+            DrawTool::Polygon => Some(FenceShape::Polygon { points: self.points.clone() }),
+        }
+    }
+
+    fn to_fence(&self) -> Option<Geofence> {
+        self.shape().map(|shape| Geofence {
+            fence_id: self.fence_id.clone(),
+            name: self.name.trim().to_string(),
+            shape,
+            alert: self.alert,
+        })
+    }
+}
+
+/// Draws, saves and lists the user's geofences, and tracks the current session's crossings
+pub struct GeofencePanel {
+    fences: Vec<Geofence>,
+    loaded: bool,
+    /// Bumped whenever `fences` changes so crossings are worked out again
+    fences_revision: u64,
+
+    draft: Option<Draft>,
+    /// Fence awaiting a second click before it is deleted
+    confirm_delete: Option<String>,
+    /// Outcome of the last change, and whether it succeeded
+    message: Option<(bool, String)>,
+
+    analysis: Analysis,
+    /// Row count, newest row id and fence revision `analysis` covers
+    analysed: Option<(usize, Option<u32>, u64)>,
+    /// Newest row checked for alerts. None until the session's first rows arrive,
+    ///     so crossings already recorded when it is opened do not alert.
+    alerted_up_to: Option<u32>,
+    /// Alerts not dismissed yet
+    alerts: Vec<FenceEvent>,
+
+    /// Results of fence list fetches
+    list_requests: RequestChannel<Result<Vec<Geofence>, BackendError>>,
+    /// Results of saving a fence
+    save_requests: RequestChannel<Result<Geofence, BackendError>>,
+    /// Results of deleting a fence, tagged with its id
+    delete_requests: RequestChannel<(String, Result<(), BackendError>)>,
+}
+
+impl GeofencePanel {
+    pub fn new() -> Self {
+        GeofencePanel {
+            fences: Vec::new(),
+            loaded: false,
+            fences_revision: 0,
+
+            draft: None,
+            confirm_delete: None,
+            message: None,
+
+            analysis: Analysis::default(),
+            analysed: None,
+            alerted_up_to: None,
+            alerts: Vec::new(),
+
+            list_requests: RequestChannel::new(),
+            save_requests: RequestChannel::new(),
+            delete_requests: RequestChannel::new(),
+        }
+    }
+
+    /// Cancel pending requests and forget the previous user's fences
+    pub fn reset(&mut self) -> () {
+        self.list_requests.cancel();
+        self.save_requests.cancel();
+        self.delete_requests.cancel();
+
+        self.fences.clear();
+        self.loaded = false;
+        self.fences_revision += 1;
+        self.draft = None;
+        self.confirm_delete = None;
+        self.message = None;
+        self.forget_session();
+    }
+
+    /// Drop the crossings of the previous session. Fences are kept.
+    pub fn forget_session(&mut self) -> () {
+        self.analysis = Analysis::default();
+        self.analysed = None;
+        self.alerted_up_to = None;
+        self.alerts.clear();
+    }
+
+    /// Request the fence list. The response is picked up by `receive`
+    fn load_fences(&mut self, ctx: &eframe::egui::Context, store: &AppStore) -> () {
+        let token = match &store.state().token {
+            Some(token) => token.clone(),
+            None => return,
+        };
+
+        self.loaded = true;
+        let responder = self.list_requests.issue(ctx);
+        let request = store.backend().geofences(&token);
+
+        wasm_bindgen_futures::spawn_local(async move {
+            responder.respond(request.await);
+        });
+    }
+
+    fn save_fence(&mut self, ctx: &eframe::egui::Context, store: &AppStore, fence: Geofence) -> () {
+        let token = match &store.state().token {
+            Some(token) => token.clone(),
+            None => return,
+        };
+
+        self.message = None;
+        let responder = self.save_requests.issue(ctx);
+        let request = store.backend().save_geofence(&token, &fence);
+
+        wasm_bindgen_futures::spawn_local(async move {
+            responder.respond(request.await);
+        });
+    }
+
+    fn delete_fence(&mut self, ctx: &eframe::egui::Context, store: &AppStore, fence_id: &str) -> () {
+        let token = match &store.state().token {
+            Some(token) => token.clone(),
+            None => return,
+        };
+
+        self.message = None;
+        let fence_id = fence_id.to_string();
+        let responder = self.delete_requests.issue(ctx);
+        let request = store.backend().delete_geofence(&token, &fence_id);
+
+        wasm_bindgen_futures::spawn_local(async move {
+            responder.respond((fence_id, request.await));
+        });
+    }
+
+    /// Apply any responses that have arrived
+    fn receive(&mut self, store: &AppStore) -> () {
+        match self.list_requests.poll() {
+            Some(Ok(fences)) => {
+                self.fences = fences;
+                self.fences_revision += 1;
+            }
+            Some(Err(e)) => {
+                store.report_error("Geofence fetch", &e);
+                self.message = Some((false, format!("Could not load geofences. {}", e)));
+            }
+            None => {}
+        }
+
+        match self.save_requests.poll() {
+            Some(Ok(saved)) => {
+                self.message = Some((true, format!("Saved \"{}\".", saved.name)));
+                match self.fences.iter_mut().find(|fence| fence.fence_id == saved.fence_id) {
+                    Some(fence) => *fence = saved,
+                    None => self.fences.push(saved),
+                }
+                self.fences_revision += 1;
+                self.draft = None;
+            }
+            Some(Err(e)) => {
+                store.report_error("Geofence save", &e);
+                self.message = Some((false, match e {
+                    BackendError::Status(422) => "The server rejected the fence.".to_string(),
+                    BackendError::Status(404) => "The fence no longer exists.".to_string(),
+                    e => format!("Could not save the fence. {}", e),
+                }));
+            }
+            None => {}
+        }
+
+        match self.delete_requests.poll() {
+            Some((fence_id, Ok(()))) => {
+                self.message = Some((true, "Fence deleted.".to_string()));
+                self.fences.retain(|fence| fence.fence_id != fence_id);
+                self.fences_revision += 1;
+            }
+            Some((_, Err(e))) => {
+                store.report_error("Geofence deletion", &e);
+                self.message = Some((false, format!("Could not delete the fence. {}", e)));
+            }
+            None => {}
+        }
+    }
+
+    /// Fetch the fences if needed, work out crossings for new rows and raise alerts.
+    ///     Called every frame with the current session's rows, newest first if `newest_first`.
+    pub fn update(&mut self, ctx: &eframe::egui::Context, store: &AppStore, rows: &[Row], newest_first: bool, live: bool) -> () {
+        if !self.loaded {
+            self.load_fences(ctx, store);
+        }
+        self.receive(store);
+
+        // Row `i` in time order
+        let row_at = |i: usize| if newest_first { &rows[rows.len() - 1 - i] } else { &rows[i] };
+        let newest = rows.len().checked_sub(1).map(|i| row_at(i).id);
+        let key = (rows.len(), newest, self.fences_revision);
+        if self.analysed != Some(key) {
+            // Rows are normally only appended. Anything else, or new fences, starts over.
+            let from = match self.analysed {
+                Some((count, last, revision)) if revision == self.fences_revision
+                    && count <= rows.len()
+                    && count.checked_sub(1).map(|i| row_at(i).id) == last => count,
+                _ => {
+                    self.analysis = Analysis::new(&self.fences);
+                    0
+                }
+            };
+            let known_events = self.analysis.events.len();
+            self.analysis.extend(&self.fences, (from..rows.len()).map(row_at));
+            self.analysed = Some(key);
+
+            // Only crossings in rows that arrived while watching a live session alert
+            if let (true, Some(checked)) = (live, self.alerted_up_to) {
+                for event in &self.analysis.events[known_events..] {
+                    let new = event.row_id > checked;
+                    let alert = self.fences.iter().any(|fence| fence.fence_id == event.fence_id && fence.alert);
+                    if new && alert {
+                        web_sys::console::log_1(&format!("Geofence alert: {:?} {} at {}", event.crossing, event.fence_name, event.timestamp).into());
+                        self.alerts.push(event.clone());
+                    }
+                }
+            }
+            if newest.is_some() {
+                self.alerted_up_to = newest;
+            }
+        }
+
+        self.draw_alerts(ctx);
+    }
+
+    /// Window listing alerts until they are dismissed
+    fn draw_alerts(&mut self, ctx: &eframe::egui::Context) -> () {
+        if self.alerts.is_empty() {
+            return;
+        }
+
+        let mut dismissed = false;
+        eframe::egui::Window::new("Geofence Alerts")
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                for event in &self.alerts {
+                    let verb = match event.crossing {
+                        Crossing::Entered => "Entered",
+                        Crossing::Exited => "Left",
+                    };
+                    ui.label(RichText::new(format!("{} \"{}\" at {}", verb, event.fence_name, time::short_datetime(&event.timestamp))).color(Color32::YELLOW));
+                }
+                dismissed = ui.button("Dismiss").clicked();
+            });
+
+        if dismissed {
+            self.alerts.clear();
+        }
+    }
+
+    /// Saved fences and the one being drawn, for the map
+    pub fn overlays(&self) -> Vec<MapOverlay> {
+        let mut overlays: Vec<MapOverlay> = self.fences.iter()
+            .enumerate()
+            // A fence being edited is shown as the draft instead
+            .filter(|(_, fence)| self.draft.as_ref().is_none_or(|draft| draft.fence_id != fence.fence_id))
+            .map(|(i, fence)| MapOverlay {
+                shape: fence.shape.clone(),
+                label: fence.name.clone(),
+                color: FENCE_COLORS[i % FENCE_COLORS.len()],
+                draft: false,
+            })
+            .collect();
+
+        if let Some(shape) = self.draft.as_ref().and_then(|draft| draft.shape()) {
+            overlays.push(MapOverlay { shape, label: String::new(), color: DRAFT_COLOR, draft: true });
+        }
+
+        overlays
+    }
+
+    /// A click on the map places the circle center or the next polygon point while drawing
+    pub fn click(&mut self, latitude: f64, longitude: f64) -> () {
+        if let Some(draft) = &mut self.draft {
+            match draft.tool {
+                DrawTool::Circle => draft.points = vec![(latitude, longitude)],
+                DrawTool::Polygon if draft.points.len() < MAX_FENCE_POINTS => draft.points.push((latitude, longitude)),
+                DrawTool::Polygon => {}
+            }
+        }
+    }
+
+    /// Draw the fence list, the drawing controls and the current session's crossings
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui, store: &AppStore) -> () {
+        ui.add_space(10.0);
+        ui.heading("Geofences:");
+
+        if let Some((success, message)) = &self.message {
+            ui.label(RichText::new(message).color(if *success { Color32::GREEN } else { Color32::RED }));
+        }
+
+        let busy = self.save_requests.is_pending() || self.delete_requests.is_pending();
+        let mut save = None;
+        let mut cancel = false;
+
+        match &mut self.draft {
+            None => {
+                ui.horizontal(|ui| {
+                    if ui.button("Draw circle").clicked() {
+                        self.draft = Some(Draft::new(DrawTool::Circle));
+                    }
+                    if ui.button("Draw polygon").clicked() {
+                        self.draft = Some(Draft::new(DrawTool::Polygon));
+                    }
+                });
+            }
+            Some(draft) => {
+                ui.group(|ui| {
+                    ui.label(match draft.tool {
+                        DrawTool::Circle => "Click the map to place the center",
+                        DrawTool::Polygon => "Click the map to add each corner",
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.label("Name");
+                        ui.text_edit_singleline(&mut draft.name);
+                        ui.checkbox(&mut draft.alert, "Alert on entry and exit");
+                    });
+
+                    match draft.tool {
+                        DrawTool::Circle => {
+                            ui.horizontal(|ui| {
+                                ui.label("Radius (m)");
+                                ui.add(DragValue::new(&mut draft.radius_m).range(1.0..=MAX_FENCE_RADIUS_M).speed(1.0));
+                            });
+                        }
+                        DrawTool::Polygon => {
+                            ui.horizontal(|ui| {
+                                ui.label(format!("{} points", draft.points.len()));
+                                if ui.add_enabled(!draft.points.is_empty(), eframe::egui::Button::new("Undo point")).clicked() {
+                                    draft.points.pop();
+                                }
+                            });
+                        }
+                    }
+
+                    let problems = match draft.to_fence() {
+                        Some(fence) => fence.validate(),
+                        None => vec!["Place the center on the map".to_string()],
+                    };
+                    for problem in &problems {
+                        ui.label(RichText::new(problem).color(Color32::RED).small());
+                    }
+
+                    ui.horizontal(|ui| {
+                        if ui.add_enabled(problems.is_empty() && !busy, eframe::egui::Button::new("Save")).clicked() {
+                            save = draft.to_fence();
+                        }
+                        cancel = ui.button("Cancel").clicked();
+                    });
+                });
+            }
+        }
+
+        if let Some(fence) = save {
+            self.save_fence(ui.ctx(), store, fence);
+        }
+        if cancel {
+            self.draft = None;
+        }
+
+        // Saved fences with how this session used them
+        let mut edit = None;
+        let mut delete = None;
+        if self.fences.is_empty() {
+            ui.label("No geofences");
+        } else {
+            eframe::egui::Grid::new("geofences").striped(true).show(ui, |ui| {
+                ui.label(RichText::new("Name").strong());
+                ui.label(RichText::new("Shape").strong());
+                ui.label(RichText::new("Alert").strong());
+                ui.label(RichText::new("Visits").strong());
+                ui.label(RichText::new("Time Inside").strong());
+                ui.end_row();
+
+                for (i, fence) in self.fences.iter().enumerate() {
+                    ui.label(RichText::new(&fence.name).color(FENCE_COLORS[i % FENCE_COLORS.len()]));
+                    ui.label(match &fence.shape {
+                        FenceShape::Circle { radius_m, .. } => format!("Circle, {:.0} m", radius_m),
+                        FenceShape::Polygon { points } => format!("Polygon, {} points", points.len()),
+                    });
+                    ui.label(if fence.alert { "Yes" } else { "No" });
+
+                    match self.analysis.zones.get(i) {
+                        Some(zone) => {
+                            ui.label(zone.visits.to_string());
+                            ui.label(time::format_duration(zone.inside_secs));
+                        }
+                        None => {
+                            ui.label("-");
+                            ui.label("-");
+                        }
+                    }
+
+                    ui.add_enabled_ui(!busy && self.draft.is_none(), |ui| {
+                        if ui.link("Edit").clicked() {
+                            edit = Some(i);
+                        }
+                    });
+
+                    ui.add_enabled_ui(!busy, |ui| {
+                        if self.confirm_delete.as_deref() == Some(fence.fence_id.as_str()) {
+                            if ui.link(RichText::new("Confirm Delete").color(Color32::RED)).clicked() {
+                                delete = Some(fence.fence_id.clone());
+                            }
+                        } else if ui.link("Delete").clicked() {
+                            self.confirm_delete = Some(fence.fence_id.clone());
+                        }
+                    });
+                    ui.end_row();
+                }
+            });
+        }
+
+        if let Some(i) = edit {
+            self.draft = Some(Draft::edit(&self.fences[i]));
+        }
+        if let Some(fence_id) = delete {
+            self.confirm_delete = None;
+            self.delete_fence(ui.ctx(), store, &fence_id);
+        }
+
+        // Crossings along this session
+        ui.add_space(5.0);
+        ui.label(RichText::new("Events").strong());
+        if self.analysis.events.is_empty() {
+            ui.label("No fence crossings in this session");
+        } else {
+            ScrollArea::vertical().id_salt("geofence_events").max_height(150.0).show(ui, |ui| {
+                eframe::egui::Grid::new("geofence_events_grid").striped(true).show(ui, |ui| {
+                    for event in self.analysis.events.iter().rev() {
+                        ui.label(time::short_datetime(&event.timestamp));
+                        ui.label(match event.crossing {
+                            Crossing::Entered => RichText::new("Entered").color(Color32::GREEN),
+                            Crossing::Exited => RichText::new("Exited").color(Color32::RED),
+                        });
+                        ui.label(&event.fence_name);
+                        ui.end_row();
+                    }
+                });
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+    use crate::display::testing::fix;
+
+    fn analyse(fences: &[Geofence], rows: &[Row]) -> (Vec<FenceEvent>, Vec<ZoneTime>) {
+        let mut analysis = Analysis::new(fences);
+        analysis.extend(fences, rows.iter());
+        (analysis.events, analysis.zones)
+    }
+
+    fn fence(fence_id: &str, shape: FenceShape) -> Geofence {
+        Geofence { fence_id: fence_id.to_string(), name: fence_id.to_string(), shape, alert: false }
+    }
+
+    /// A unit square of a degree, south west corner at the origin of the test area
+    fn square() -> FenceShape {
+        FenceShape::Polygon { points: vec![(44.0, -124.0), (45.0, -124.0), (45.0, -123.0), (44.0, -123.0)] }
+    }
+
+    #[wasm_bindgen_test]
+    fn circles_contain_positions_within_the_radius() {
+        let circle = FenceShape::Circle { latitude: 44.5, longitude: -123.5, radius_m: 50.0 };
+
+        assert!(contains(&circle, 44.5, -123.5));
+        // About 33 m north
+        assert!(contains(&circle, 44.5003, -123.5));
+        // About 56 m north
+        assert!(!contains(&circle, 44.5005, -123.5));
+    }
+
+    #[wasm_bindgen_test]
+    fn polygons_contain_positions_inside_the_outline() {
+        assert!(contains(&square(), 44.5, -123.5));
+        assert!(!contains(&square(), 45.5, -123.5));
+        assert!(!contains(&square(), 44.5, -122.5));
+
+        // An L shape, with the notch in the north east
+        let l_shape = FenceShape::Polygon {
+            points: vec![(44.0, -124.0), (45.0, -124.0), (45.0, -123.5), (44.5, -123.5), (44.5, -123.0), (44.0, -123.0)],
+        };
+        assert!(contains(&l_shape, 44.25, -123.25));
+        assert!(contains(&l_shape, 44.75, -123.75));
+        assert!(!contains(&l_shape, 44.75, -123.25));
+    }
+
+    #[wasm_bindgen_test]
+    fn crossings_are_found_in_time_order() {
+        let fences = [fence("square", square())];
+        let rows = [
+            fix(0, 0.0, 43.5, -123.5, 0.0),
+            fix(1, 10.0, 44.5, -123.5, 0.0),
+            fix(2, 20.0, 44.6, -123.5, 0.0),
+            fix(3, 30.0, 45.5, -123.5, 0.0),
+            fix(4, 40.0, 44.5, -123.5, 0.0),
+        ];
+        let (events, zones) = analyse(&fences, &rows);
+
+        let found: Vec<(Crossing, u32)> = events.iter().map(|e| (e.crossing, e.row_id)).collect();
+        assert_eq!(found, vec![(Crossing::Entered, 1), (Crossing::Exited, 3), (Crossing::Entered, 4)]);
+        assert_eq!(events[0].timestamp, rows[1].timestamp);
+
+        assert_eq!(zones[0].visits, 2);
+        assert_eq!(zones[0].inside_secs, 20.0);
+    }
+
+    #[wasm_bindgen_test]
+    fn starting_inside_counts_as_a_visit() {
+        let fences = [fence("square", square())];
+        let rows = [fix(0, 0.0, 44.5, -123.5, 0.0), fix(1, 5.0, 44.6, -123.5, 0.0)];
+        let (events, zones) = analyse(&fences, &rows);
+
+        assert!(events.is_empty());
+        assert_eq!(zones[0].visits, 1);
+        assert_eq!(zones[0].inside_secs, 5.0);
+    }
+
+    #[wasm_bindgen_test]
+    fn gaps_and_missing_fixes_are_skipped() {
+        let fences = [fence("square", square())];
+        let rows = [
+            fix(0, 0.0, 44.5, -123.5, 0.0),
+            // No fix yet, does not count as leaving
+            fix(1, 5.0, 0.0, 0.0, 0.0),
+            fix(2, 10.0, 44.5, -123.5, 0.0),
+            // Paused for longer than the gap limit
+            fix(3, 100.0, 44.5, -123.5, 0.0),
+        ];
+        let (events, zones) = analyse(&fences, &rows);
+
+        assert!(events.is_empty());
+        assert_eq!(zones[0].inside_secs, 10.0);
+    }
+
+    #[wasm_bindgen_test]
+    fn events_from_several_fences_are_interleaved() {
+        let fences = [
+            fence("west", FenceShape::Circle { latitude: 44.5, longitude: -123.6, radius_m: 1000.0 }),
+            fence("east", FenceShape::Circle { latitude: 44.5, longitude: -123.4, radius_m: 1000.0 }),
+        ];
+        let rows = [
+            fix(0, 0.0, 44.5, -123.7, 0.0),
+            fix(1, 10.0, 44.5, -123.6, 0.0),
+            fix(2, 20.0, 44.5, -123.5, 0.0),
+            fix(3, 30.0, 44.5, -123.4, 0.0),
+        ];
+        let (events, zones) = analyse(&fences, &rows);
+
+        let found: Vec<(&str, Crossing)> = events.iter().map(|e| (e.fence_id.as_str(), e.crossing)).collect();
+        assert_eq!(found, vec![("west", Crossing::Entered), ("west", Crossing::Exited), ("east", Crossing::Entered)]);
+        assert_eq!(zones.len(), 2);
+    }
+
+    #[wasm_bindgen_test]
+    fn added_rows_carry_on_from_the_last_fix() {
+        let fences = [fence("square", square())];
+        let rows = [
+            fix(0, 0.0, 43.5, -123.5, 0.0),
+            fix(1, 10.0, 44.5, -123.5, 0.0),
+            fix(2, 20.0, 44.6, -123.5, 0.0),
+            fix(3, 30.0, 45.5, -123.5, 0.0),
+        ];
+        let mut analysis = Analysis::new(&fences);
+        analysis.extend(&fences, rows[..2].iter());
+        analysis.extend(&fences, rows[2..].iter());
+
+        let found: Vec<(Crossing, u32)> = analysis.events.iter().map(|e| (e.crossing, e.row_id)).collect();
+        assert_eq!(found, vec![(Crossing::Entered, 1), (Crossing::Exited, 3)]);
+        assert_eq!(analysis.zones[0].visits, 1);
+        assert_eq!(analysis.zones[0].inside_secs, 20.0);
+    }
+}
@@ -0,0 +1,292 @@
+//! Kinematics derived from a session's GPS fixes
+//!
+//! Speed, heading and distance come from consecutive fixes. Samples without a
+//!     fix, or with a timestamp that does not parse, carry the last known
+//!     totals and no speed or heading.
+//!
+
+use crate::time;
+
+use super::Row;
+
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+/// Latitudes past this cannot be shown on the map either
+const MAX_LATITUDE: f64 = 85.051_128_78;
+/// Slower than this counts as stopped, m/s. Covers GPS drift while standing still.
+const MOVING_SPEED: f64 = 0.5;
+/// Altitude changes smaller than this are treated as noise when adding up climbs, m
+const ELEVATION_DEADBAND: f64 = 2.0;
+/// Gaps between fixes longer than this, e.g. a paused recording, count as neither moving nor stopped, s
+pub const MAX_GAP_SECS: f64 = 30.0;
+
+/// Whether a row holds a usable GPS fix. Receivers report 0, 0 before they have one.
+pub fn has_fix(row: &Row) -> bool {
+    row.latitude.is_finite()
+        && row.longitude.is_finite()
+        && row.latitude.abs() <= MAX_LATITUDE
+        && row.longitude.abs() <= 180.0
+        && !(row.latitude == 0.0 && row.longitude == 0.0)
+}
+
+/// Great circle distance between two positions in degrees, m
+pub fn haversine_m(lat_1: f64, lon_1: f64, lat_2: f64, lon_2: f64) -> f64 {
+    let (phi_1, phi_2) = (lat_1.to_radians(), lat_2.to_radians());
+    let d_phi = (lat_2 - lat_1).to_radians();
+    let d_lambda = (lon_2 - lon_1).to_radians();
+
+    let a = (d_phi / 2.0).sin().powi(2) + phi_1.cos() * phi_2.cos() * (d_lambda / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_M * a.sqrt().asin()
+}
+
+/// Initial bearing from the first position to the second, degrees clockwise from north
+pub fn bearing_deg(lat_1: f64, lon_1: f64, lat_2: f64, lon_2: f64) -> f64 {
+    let (phi_1, phi_2) = (lat_1.to_radians(), lat_2.to_radians());
+    let d_lambda = (lon_2 - lon_1).to_radians();
+
+    let y = d_lambda.sin() * phi_2.cos();
+    let x = phi_1.cos() * phi_2.sin() - phi_1.sin() * phi_2.cos() * d_lambda.cos();
+    y.atan2(x).to_degrees().rem_euclid(360.0)
+}
+
+/// Values derived for one sample
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Kinematics {
+    /// Speed over ground since the previous fix, m/s
+    pub speed: Option<f64>,
+    /// Heading since the previous fix, degrees clockwise from north. None while stopped.
+    pub heading: Option<f64>,
+    /// Distance covered since the start of the session, m
+    pub distance: f64,
+    /// Climb and descent since the start of the session, m
+    pub elevation_gain: f64,
+    pub elevation_loss: f64,
+    /// Whether the sample was taken while moving
+    pub moving: bool,
+}
+
+/// Totals for a whole session
+#[derive(Clone, Copy, Default, Debug)]
+pub struct TrackSummary {
+    /// m
+    pub distance: f64,
+    /// s
+    pub moving_secs: f64,
+    pub stopped_secs: f64,
+    /// m/s
+    pub max_speed: f64,
+    /// Distance over moving time, m/s
+    pub average_moving_speed: f64,
+    /// m
+    pub elevation_gain: f64,
+    pub elevation_loss: f64,
+    /// Samples with a GPS fix
+    pub fixes: usize,
+}
+
+/// Kinematics and totals of a session, worked out one row at a time in time order.
+///     Rows appended to a session carry on from the last one pushed.
+#[derive(Clone, Default, Debug)]
+pub struct Tracker {
+    /// Kinematics of the last row pushed
+    current: Kinematics,
+    /// Latitude, longitude and time of the last fix
+    previous: Option<(f64, f64, f64)>,
+    /// Altitude climbs are measured from
+    reference_altitude: Option<f64>,
+    /// Totals so far, without the ones read off `current`
+    summary: TrackSummary,
+    /// Only distance covered while moving, so drift while stopped does not lower the average
+    moving_distance: f64,
+}
+
+impl Tracker {
+    /// Derive the kinematics of the next row and add it to the totals
+    pub fn push(&mut self, row: &Row) -> Kinematics {
+        let current = &mut self.current;
+        current.speed = None;
+        current.heading = None;
+
+        let ms = match time::parse_datetime(&row.timestamp).filter(|_| has_fix(row)) {
+            Some(ms) => ms,
+            None => return *current,
+        };
+        self.summary.fixes += 1;
+
+        if let Some((latitude, longitude, last_ms)) = self.previous.filter(|(_, _, last_ms)| ms > *last_ms) {
+            let metres = haversine_m(latitude, longitude, row.latitude, row.longitude);
+            let secs = (ms - last_ms) / 1000.0;
+            let speed = metres / secs;
+
+            current.distance += metres;
+            current.speed = Some(speed);
+            current.moving = speed >= MOVING_SPEED;
+            if current.moving {
+                current.heading = Some(bearing_deg(latitude, longitude, row.latitude, row.longitude));
+            }
+
+            if secs <= MAX_GAP_SECS {
+                if current.moving {
+                    self.summary.moving_secs += secs;
+                    self.moving_distance += metres;
+                } else {
+                    self.summary.stopped_secs += secs;
+                }
+            }
+            self.summary.max_speed = self.summary.max_speed.max(speed);
+        }
+
+        if row.altitude.is_finite() {
+            match self.reference_altitude {
+                Some(reference) if row.altitude - reference >= ELEVATION_DEADBAND => {
+                    current.elevation_gain += row.altitude - reference;
+                    self.reference_altitude = Some(row.altitude);
+                }
+                Some(reference) if reference - row.altitude >= ELEVATION_DEADBAND => {
+                    current.elevation_loss += reference - row.altitude;
+                    self.reference_altitude = Some(row.altitude);
+                }
+                Some(_) => {}
+                None => self.reference_altitude = Some(row.altitude),
+            }
+        }
+
+        self.previous = Some((row.latitude, row.longitude, ms));
+        *current
+    }
+
+    /// Totals over every row pushed so far
+    pub fn summary(&self) -> TrackSummary {
+        let mut summary = self.summary;
+        summary.distance = self.current.distance;
+        summary.elevation_gain = self.current.elevation_gain;
+        summary.elevation_loss = self.current.elevation_loss;
+        if summary.moving_secs > 0.0 {
+            summary.average_moving_speed = self.moving_distance / summary.moving_secs;
+        }
+
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+    use crate::display::testing::fix;
+
+    /// Roughly 11.1 m of latitude
+    const STEP_DEG: f64 = 0.0001;
+
+    fn close(a: f64, b: f64, tolerance: f64) -> bool {
+        (a - b).abs() <= tolerance
+    }
+
+    fn kinematics(rows: &[Row]) -> Vec<Kinematics> {
+        let mut tracker = Tracker::default();
+        rows.iter().map(|row| tracker.push(row)).collect()
+    }
+
+    #[wasm_bindgen_test]
+    fn missing_fixes_are_detected() {
+        assert!(has_fix(&fix(0, 0.0, 44.5, -123.0, 0.0)));
+        assert!(!has_fix(&fix(0, 0.0, 0.0, 0.0, 0.0)));
+        assert!(!has_fix(&fix(0, 0.0, f64::NAN, -123.0, 0.0)));
+        assert!(!has_fix(&fix(0, 0.0, 89.0, -123.0, 0.0)));
+    }
+
+    #[wasm_bindgen_test]
+    fn speed_heading_and_distance_follow_the_fixes() {
+        let rows = [
+            fix(0, 0.0, 44.5, -123.0, 70.0),
+            fix(1, 1.0, 44.5 + STEP_DEG, -123.0, 70.0),
+            fix(2, 2.0, 44.5 + 2.0 * STEP_DEG, -123.0, 70.0),
+        ];
+        let derived = kinematics(&rows);
+
+        assert_eq!(derived[0].speed, None);
+        assert_eq!(derived[0].distance, 0.0);
+
+        let step_m = haversine_m(44.5, -123.0, 44.5 + STEP_DEG, -123.0);
+        assert!(close(step_m, 11.12, 0.01));
+        assert!(close(derived[1].speed.unwrap(), step_m, 1e-6));
+        assert!(close(derived[2].distance, 2.0 * step_m, 1e-6));
+        assert!(close(derived[2].heading.unwrap(), 0.0, 1e-6));
+        assert!(derived[2].moving);
+    }
+
+    #[wasm_bindgen_test]
+    fn rows_without_a_fix_carry_the_totals() {
+        let rows = [
+            fix(0, 0.0, 44.5, -123.0, 70.0),
+            fix(1, 1.0, 44.5 + STEP_DEG, -123.0, 70.0),
+            fix(2, 2.0, 0.0, 0.0, 0.0),
+            fix(3, 3.0, 44.5 + 2.0 * STEP_DEG, -123.0, 70.0),
+        ];
+        let derived = kinematics(&rows);
+
+        assert_eq!(derived[2].speed, None);
+        assert_eq!(derived[2].distance, derived[1].distance);
+        // Measured from the last fix, two seconds back
+        assert!(close(derived[3].speed.unwrap(), derived[1].speed.unwrap() / 2.0, 1e-6));
+    }
+
+    #[wasm_bindgen_test]
+    fn small_altitude_changes_are_ignored() {
+        let rows: Vec<Row> = [100.0, 101.0, 103.0, 102.0, 99.0, 100.5].iter()
+            .enumerate()
+            .map(|(i, altitude)| fix(i as u32, i as f64, 44.5, -123.0, *altitude))
+            .collect();
+        let last = *kinematics(&rows).last().unwrap();
+
+        assert_eq!(last.elevation_gain, 3.0);
+        assert_eq!(last.elevation_loss, 4.0);
+    }
+
+    #[wasm_bindgen_test]
+    fn summary_splits_moving_and_stopped_time() {
+        let rows = [
+            fix(0, 0.0, 44.5, -123.0, 70.0),
+            // Walking for two seconds
+            fix(1, 1.0, 44.5 + STEP_DEG, -123.0, 70.0),
+            fix(2, 2.0, 44.5 + 2.0 * STEP_DEG, -123.0, 70.0),
+            // Standing for three
+            fix(3, 5.0, 44.5 + 2.0 * STEP_DEG, -123.0, 70.0),
+            // Recording paused for a minute, not counted either way
+            fix(4, 65.0, 44.5 + 3.0 * STEP_DEG, -123.0, 70.0),
+        ];
+        let mut tracker = Tracker::default();
+        rows.iter().for_each(|row| { tracker.push(row); });
+        let summary = tracker.summary();
+
+        let step_m = haversine_m(44.5, -123.0, 44.5 + STEP_DEG, -123.0);
+        assert_eq!(summary.fixes, 5);
+        assert_eq!(summary.moving_secs, 2.0);
+        assert_eq!(summary.stopped_secs, 3.0);
+        assert!(close(summary.distance, 3.0 * step_m, 1e-6));
+        assert!(close(summary.max_speed, step_m, 1e-6));
+        assert!(close(summary.average_moving_speed, step_m, 1e-6));
+    }
+
+    #[wasm_bindgen_test]
+    fn appended_rows_carry_on_from_the_last_one() {
+        let rows: Vec<Row> = (0..6)
+            .map(|i| fix(i, i as f64 * 2.0, 44.5 + i as f64 * STEP_DEG, -123.0, 70.0 + i as f64 * 3.0))
+            .collect();
+
+        let mut whole = Tracker::default();
+        let all: Vec<Kinematics> = rows.iter().map(|row| whole.push(row)).collect();
+
+        let mut appended = Tracker::default();
+        rows[..2].iter().for_each(|row| { appended.push(row); });
+        let before = appended.summary();
+        let later: Vec<Kinematics> = rows[2..].iter().map(|row| appended.push(row)).collect();
+
+        assert_eq!(before.fixes, 2);
+        assert!(close(later[0].speed.unwrap(), all[2].speed.unwrap(), 1e-9));
+        assert!(close(later[3].distance, all[5].distance, 1e-9));
+        assert_eq!(appended.summary().fixes, 6);
+        assert!(close(appended.summary().moving_secs, whole.summary().moving_secs, 1e-9));
+        assert_eq!(appended.summary().elevation_gain, whole.summary().elevation_gain);
+    }
+}
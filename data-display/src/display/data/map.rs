@@ -0,0 +1,409 @@
+//! GPS track map for the data window
+//!
+//! Positions are drawn in Web Mercator, the projection basemap tiles use.
+//!     The view is kept as a center point in normalized world coordinates
+//!     (0 to 1 on both axes) and a fractional zoom level.
+//!
+
+use std::collections::HashMap;
+
+use eframe::egui::{Align2, Color32, ComboBox, FontId, Pos2, Rect, Sense, Shape, Stroke, Vec2};
+
+use crate::backend::FenceShape;
+
+use super::coloring::TrackColoring;
+use super::gps::{has_fix, Kinematics};
+use super::tiles::{tile_sources, TileCache, TileKey, TileSource, TILE_SIZE};
+use super::Row;
+
+/// Latitudes past this cannot be shown in Web Mercator
+const MAX_LATITUDE: f64 = 85.051_128_78;
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+const MIN_ZOOM: f64 = 1.0;
+const MAX_ZOOM: f64 = 21.0;
+/// Zoom used when the track is a single point
+const POINT_ZOOM: f64 = 17.0;
+/// Samples further than this from the pointer are not hovered, px
+const HOVER_RADIUS: f32 = 12.0;
+/// Most tiles drawn in one frame, guards against huge maps
+const MAX_VISIBLE_TILES: u32 = 64;
+const MAP_WIDTH: f32 = 800.0;
+const MAP_HEIGHT: f32 = 450.0;
+
+/// Web Mercator position with both axes normalized to 0..1, x east and y south
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct WorldPos {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// Project a latitude and longitude in degrees
+pub fn project(latitude: f64, longitude: f64) -> WorldPos {
+    let lat = latitude.clamp(-MAX_LATITUDE, MAX_LATITUDE).to_radians();
+
+    WorldPos {
+        x: (longitude + 180.0) / 360.0,
+        y: (1.0 - (lat.tan() + 1.0 / lat.cos()).ln() / std::f64::consts::PI) / 2.0,
+    }
+}
+
+/// Latitude and longitude in degrees of a projected position
+pub fn unproject(pos: WorldPos) -> (f64, f64) {
+    let n = std::f64::consts::PI * (1.0 - 2.0 * pos.y);
+
+    (n.sinh().atan().to_degrees(), pos.x * 360.0 - 180.0)
+}
+
+/// A shape drawn over the map, such as a geofence
+pub struct MapOverlay {
+    pub shape: FenceShape,
+    pub label: String,
+    pub color: Color32,
+    /// Still being drawn: polygons are left open and show their vertices
+    pub draft: bool,
+}
+
+/// What happened on the map this frame
+pub struct MapResponse {
+    /// Index in `rows` of the sample under the pointer
+    pub hovered: Option<usize>,
+    /// Latitude and longitude clicked, if the map was clicked without dragging
+    pub clicked: Option<(f64, f64)>,
+}
+
+/// Pannable, zoomable map of a session's GPS track over basemap tiles
+pub struct MapView {
+    center: WorldPos,
+    zoom: f64,
+    /// Fit the view to the track once there is one
+    fit_pending: bool,
+    sources: Vec<TileSource>,
+    /// Index of the picked source in `sources`
+    source: usize,
+    tiles: TileCache,
+    coloring: TrackColoring,
+}
+
+impl MapView {
+    pub fn new() -> Self {
+        let sources = tile_sources();
+        // A configured tile server is listed last and preferred
+        let source = if sources.len() > 2 { sources.len() - 1 } else { 0 };
+
+        MapView {
+            center: WorldPos { x: 0.5, y: 0.5 },
+            zoom: 2.0,
+            fit_pending: true,
+            sources,
+            source,
+            tiles: TileCache::new(),
+            coloring: TrackColoring::new(),
+        }
+    }
+
+    /// Fit the view to the next track drawn, e.g. after switching sessions
+    pub fn reset_view(&mut self) -> () {
+        self.fit_pending = true;
+    }
+
+    /// Screen pixels per unit of world coordinates
+    fn scale(&self) -> f64 {
+        TILE_SIZE * 2f64.powf(self.zoom)
+    }
+
+    fn to_screen(&self, rect: Rect, pos: WorldPos) -> Pos2 {
+        let scale = self.scale();
+        rect.center() + Vec2::new(((pos.x - self.center.x) * scale) as f32, ((pos.y - self.center.y) * scale) as f32)
+    }
+
+    fn to_world(&self, rect: Rect, pos: Pos2) -> WorldPos {
+        let scale = self.scale();
+        let offset = pos - rect.center();
+
+        WorldPos {
+            x: self.center.x + offset.x as f64 / scale,
+            y: self.center.y + offset.y as f64 / scale,
+        }
+    }
+
+    /// Change the zoom while keeping the world position under `anchor` in place
+    fn zoom_around(&mut self, rect: Rect, anchor: Pos2, delta: f64) -> () {
+        let before = self.to_world(rect, anchor);
+        self.zoom = (self.zoom + delta).clamp(MIN_ZOOM, MAX_ZOOM);
+
+        let scale = self.scale();
+        let offset = anchor - rect.center();
+        self.center = WorldPos {
+            x: before.x - offset.x as f64 / scale,
+            y: before.y - offset.y as f64 / scale,
+        };
+    }
+
+    /// Center and zoom so the whole track is visible
+    fn fit(&mut self, rect: Rect, track: &[WorldPos]) -> () {
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (f64::MAX, f64::MAX, f64::MIN, f64::MIN);
+        for pos in track {
+            min_x = min_x.min(pos.x);
+            min_y = min_y.min(pos.y);
+            max_x = max_x.max(pos.x);
+            max_y = max_y.max(pos.y);
+        }
+
+        self.center = WorldPos { x: (min_x + max_x) / 2.0, y: (min_y + max_y) / 2.0 };
+
+        // Leave a margin around the track
+        let (width, height) = (max_x - min_x, max_y - min_y);
+        self.zoom = if width <= 0.0 && height <= 0.0 {
+            POINT_ZOOM
+        } else {
+            let fit_x = rect.width() as f64 * 0.85 / (width * TILE_SIZE);
+            let fit_y = rect.height() as f64 * 0.85 / (height * TILE_SIZE);
+            fit_x.min(fit_y).log2()
+        }
+        .clamp(MIN_ZOOM, MAX_ZOOM);
+    }
+
+    /// Draw geofences and other shapes over the basemap
+    fn draw_overlays(&self, painter: &eframe::egui::Painter, rect: Rect, overlays: &[MapOverlay]) -> () {
+        for overlay in overlays {
+            let stroke = Stroke::new(2.0, overlay.color);
+            let fill = overlay.color.gamma_multiply(0.2);
+
+            let label_at = match &overlay.shape {
+                FenceShape::Circle { latitude, longitude, radius_m } => {
+                    let center = self.to_screen(rect, project(*latitude, *longitude));
+                    // Ground distance covered by one world unit shrinks away from the equator
+                    let metres_per_unit = 2.0 * std::f64::consts::PI * EARTH_RADIUS_M * latitude.to_radians().cos();
+                    let radius = (radius_m / metres_per_unit * self.scale()) as f32;
+
+                    painter.circle(center, radius.max(2.0), fill, stroke);
+                    center
+                }
+                FenceShape::Polygon { points } => {
+                    let screen: Vec<Pos2> = points.iter()
+                        .map(|(latitude, longitude)| self.to_screen(rect, project(*latitude, *longitude)))
+                        .collect();
+                    if screen.is_empty() {
+                        continue;
+                    }
+
+                    if overlay.draft {
+                        painter.add(Shape::line(screen.clone(), stroke));
+                        for pos in &screen {
+                            painter.circle(*pos, 4.0, overlay.color, Stroke::new(1.0, Color32::BLACK));
+                        }
+                    } else {
+                        // egui only fills convex shapes, so polygons are outlined
+                        painter.add(Shape::closed_line(screen.clone(), Stroke::new(3.0, overlay.color)));
+                    }
+                    screen[0]
+                }
+            };
+
+            if !overlay.label.is_empty() {
+                painter.text(label_at, Align2::CENTER_BOTTOM, &overlay.label, FontId::proportional(12.0), overlay.color);
+            }
+        }
+    }
+
+    /// Draw the tiles covering the view, or a plain grid without a basemap
+    fn draw_tiles(&mut self, ctx: &eframe::egui::Context, painter: &eframe::egui::Painter, rect: Rect) -> () {
+        let source = &self.sources[self.source];
+        let z = self.zoom.round().clamp(0.0, source.max_zoom as f64) as u8;
+        let count = 1u32 << z;
+        let tile_px = (self.scale() / count as f64) as f32;
+
+        let top_left = self.to_world(rect, rect.min);
+        let bottom_right = self.to_world(rect, rect.max);
+        let to_tile = |v: f64| (v * count as f64).floor().clamp(0.0, (count - 1) as f64) as u32;
+        let (x0, x1) = (to_tile(top_left.x), to_tile(bottom_right.x));
+        let (y0, y1) = (to_tile(top_left.y), to_tile(bottom_right.y));
+        if (x1 - x0 + 1) * (y1 - y0 + 1) > MAX_VISIBLE_TILES {
+            return;
+        }
+
+        let basemap = source.url_template.is_some();
+        for x in x0..=x1 {
+            for y in y0..=y1 {
+                let min = self.to_screen(rect, WorldPos { x: x as f64 / count as f64, y: y as f64 / count as f64 });
+                let tile_rect = Rect::from_min_size(min, Vec2::splat(tile_px));
+
+                if !basemap {
+                    painter.rect_stroke(tile_rect, 0.0, Stroke::new(1.0, Color32::from_gray(70)));
+                    continue;
+                }
+
+                if let Some(texture) = self.tiles.get(ctx, TileKey { z, x, y }) {
+                    let uv = Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0));
+                    painter.image(texture.id(), tile_rect, uv, Color32::WHITE);
+                }
+            }
+        }
+    }
+
+    /// Draw the map controls and the track of `rows`, which must be in time order.
+    ///     In live mode the latest position is highlighted. `marked` is an index in `rows`
+    ///     to mark, e.g. the sample under the cursor on another view. `overlays` are drawn
+    ///     under the track. `motion` holds the kinematics derived for each row id.
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui, rows: &[&Row], motion: &HashMap<u32, Kinematics>, live: bool, marked: Option<usize>, overlays: &[MapOverlay]) -> MapResponse {
+        ui.horizontal(|ui| {
+            ui.label("Basemap:");
+            ComboBox::from_id_salt("map_source")
+                .selected_text(self.sources[self.source].name.clone())
+                .show_ui(ui, |ui| {
+                    for (i, source) in self.sources.iter().enumerate() {
+                        ui.selectable_value(&mut self.source, i, source.name.clone());
+                    }
+                });
+            if ui.button("Fit").clicked() {
+                self.fit_pending = true;
+            }
+            ui.separator();
+            self.coloring.show(ui);
+        });
+        ui.label(eframe::egui::RichText::new("Drag to pan, scroll to zoom").small());
+
+        let (rect, response) = ui.allocate_exact_size(Vec2::new(MAP_WIDTH, MAP_HEIGHT), Sense::click_and_drag());
+        let painter = ui.painter_at(rect);
+        let ctx = ui.ctx().clone();
+
+        // Samples with a fix, keeping their index in `rows`
+        let track: Vec<(usize, WorldPos)> = rows.iter().enumerate()
+            .filter(|(_, row)| has_fix(row))
+            .map(|(i, row)| (i, project(row.latitude, row.longitude)))
+            .collect();
+
+        if self.fit_pending && !track.is_empty() {
+            let positions: Vec<WorldPos> = track.iter().map(|(_, pos)| *pos).collect();
+            self.fit(rect, &positions);
+            self.fit_pending = false;
+        }
+
+        // Pan and zoom
+        if response.dragged() {
+            let delta = response.drag_delta();
+            let scale = self.scale();
+            self.center.x -= delta.x as f64 / scale;
+            self.center.y -= delta.y as f64 / scale;
+        }
+        if let Some(pointer) = response.hover_pos() {
+            let (scroll, pinch) = ui.input(|i| (i.smooth_scroll_delta.y, i.zoom_delta()));
+            let delta = scroll as f64 / 200.0 + (pinch as f64).log2();
+            if delta != 0.0 {
+                self.zoom_around(rect, pointer, delta);
+            }
+        }
+        self.center.x = self.center.x.clamp(0.0, 1.0);
+        self.center.y = self.center.y.clamp(0.0, 1.0);
+
+        // Basemap
+        painter.rect_filled(rect, 0.0, Color32::from_gray(40));
+        self.tiles.set_source(&self.sources[self.source]);
+        self.tiles.update(&ctx);
+        self.draw_tiles(&ctx, &painter, rect);
+        self.draw_overlays(&painter, rect, overlays);
+
+        let clicked = response.clicked()
+            .then(|| response.interact_pointer_pos())
+            .flatten()
+            .map(|pointer| unproject(self.to_world(rect, pointer)));
+
+        if track.is_empty() {
+            painter.text(rect.center(), Align2::CENTER_CENTER, "No GPS fixes in this session", FontId::proportional(16.0), Color32::WHITE);
+            return MapResponse { hovered: None, clicked };
+        }
+
+        // Track, skipping points that would land on the previous one
+        let screen: Vec<Pos2> = track.iter().map(|(_, pos)| self.to_screen(rect, *pos)).collect();
+        let mut line: Vec<(Pos2, usize)> = Vec::with_capacity(screen.len());
+        for (k, pos) in screen.iter().enumerate() {
+            if line.last().is_none_or(|(last, _)| last.distance(*pos) >= 1.5) {
+                line.push((*pos, k));
+            }
+        }
+
+        // Colored segments take the peak of every sample since the previous
+        //     point drawn, so spikes dropped from the line still show
+        let colors = self.coloring.colors(rows, motion);
+        match &colors {
+            Some(colors) => {
+                for pair in line.windows(2) {
+                    let color = colors.segment_color(track[pair[0].1].0 + 1..track[pair[1].1].0 + 1);
+                    painter.line_segment([pair[0].0, pair[1].0], Stroke::new(4.0, color));
+                }
+            }
+            None => {
+                let points = line.iter().map(|(pos, _)| *pos).collect();
+                painter.add(Shape::line(points, Stroke::new(3.0, Color32::from_rgb(30, 144, 255))));
+            }
+        }
+
+        // Start and end markers
+        let start = screen[0];
+        let end = screen[screen.len() - 1];
+        painter.circle(start, 6.0, Color32::GREEN, Stroke::new(2.0, Color32::BLACK));
+        if live {
+            painter.circle_stroke(end, 12.0, Stroke::new(2.0, Color32::from_rgb(255, 140, 0)));
+            painter.circle(end, 7.0, Color32::from_rgb(255, 140, 0), Stroke::new(2.0, Color32::BLACK));
+        } else {
+            painter.circle(end, 6.0, Color32::RED, Stroke::new(2.0, Color32::BLACK));
+        }
+
+        if let Some(colors) = &colors {
+            self.coloring.draw_legend(&painter, rect, colors);
+        }
+
+        // Credit for the basemap
+        let attribution = &self.sources[self.source].attribution;
+        if !attribution.is_empty() {
+            painter.text(rect.right_bottom() - Vec2::new(4.0, 2.0), Align2::RIGHT_BOTTOM, attribution, FontId::proportional(10.0), Color32::BLACK);
+        }
+
+        // Nearest sample to the pointer
+        let hovered = response.hover_pos().and_then(|pointer| {
+            screen.iter()
+                .enumerate()
+                .map(|(i, pos)| (i, pos.distance(pointer)))
+                .filter(|(_, distance)| *distance <= HOVER_RADIUS)
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(i, _)| i)
+        });
+
+        if let Some(k) = marked.and_then(|i| track.iter().position(|(row, _)| *row == i)) {
+            painter.circle(screen[k], 6.0, Color32::YELLOW, Stroke::new(2.0, Color32::BLACK));
+        }
+
+        if let Some(i) = hovered {
+            painter.circle_stroke(screen[i], 8.0, Stroke::new(2.0, Color32::WHITE));
+
+            let row = rows[track[i].0];
+            response.on_hover_ui_at_pointer(|ui| {
+                ui.label(eframe::egui::RichText::new(&row.timestamp).strong());
+                eframe::egui::Grid::new("map_tooltip").num_columns(2).show(ui, |ui| {
+                    let values = [
+                        ("Latitude", row.latitude),
+                        ("Longitude", row.longitude),
+                        ("Altitude", row.altitude),
+                        ("Accel X", row.accel_x),
+                        ("Accel Y", row.accel_y),
+                        ("Accel Z", row.accel_z),
+                        ("Gyro X", row.gyro_x),
+                        ("Gyro Y", row.gyro_y),
+                        ("Gyro Z", row.gyro_z),
+                        ("Dac 1", row.dac_1),
+                        ("Dac 2", row.dac_2),
+                        ("Dac 3", row.dac_3),
+                        ("Dac 4", row.dac_4),
+                    ];
+                    for (label, value) in values {
+                        ui.label(label);
+                        ui.label(format!("{:.6}", value));
+                        ui.end_row();
+                    }
+                });
+            });
+        }
+
+        MapResponse { hovered: hovered.map(|i| track[i].0), clicked }
+    }
+}
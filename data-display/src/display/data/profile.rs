@@ -0,0 +1,75 @@
+//! Altitude and speed profile against distance for location data
+//!
+//! The two plots share their distance axis and cursor, and are linked to the
+//!     map through row ids: the sample under the cursor is highlighted on the
+//!     map, and the sample hovered on the map is marked on the profile.
+//!
+
+use std::collections::HashMap;
+
+use eframe::egui::{Color32, Vec2b};
+use egui_plot::{Legend, Line, Plot, PlotPoints, VLine};
+
+use super::gps::{has_fix, Kinematics};
+use super::Row;
+
+/// Draw the profile of `rows`, which must be in time order, with a line at the `marked` row.
+///
+/// Returns the id of the row nearest the cursor, if the profile is hovered.
+pub fn show(ui: &mut eframe::egui::Ui, rows: &[&Row], motion: &HashMap<u32, Kinematics>, marked: Option<u32>) -> Option<u32> {
+    // Distance along the track, in km, of each fix
+    let samples: Vec<(&Row, f64, &Kinematics)> = rows.iter()
+        .filter(|row| has_fix(row))
+        .filter_map(|row| motion.get(&row.id).map(|k| (*row, k.distance / 1000.0, k)))
+        .collect();
+
+    if samples.len() < 2 {
+        ui.label("Not enough GPS fixes for a profile");
+        return None;
+    }
+
+    let marked_km = marked.and_then(|id| samples.iter().find(|(row, _, _)| row.id == id)).map(|(_, km, _)| *km);
+    let nearest = |km: f64| samples.iter()
+        .min_by(|a, b| (a.1 - km).abs().total_cmp(&(b.1 - km).abs()))
+        .map(|(row, _, _)| row.id);
+
+    let altitude: PlotPoints = samples.iter().map(|(row, km, _)| [*km, row.altitude]).collect();
+    let altitude_hover = Plot::new("altitude_profile")
+        .legend(Legend::default())
+        .x_axis_label("Distance (km)")
+        .y_axis_label("Altitude (m)")
+        .link_axis("location_profile", Vec2b::new(true, false))
+        .link_cursor("location_profile", Vec2b::new(true, false))
+        .width(800.0)
+        .height(200.0)
+        .show(ui, |ui| {
+            ui.line(Line::new(altitude).name("Altitude").color(Color32::GREEN));
+            if let Some(km) = marked_km {
+                ui.vline(VLine::new(km).color(Color32::YELLOW));
+            }
+            // The pointer is reported even over other widgets, only use it while over this plot
+            ui.pointer_coordinate().filter(|_| ui.response().hovered()).map(|point| point.x)
+        })
+        .inner;
+
+    let speed: PlotPoints = samples.iter().filter_map(|(_, km, k)| k.speed.map(|v| [*km, v])).collect();
+    let speed_hover = Plot::new("speed_profile")
+        .legend(Legend::default())
+        .x_axis_label("Distance (km)")
+        .y_axis_label("Speed (m/s)")
+        .link_axis("location_profile", Vec2b::new(true, false))
+        .link_cursor("location_profile", Vec2b::new(true, false))
+        .width(800.0)
+        .height(200.0)
+        .show(ui, |ui| {
+            ui.line(Line::new(speed).name("Speed").color(Color32::RED));
+            if let Some(km) = marked_km {
+                ui.vline(VLine::new(km).color(Color32::YELLOW));
+            }
+            ui.pointer_coordinate().filter(|_| ui.response().hovered()).map(|point| point.x)
+        })
+        .inner;
+
+    // At most one of the plots is hovered
+    altitude_hover.or(speed_hover).and_then(nearest)
+}
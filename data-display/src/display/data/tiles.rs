@@ -0,0 +1,298 @@
+//! Basemap tiles for the map view
+//!
+//! Tiles follow the usual `{z}/{x}/{y}` Web Mercator scheme and can come from
+//!     any source that serves them over http, such as a tile directory copied
+//!     next to the app or a self-hosted tile server, so the map works without
+//!     internet access.
+//!
+
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+use eframe::egui::{ColorImage, Context, TextureHandle, TextureOptions};
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+
+/// Tile edge length, px
+pub const TILE_SIZE: f64 = 256.0;
+/// Tiles fetched at the same time
+const MAX_IN_FLIGHT: usize = 6;
+/// Decoded tiles kept before those not drawn recently are dropped
+const MAX_CACHED: usize = 300;
+
+/// Where basemap tiles come from
+#[derive(Clone, PartialEq)]
+pub struct TileSource {
+    pub name: String,
+    /// Url with `{z}`, `{x}` and `{y}` replaced per tile. None draws no basemap.
+    pub url_template: Option<String>,
+    /// Deepest zoom level the source has. Closer zooms scale these tiles up.
+    pub max_zoom: u8,
+    /// Credit shown in the corner of the map
+    pub attribution: String,
+}
+
+impl TileSource {
+    fn url(&self, tile: TileKey) -> Option<String> {
+        self.url_template.as_ref().map(|template| template
+            .replace("{z}", &tile.z.to_string())
+            .replace("{x}", &tile.x.to_string())
+            .replace("{y}", &tile.y.to_string()))
+    }
+}
+
+/// Tile sources to pick from.
+///
+/// `MAP_TILE_URL` set at compile time adds a tile server, e.g.
+///     `MAP_TILE_URL=http://tiles.local/{z}/{x}/{y}.png`, credited with `MAP_TILE_ATTRIBUTION`.
+pub fn tile_sources() -> Vec<TileSource> {
+    let mut sources = vec![
+        TileSource {
+            name: "No basemap".to_string(),
+            url_template: None,
+            max_zoom: 19,
+            attribution: String::new(),
+        },
+        TileSource {
+            name: "Local tiles".to_string(),
+            url_template: Some("tiles/{z}/{x}/{y}.png".to_string()),
+            max_zoom: 18,
+            attribution: String::new(),
+        },
+    ];
+
+    if let Some(url) = option_env!("MAP_TILE_URL").filter(|url| !url.is_empty()) {
+        sources.push(TileSource {
+            name: "Tile server".to_string(),
+            url_template: Some(url.to_string()),
+            max_zoom: 19,
+            attribution: option_env!("MAP_TILE_ATTRIBUTION").unwrap_or_default().to_string(),
+        });
+    }
+
+    sources
+}
+
+/// Position of a tile in the `{z}/{x}/{y}` scheme
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct TileKey {
+    pub z: u8,
+    pub x: u32,
+    pub y: u32,
+}
+
+/// Fetch a tile's bytes
+async fn fetch_tile(url: String) -> Result<Vec<u8>, String> {
+    let window = web_sys::window().ok_or_else(|| "No window".to_string())?;
+
+    let response = JsFuture::from(window.fetch_with_str(&url)).await
+        .map_err(|e| format!("{:?}", e))?
+        .dyn_into::<web_sys::Response>()
+        .map_err(|_| "Not a response".to_string())?;
+    if !response.ok() {
+        return Err(format!("Status {}", response.status()));
+    }
+
+    let buffer = JsFuture::from(response.array_buffer().map_err(|e| format!("{:?}", e))?).await
+        .map_err(|e| format!("{:?}", e))?;
+    Ok(js_sys::Uint8Array::new(&buffer).to_vec())
+}
+
+/// Decode a PNG tile into an image egui can upload
+fn decode_tile(bytes: &[u8]) -> Result<ColorImage, String> {
+    let image = image::load_from_memory(bytes).map_err(|e| e.to_string())?.to_rgba8();
+    let size = [image.width() as usize, image.height() as usize];
+
+    Ok(ColorImage::from_rgba_unmultiplied(size, image.as_raw()))
+}
+
+/// Fetched tile tagged with the source generation it was requested for
+type TileResponse = (u64, TileKey, Result<Vec<u8>, String>);
+
+/// Fetched tiles for one source, uploaded as textures
+pub struct TileCache {
+    source: Option<TileSource>,
+    /// Bumped whenever the source changes, so fetches for an old source are recognised
+    generation: u64,
+    textures: HashMap<TileKey, TextureHandle>,
+    /// Frame each loaded tile was last drawn in, drives eviction. Has the same keys as `textures`.
+    last_used: HashMap<TileKey, u64>,
+    frame: u64,
+    in_flight: HashSet<TileKey>,
+    /// Tiles that failed to load. Not retried until the source changes.
+    failed: HashSet<TileKey>,
+
+    sender: Sender<TileResponse>,
+    receiver: Receiver<TileResponse>,
+}
+
+impl TileCache {
+    pub fn new() -> Self {
+        let (sender, receiver) = channel();
+
+        TileCache {
+            source: None,
+            generation: 0,
+            textures: HashMap::new(),
+            last_used: HashMap::new(),
+            frame: 0,
+            in_flight: HashSet::new(),
+            failed: HashSet::new(),
+
+            sender,
+            receiver,
+        }
+    }
+
+    /// Switch to a different source, dropping every tile of the old one
+    pub fn set_source(&mut self, source: &TileSource) -> () {
+        if self.source.as_ref() == Some(source) {
+            return;
+        }
+
+        self.source = Some(source.clone());
+        self.generation += 1;
+        self.textures.clear();
+        self.last_used.clear();
+        self.in_flight.clear();
+        self.failed.clear();
+    }
+
+    /// Upload tiles that arrived since the last frame and evict old ones. Called once per frame.
+    pub fn update(&mut self, ctx: &Context) -> () {
+        self.frame += 1;
+
+        while let Ok((generation, tile, result)) = self.receiver.try_recv() {
+            // Fetches still landing for the old source after a switch are dropped
+            if generation != self.generation || !self.in_flight.remove(&tile) {
+                continue;
+            }
+
+            match result.and_then(|bytes| decode_tile(&bytes)) {
+                Ok(image) => {
+                    let name = format!("tile_{}_{}_{}", tile.z, tile.x, tile.y);
+                    self.textures.insert(tile, ctx.load_texture(name, image, TextureOptions::LINEAR));
+                    self.last_used.insert(tile, self.frame);
+                }
+                Err(e) => {
+                    log::warn!("Tile {:?} failed to load. {}", tile, e);
+                    self.failed.insert(tile);
+                }
+            }
+        }
+
+        if self.textures.len() > MAX_CACHED {
+            let mut by_age: Vec<(TileKey, u64)> = self.textures.keys()
+                .map(|tile| (*tile, self.last_used.get(tile).copied().unwrap_or(0)))
+                .collect();
+            by_age.sort_by_key(|(_, frame)| *frame);
+
+            for (tile, _) in by_age.into_iter().take(self.textures.len() - MAX_CACHED) {
+                self.textures.remove(&tile);
+                self.last_used.remove(&tile);
+            }
+        }
+    }
+
+    /// Texture for a tile, starting a fetch if it is not loaded yet
+    pub fn get(&mut self, ctx: &Context, tile: TileKey) -> Option<&TextureHandle> {
+        if let Some(frame) = self.last_used.get_mut(&tile) {
+            *frame = self.frame;
+        }
+
+        if !self.textures.contains_key(&tile) && !self.in_flight.contains(&tile) && !self.failed.contains(&tile)
+            && self.in_flight.len() < MAX_IN_FLIGHT
+        {
+            if let Some(url) = self.source.as_ref().and_then(|source| source.url(tile)) {
+                self.in_flight.insert(tile);
+
+                let generation = self.generation;
+                let sender = self.sender.clone();
+                let repaint = ctx.clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    let _ = sender.send((generation, tile, fetch_tile(url).await));
+                    repaint.request_repaint();
+                });
+            }
+        }
+
+        self.textures.get(&tile)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    fn source(name: &str) -> TileSource {
+        TileSource {
+            name: name.to_string(),
+            url_template: Some(format!("{}/{{z}}/{{x}}/{{y}}.png", name)),
+            max_zoom: 18,
+            attribution: String::new(),
+        }
+    }
+
+    fn tile(x: u32) -> TileKey {
+        TileKey { z: 10, x, y: 0 }
+    }
+
+    fn png() -> Vec<u8> {
+        let mut bytes = std::io::Cursor::new(Vec::new());
+        image::RgbaImage::new(1, 1).write_to(&mut bytes, image::ImageFormat::Png).unwrap();
+        bytes.into_inner()
+    }
+
+    /// Pretend a fetch for the tile was started for the current source, and land its response
+    fn respond(cache: &mut TileCache, tile: TileKey, result: Result<Vec<u8>, String>) -> () {
+        cache.in_flight.insert(tile);
+        cache.sender.send((cache.generation, tile, result)).unwrap();
+    }
+
+    #[wasm_bindgen_test]
+    fn tiles_from_the_old_source_are_dropped() {
+        let ctx = Context::default();
+        let mut cache = TileCache::new();
+
+        cache.set_source(&source("a"));
+        cache.in_flight.insert(tile(0));
+        let old_generation = cache.generation;
+
+        // The same tile is requested again from the new source before the old fetch lands
+        cache.set_source(&source("b"));
+        cache.in_flight.insert(tile(0));
+        cache.sender.send((old_generation, tile(0), Ok(png()))).unwrap();
+        cache.update(&ctx);
+
+        assert!(cache.textures.is_empty());
+        assert!(cache.in_flight.contains(&tile(0)));
+
+        cache.sender.send((cache.generation, tile(0), Ok(png()))).unwrap();
+        cache.update(&ctx);
+        assert!(cache.textures.contains_key(&tile(0)));
+    }
+
+    #[wasm_bindgen_test]
+    fn only_loaded_tiles_are_tracked_and_evicted() {
+        let ctx = Context::default();
+        let mut cache = TileCache::new();
+        cache.set_source(&source("a"));
+
+        for x in 0..MAX_CACHED as u32 + 10 {
+            respond(&mut cache, tile(x), Ok(png()));
+            cache.update(&ctx);
+        }
+        respond(&mut cache, tile(9999), Err("Status 404".to_string()));
+        cache.update(&ctx);
+
+        assert_eq!(cache.textures.len(), MAX_CACHED);
+        assert_eq!(cache.last_used.len(), MAX_CACHED);
+        assert!(cache.last_used.keys().all(|tile| cache.textures.contains_key(tile)));
+        // The first tiles to arrive were never drawn again and went first
+        assert!(!cache.textures.contains_key(&tile(0)));
+        assert!(cache.textures.contains_key(&tile(MAX_CACHED as u32 + 9)));
+        assert!(cache.failed.contains(&tile(9999)));
+    }
+}
@@ -1,60 +1,288 @@
 //! Device status display and connection settings
 //!
 
+mod history;
+mod settings;
+
 use eframe::egui::{Color32, RichText};
+use zeroize::Zeroize;
+
+use crate::backend::{BackendError, ConnectionType, DeviceStatus};
+use crate::time;
+
+use crate::link::{DeviceLink, LinkTarget};
+
+use super::login::password_field;
+use super::request::RequestChannel;
+use super::store::AppStore;
+
+/// Time between status polls, ms
+const POLL_INTERVAL_MS: f64 = 2000.0;
+/// A device that has not reported for this long is shown offline
+const OFFLINE_AFTER_SECS: f64 = 30.0;
+
+/// Warning and critical thresholds for each metric
+const CPU_TEMP_WARN_C: f64 = 70.0;
+const CPU_TEMP_CRIT_C: f64 = 80.0;
+const CPU_LOAD_WARN_PCT: f64 = 90.0;
+const CPU_LOAD_CRIT_PCT: f64 = 98.0;
+const BATTERY_TEMP_WARN_C: f64 = 45.0;
+const BATTERY_TEMP_CRIT_C: f64 = 55.0;
+const BATTERY_WARN_PCT: f64 = 20.0;
+const BATTERY_CRIT_PCT: f64 = 10.0;
+const CELLULAR_WARN_DBM: f64 = -100.0;
+const CELLULAR_CRIT_DBM: f64 = -110.0;
+
+/// Color for a metric that gets worse as it rises
+fn high_warning(value: f64, warn: f64, crit: f64) -> Color32 {
+    if value >= crit {
+        Color32::RED
+    } else if value >= warn {
+        Color32::YELLOW
+    } else {
+        Color32::GREEN
+    }
+}
+
+/// Color for a metric that gets worse as it falls
+fn low_warning(value: f64, warn: f64, crit: f64) -> Color32 {
+    if value <= crit {
+        Color32::RED
+    } else if value <= warn {
+        Color32::YELLOW
+    } else {
+        Color32::GREEN
+    }
+}
+
+/// Draw one `name: value unit` line, colored by `color` if the value was reported
+fn metric(ui: &mut eframe::egui::Ui, name: &str, value: Option<f64>, unit: &str, color: impl Fn(f64) -> Color32) -> () {
+    ui.horizontal(|ui| {
+        ui.label(format!("{}:", name));
+        match value {
+            Some(value) => ui.label(RichText::new(format!("{:.1} {}", value, unit)).color(color(value))),
+            None => ui.label("---"),
+        };
+    });
+}
 
 /// Displays information about the device and the properties of the connection
 ///     made to the device.
 /// It also provides a connection settings panel.
 ///
 pub struct DeviceDisplay {
-    _account_id: String, // Example
+    /// Latest status report
+    status: Option<DeviceStatus>,
+    /// When the last poll was sent, ms since the unix epoch
+    last_poll: f64,
+    /// Set when the backend cannot report device status. Polling stops.
+    unavailable: Option<String>,
+    /// Every report received this login, for the trend charts
+    history: history::TelemetryHistory,
+    /// Last address the device reported, used to open the direct link
+    device_address: Option<String>,
+    /// Address typed by the user, used instead of the reported one when set
+    address_str: String,
+    /// Secret of a device key, sent to the device to open the direct link. Zeroized on reset.
+    key_str: String,
+    /// Show the key in plain text
+    reveal_key: bool,
+    /// Acquisition settings editor behind the Settings link
+    settings: settings::SettingsDialog,
+    status_requests: RequestChannel<Result<DeviceStatus, BackendError>>,
 }
 
 impl DeviceDisplay {
     pub fn new() -> Self {
         DeviceDisplay {
-            _account_id: String::from(""),
+            status: None,
+            last_poll: 0.0,
+            unavailable: None,
+            history: history::TelemetryHistory::new(),
+            device_address: None,
+            address_str: String::new(),
+            key_str: String::new(),
+            reveal_key: false,
+            settings: settings::SettingsDialog::new(),
+            status_requests: RequestChannel::new(),
+        }
+    }
+
+    /// Clear anything held for the previous user
+    pub fn reset(&mut self) -> () {
+        self.status_requests.cancel();
+
+        self.status = None;
+        self.last_poll = 0.0;
+        self.unavailable = None;
+        self.history.clear();
+        self.device_address = None;
+        self.address_str.clear();
+        self.key_str.zeroize();
+        self.reveal_key = false;
+        self.settings.reset();
+    }
+
+    /// Request the device status. The response is picked up by `receive_status`
+    fn poll_status(&mut self, ctx: &eframe::egui::Context, store: &AppStore) -> () {
+        let token = match &store.state().token {
+            Some(token) => token.clone(),
+            None => return,
+        };
+
+        self.last_poll = time::now_ms();
+        let responder = self.status_requests.issue(ctx);
+        let request = store.backend().device_status(&token);
+
+        wasm_bindgen_futures::spawn_local(async move {
+            responder.respond(request.await);
+        });
+    }
+
+    fn receive_status(&mut self, store: &AppStore) -> () {
+        match self.status_requests.poll() {
+            Some(Ok(status)) => self.update_status(status),
+            Some(Err(BackendError::Unsupported)) => {
+                self.unavailable = Some("This server does not report device status".to_string());
+            }
+            Some(Err(e)) => store.report_error("Device status", &e),
+            None => {}
         }
     }
 
+    /// Take in a status report from either the server or the direct link
+    fn update_status(&mut self, status: DeviceStatus) -> () {
+        if let Some(address) = &status.ipv4 {
+            self.device_address = Some(address.clone());
+        }
+        self.history.record(&status);
+        self.status = Some(status);
+    }
+
+    /// Device the direct link should connect to, once an address and a key are known
+    fn link_target(&self) -> Option<LinkTarget> {
+        let typed = Some(self.address_str.trim()).filter(|address| !address.is_empty());
+        let url = DeviceLink::url_for(typed.or(self.device_address.as_deref()))?;
+        let key = Some(self.key_str.trim()).filter(|key| !key.is_empty())?;
+
+        Some(LinkTarget { url, key: key.to_string() })
+    }
+
+    /// Seconds since the device last reported, None if it never has
+    fn last_seen_secs(&self) -> Option<f64> {
+        let last_seen = self.status.as_ref()?.last_seen.as_deref()?;
+        time::parse_datetime(last_seen).map(|ms| (time::now_ms() - ms) / 1000.0)
+    }
+
     /// Helper function to assign contents for the device window
-    fn show_device_info(&mut self, ui: &mut eframe::egui::Ui) -> () {
+    fn show_device_info(&mut self, ui: &mut eframe::egui::Ui, store: &AppStore, link: &DeviceLink) -> () {
+        let last_seen = self.last_seen_secs();
+        let online = last_seen.is_some_and(|secs| secs < OFFLINE_AFTER_SECS);
+        // Values from an offline device are stale, so only show them while it is online
+        let status = self.status.as_ref().filter(|_| online);
+
         // Connection Info Section
         ui.label(RichText::new("Connection").heading().underline());
-        ui.label(RichText::new("OFFLINE").color(Color32::RED));
+        ui.horizontal(|ui| {
+            if online {
+                ui.label(RichText::new("ONLINE").color(Color32::GREEN));
+            } else {
+                ui.label(RichText::new("OFFLINE").color(Color32::RED));
+            }
+            match last_seen {
+                Some(secs) => ui.label(format!("Last seen {} ago", time::format_duration(secs))),
+                None => ui.label("Never seen"),
+            };
+        });
+        if let Some(reason) = &self.unavailable {
+            ui.label(RichText::new(reason).color(Color32::YELLOW).small());
+        }
+        ui.label(RichText::new(link.describe()).small());
+        eframe::egui::CollapsingHeader::new("Direct link")
+            .default_open(self.key_str.is_empty())
+            .show(ui, |ui| {
+                ui.label("Device address:");
+                let reported = self.device_address.clone().unwrap_or_else(|| "IP address on the local network".to_string());
+                ui.add(eframe::egui::TextEdit::singleline(&mut self.address_str).hint_text(reported));
+
+                ui.label("Device key:");
+                password_field(ui, &mut self.key_str, &mut self.reveal_key)
+                    .on_hover_text("Secret of one of your device keys, from the Account window");
+            });
         if ui.link("Settings").clicked() {
-            // TODO: Implement
+            self.settings.open(ui.ctx(), store);
         }
+
+        // The direct link knows best whether it is up
+        let connection = match link.mode() {
+            ConnectionType::Direct => Some(ConnectionType::Direct),
+            ConnectionType::Proxy => status.and_then(|s| s.connection),
+        };
+        let type_color = |kind: ConnectionType| if connection == Some(kind) { Color32::GREEN } else { Color32::RED };
         ui.group(|ui| {
             ui.label("Type:");
             ui.horizontal_wrapped(|ui| {
                 ui.group(|ui| {
-                    ui.label(RichText::new("DIRECT").color(Color32::RED));
+                    ui.label(RichText::new("DIRECT").color(type_color(ConnectionType::Direct)));
                     ui.label("|");
-                    ui.label(RichText::new("PROXY").color(Color32::RED));
+                    ui.label(RichText::new("PROXY").color(type_color(ConnectionType::Proxy)));
                 });
             });
-            ui.label("IPv4: ---");
-            ui.label("IPv6: ---");
-            ui.label("Cellular Strength: ---");
+            ui.label(format!("IPv4: {}", status.and_then(|s| s.ipv4.as_deref()).unwrap_or("---")));
+            ui.label(format!("IPv6: {}", status.and_then(|s| s.ipv6.as_deref()).unwrap_or("---")));
+            metric(ui, "Cellular Strength", status.and_then(|s| s.cellular_dbm), "dBm",
+                |v| low_warning(v, CELLULAR_WARN_DBM, CELLULAR_CRIT_DBM));
         });
         ui.add_space(5.0);
 
         // Hardware info section
         ui.label(RichText::new("Hardware").heading().underline());
         ui.group(|ui| {
-            ui.label("CPU Temperature: ---");
-            ui.label("CPU Load: ---");
-            ui.label("Battery Temperature: ---");
-            ui.label("Battery Remaining Capacity: ---");
+            metric(ui, "CPU Temperature", status.and_then(|s| s.cpu_temp_c), "°C",
+                |v| high_warning(v, CPU_TEMP_WARN_C, CPU_TEMP_CRIT_C));
+            metric(ui, "CPU Load", status.and_then(|s| s.cpu_load_pct), "%",
+                |v| high_warning(v, CPU_LOAD_WARN_PCT, CPU_LOAD_CRIT_PCT));
+            metric(ui, "Battery Temperature", status.and_then(|s| s.battery_temp_c), "°C",
+                |v| high_warning(v, BATTERY_TEMP_WARN_C, BATTERY_TEMP_CRIT_C));
+            metric(ui, "Battery Remaining Capacity", status.and_then(|s| s.battery_pct), "%",
+                |v| low_warning(v, BATTERY_WARN_PCT, BATTERY_CRIT_PCT));
         });
+        ui.add_space(5.0);
+
+        // Trends over this login
+        eframe::egui::CollapsingHeader::new(RichText::new("History").heading().underline())
+            .default_open(false)
+            .show(ui, |ui| {
+                self.history.show(ui);
+            });
     }
 
     /// Performs the draw step for the device window
-    pub fn draw(&mut self, ctx: &eframe::egui::Context) -> () {
+    pub fn draw(&mut self, ctx: &eframe::egui::Context, store: &AppStore) -> () {
+        let link = store.link();
+
+        // Reports come straight from the device while the direct link is up,
+        //     otherwise poll the server for them
+        if let Some(mut status) = link.take_status() {
+            status.connection = Some(ConnectionType::Direct);
+            self.update_status(status);
+        }
+        let direct = link.mode() == ConnectionType::Direct;
+        let due = time::now_ms() - self.last_poll >= POLL_INTERVAL_MS;
+        if !direct && due && self.unavailable.is_none() && !self.status_requests.is_pending() {
+            self.poll_status(ctx, store);
+        }
+        self.receive_status(store);
+
+        link.set_target(self.link_target());
+
+        // Keep polling and the last seen time ticking without user input
+        ctx.request_repaint_after(std::time::Duration::from_secs(1));
+
         eframe::egui::Window::new("Device").show(ctx, |ui| {
-            self.show_device_info(ui);
+            self.show_device_info(ui, store, &link);
         });
+
+        self.settings.draw(ctx, store);
     }
 }
@@ -0,0 +1,245 @@
+//! Rolling telemetry history and charts for the Device window
+//!
+
+use std::collections::VecDeque;
+
+use eframe::egui;
+use egui_plot::{Legend, Line, Plot, PlotPoints};
+
+use crate::backend::DeviceStatus;
+use crate::time;
+
+/// Samples kept, one hour at the default poll interval
+const HISTORY_LEN: usize = 1800;
+/// Only the most recent stretch of samples is used to fit the discharge slope, s
+const ESTIMATE_WINDOW_SECS: f64 = 600.0;
+/// Span the battery readings must cover before runtime is estimated, s
+const MIN_ESTIMATE_SPAN_SECS: f64 = 60.0;
+
+/// One status report
+struct TelemetrySample {
+    /// Time the device reported, ms since the unix epoch
+    time_ms: f64,
+    cpu_temp_c: Option<f64>,
+    cpu_load_pct: Option<f64>,
+    battery_temp_c: Option<f64>,
+    battery_pct: Option<f64>,
+    cellular_dbm: Option<f64>,
+}
+
+/// Battery runtime estimated from the discharge slope
+pub struct RuntimeEstimate {
+    /// Seconds until the battery is empty at the current drain
+    pub remaining_secs: f64,
+    /// Percent per minute
+    pub drain_pct_per_min: f64,
+}
+
+/// Rolling history of device status reports
+pub struct TelemetryHistory {
+    samples: VecDeque<TelemetrySample>,
+}
+
+impl TelemetryHistory {
+    pub fn new() -> Self {
+        TelemetryHistory {
+            samples: VecDeque::new(),
+        }
+    }
+
+    pub fn clear(&mut self) -> () {
+        self.samples.clear();
+    }
+
+    /// Add a status report. Reports without a timestamp, or repeating the
+    ///     latest one, are ignored.
+    pub fn record(&mut self, status: &DeviceStatus) -> () {
+        let time_ms = match status.last_seen.as_deref().and_then(time::parse_datetime) {
+            Some(time_ms) => time_ms,
+            None => return,
+        };
+        if self.samples.back().is_some_and(|last| last.time_ms >= time_ms) {
+            return;
+        }
+
+        self.samples.push_back(TelemetrySample {
+            time_ms,
+            cpu_temp_c: status.cpu_temp_c,
+            cpu_load_pct: status.cpu_load_pct,
+            battery_temp_c: status.battery_temp_c,
+            battery_pct: status.battery_pct,
+            cellular_dbm: status.cellular_dbm,
+        });
+
+        while self.samples.len() > HISTORY_LEN {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Fit a line through recent battery readings and extrapolate to empty.
+    ///
+    /// None while there is not enough data or the battery is not draining.
+    pub fn battery_runtime(&self) -> Option<RuntimeEstimate> {
+        let latest = self.samples.back()?.time_ms;
+        let points: Vec<(f64, f64)> = self.samples.iter()
+            .filter(|s| latest - s.time_ms <= ESTIMATE_WINDOW_SECS * 1000.0)
+            .filter_map(|s| s.battery_pct.map(|pct| ((s.time_ms - latest) / 1000.0, pct)))
+            .collect();
+
+        let span = points.last()?.0 - points.first()?.0;
+        if points.len() < 3 || span < MIN_ESTIMATE_SPAN_SECS {
+            return None;
+        }
+
+        // Least squares slope, percent per second
+        let n = points.len() as f64;
+        let mean_t = points.iter().map(|(t, _)| t).sum::<f64>() / n;
+        let mean_pct = points.iter().map(|(_, pct)| pct).sum::<f64>() / n;
+        let covariance: f64 = points.iter().map(|(t, pct)| (t - mean_t) * (pct - mean_pct)).sum();
+        let variance: f64 = points.iter().map(|(t, _)| (t - mean_t).powi(2)).sum();
+        let slope = covariance / variance;
+        if !slope.is_finite() || slope >= 0.0 {
+            return None;
+        }
+
+        // Extrapolate from the fitted value now rather than the noisy last reading
+        let current = mean_pct - slope * mean_t;
+        Some(RuntimeEstimate {
+            remaining_secs: (current / -slope).max(0.0),
+            drain_pct_per_min: -slope * 60.0,
+        })
+    }
+
+    /// Points for one metric, x in minutes relative to now
+    fn series(&self, now_ms: f64, metric: impl Fn(&TelemetrySample) -> Option<f64>) -> PlotPoints {
+        self.samples.iter()
+            .filter_map(|s| metric(s).map(|value| [(s.time_ms - now_ms) / 60_000.0, value]))
+            .collect()
+    }
+
+    /// Draw the history charts and the runtime estimate
+    pub fn show(&self, ui: &mut egui::Ui) -> () {
+        match self.battery_runtime() {
+            Some(estimate) => ui.label(format!(
+                "Estimated battery runtime: {} (draining {:.2} %/min)",
+                time::format_duration(estimate.remaining_secs),
+                estimate.drain_pct_per_min
+            )),
+            None => ui.label("Estimated battery runtime: ---"),
+        };
+
+        if self.samples.is_empty() {
+            ui.label("No telemetry recorded yet");
+            return;
+        }
+
+        let now = time::now_ms();
+
+        Plot::new("device_temperature_graph")
+            .legend(Legend::default())
+            .x_axis_label("Minutes")
+            .y_axis_label("°C")
+            .width(400.0)
+            .height(150.0)
+            .show(ui, |ui| {
+                ui.line(Line::new(self.series(now, |s| s.cpu_temp_c)).name("CPU Temperature").color(egui::Color32::RED));
+                ui.line(Line::new(self.series(now, |s| s.battery_temp_c)).name("Battery Temperature").color(egui::Color32::YELLOW));
+            });
+
+        Plot::new("device_load_graph")
+            .legend(Legend::default())
+            .x_axis_label("Minutes")
+            .y_axis_label("%")
+            .include_y(0.0)
+            .include_y(100.0)
+            .width(400.0)
+            .height(150.0)
+            .show(ui, |ui| {
+                ui.line(Line::new(self.series(now, |s| s.cpu_load_pct)).name("CPU Load").color(egui::Color32::BLUE));
+                ui.line(Line::new(self.series(now, |s| s.battery_pct)).name("Battery Capacity").color(egui::Color32::GREEN));
+            });
+
+        Plot::new("device_cellular_graph")
+            .legend(Legend::default())
+            .x_axis_label("Minutes")
+            .y_axis_label("dBm")
+            .width(400.0)
+            .height(150.0)
+            .show(ui, |ui| {
+                ui.line(Line::new(self.series(now, |s| s.cellular_dbm)).name("Cellular Strength").color(egui::Color32::LIGHT_BLUE));
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    /// History holding a battery reading at each `(secs, percent)`
+    fn history(readings: &[(f64, f64)]) -> TelemetryHistory {
+        let mut history = TelemetryHistory::new();
+        for (secs, pct) in readings {
+            history.samples.push_back(TelemetrySample {
+                time_ms: secs * 1000.0,
+                cpu_temp_c: None,
+                cpu_load_pct: None,
+                battery_temp_c: None,
+                battery_pct: Some(*pct),
+                cellular_dbm: None,
+            });
+        }
+
+        history
+    }
+
+    /// Readings every 30 s for ten minutes, draining one percent a minute down to 90 %
+    fn draining() -> Vec<(f64, f64)> {
+        (0..=20).map(|i| (1000.0 + i as f64 * 30.0, 100.0 - i as f64 * 0.5)).collect()
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-6
+    }
+
+    #[wasm_bindgen_test]
+    fn steady_drain_is_extrapolated_to_empty() {
+        let estimate = history(&draining()).battery_runtime().unwrap();
+
+        assert!(close(estimate.drain_pct_per_min, 1.0));
+        assert!(close(estimate.remaining_secs, 90.0 * 60.0));
+    }
+
+    #[wasm_bindgen_test]
+    fn too_few_readings_give_no_estimate() {
+        assert!(history(&[]).battery_runtime().is_none());
+        assert!(history(&[(0.0, 100.0), (120.0, 98.0)]).battery_runtime().is_none());
+    }
+
+    #[wasm_bindgen_test]
+    fn short_spans_give_no_estimate() {
+        let readings: Vec<(f64, f64)> = (0..5).map(|i| (i as f64 * 10.0, 100.0 - i as f64)).collect();
+        assert!(history(&readings).battery_runtime().is_none());
+    }
+
+    #[wasm_bindgen_test]
+    fn flat_or_charging_batteries_give_no_estimate() {
+        let flat: Vec<(f64, f64)> = (0..10).map(|i| (i as f64 * 30.0, 80.0)).collect();
+        let charging: Vec<(f64, f64)> = (0..10).map(|i| (i as f64 * 30.0, 50.0 + i as f64)).collect();
+
+        assert!(history(&flat).battery_runtime().is_none());
+        assert!(history(&charging).battery_runtime().is_none());
+    }
+
+    #[wasm_bindgen_test]
+    fn readings_before_the_window_are_ignored() {
+        // Charging more than ten minutes before the latest reading
+        let mut readings: Vec<(f64, f64)> = (0..10).map(|i| (i as f64 * 30.0, 40.0 + i as f64 * 5.0)).collect();
+        readings.extend(draining());
+        let estimate = history(&readings).battery_runtime().unwrap();
+
+        assert!(close(estimate.drain_pct_per_min, 1.0));
+        assert!(close(estimate.remaining_secs, 90.0 * 60.0));
+    }
+}
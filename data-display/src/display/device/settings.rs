@@ -0,0 +1,262 @@
+//! Device acquisition settings dialog opened from the Device window
+//!
+
+use eframe::egui::{Color32, ComboBox, DragValue, RichText};
+
+use crate::backend::{
+    BackendError, DeviceConfig, ACCEL_RANGES_G, ACCEL_RATE_LIMITS_HZ, DAC_RATE_LIMITS_HZ, DAC_VREF_LIMITS_V,
+    GPS_RATE_LIMITS_HZ, GYRO_RANGES_DPS, GYRO_RATE_LIMITS_HZ, SENSOR_CHANNELS,
+};
+
+use crate::display::request::RequestChannel;
+use crate::display::store::AppStore;
+
+/// Draw a sample rate editor limited to the group's supported range
+fn rate_field(ui: &mut eframe::egui::Ui, label: &str, rate: &mut f64, (min, max): (f64, f64)) -> () {
+    ui.label(label);
+    ui.add(DragValue::new(rate).range(min..=max).speed(0.1).suffix(" Hz"));
+    ui.label(RichText::new(format!("{} - {} Hz", min, max)).small());
+    ui.end_row();
+}
+
+/// Edits the acquisition settings on the device and confirms the device applied them
+pub struct SettingsDialog {
+    open: bool,
+    /// Settings last read from the device
+    current: Option<DeviceConfig>,
+    /// Settings being edited
+    draft: DeviceConfig,
+    /// Set once a save was acknowledged, until the read back arrives
+    awaiting_confirm: bool,
+    /// Outcome of the last save, and whether it succeeded
+    message: Option<(bool, String)>,
+
+    /// Results of reading the config, used both to load it and to confirm a save
+    load_requests: RequestChannel<Result<DeviceConfig, BackendError>>,
+    save_requests: RequestChannel<Result<(), BackendError>>,
+}
+
+impl SettingsDialog {
+    pub fn new() -> Self {
+        SettingsDialog {
+            open: false,
+            current: None,
+            draft: DeviceConfig::default(),
+            awaiting_confirm: false,
+            message: None,
+
+            load_requests: RequestChannel::new(),
+            save_requests: RequestChannel::new(),
+        }
+    }
+
+    /// Close the dialog and forget the previous user's settings
+    pub fn reset(&mut self) -> () {
+        self.load_requests.cancel();
+        self.save_requests.cancel();
+
+        self.open = false;
+        self.current = None;
+        self.draft = DeviceConfig::default();
+        self.awaiting_confirm = false;
+        self.message = None;
+    }
+
+    /// Show the dialog and read the device's current settings into it
+    pub fn open(&mut self, ctx: &eframe::egui::Context, store: &AppStore) -> () {
+        self.open = true;
+        self.message = None;
+        self.awaiting_confirm = false;
+        self.load(ctx, store);
+    }
+
+    fn load(&mut self, ctx: &eframe::egui::Context, store: &AppStore) -> () {
+        let token = match &store.state().token {
+            Some(token) => token.clone(),
+            None => return,
+        };
+
+        let responder = self.load_requests.issue(ctx);
+        let request = store.backend().device_config(&token);
+
+        wasm_bindgen_futures::spawn_local(async move {
+            responder.respond(request.await);
+        });
+    }
+
+    fn save(&mut self, ctx: &eframe::egui::Context, store: &AppStore) -> () {
+        let token = match &store.state().token {
+            Some(token) => token.clone(),
+            None => return,
+        };
+
+        self.message = None;
+        let responder = self.save_requests.issue(ctx);
+        let request = store.backend().set_device_config(&token, &self.draft);
+
+        wasm_bindgen_futures::spawn_local(async move {
+            responder.respond(request.await);
+        });
+    }
+
+    /// Apply any responses that have arrived. A successful save is followed by
+    ///     a read back, which must match what was sent.
+    fn receive(&mut self, ctx: &eframe::egui::Context, store: &AppStore) -> () {
+        match self.save_requests.poll() {
+            Some(Ok(())) => {
+                self.awaiting_confirm = true;
+                self.load(ctx, store);
+            }
+            Some(Err(BackendError::Status(422))) => {
+                self.message = Some((false, "The device rejected these settings.".to_string()));
+            }
+            Some(Err(e)) => {
+                store.report_error("Device settings save", &e);
+                self.message = Some((false, format!("Could not send the settings. {}", e)));
+            }
+            None => {}
+        }
+
+        match self.load_requests.poll() {
+            Some(Ok(mut config)) => {
+                // The device may list its channels in any order
+                config.sort_channels();
+                if self.awaiting_confirm {
+                    self.awaiting_confirm = false;
+                    self.message = Some(if config == self.draft {
+                        (true, "Settings confirmed by the device.".to_string())
+                    } else {
+                        (false, "The device reports different settings than were sent. Showing what it applied.".to_string())
+                    });
+                }
+                self.draft = config.clone();
+                self.current = Some(config);
+            }
+            Some(Err(BackendError::Unsupported)) => {
+                self.message = Some((false, "This server cannot configure the device.".to_string()));
+            }
+            Some(Err(e)) => {
+                store.report_error("Device settings load", &e);
+                self.message = Some((false, format!("Could not read the settings. {}", e)));
+            }
+            None => {}
+        }
+    }
+
+    /// Helper function to draw the editor fields
+    fn show_fields(&mut self, ui: &mut eframe::egui::Ui) -> () {
+        ui.label(RichText::new("Sample Rates").strong());
+        eframe::egui::Grid::new("device_rates").num_columns(3).show(ui, |ui| {
+            rate_field(ui, "GPS", &mut self.draft.gps_rate_hz, GPS_RATE_LIMITS_HZ);
+            rate_field(ui, "Accelerometer", &mut self.draft.accel_rate_hz, ACCEL_RATE_LIMITS_HZ);
+            rate_field(ui, "Gyroscope", &mut self.draft.gyro_rate_hz, GYRO_RATE_LIMITS_HZ);
+            rate_field(ui, "DAC", &mut self.draft.dac_rate_hz, DAC_RATE_LIMITS_HZ);
+        });
+        ui.add_space(5.0);
+
+        ui.label(RichText::new("Channels").strong());
+        for (group, channels) in SENSOR_CHANNELS {
+            ui.horizontal(|ui| {
+                ui.label(format!("{}:", group));
+                for channel in channels.iter() {
+                    let mut enabled = self.draft.enabled_channels.iter().any(|c| c == channel);
+                    if ui.checkbox(&mut enabled, *channel).changed() {
+                        if enabled {
+                            self.draft.enabled_channels.push(channel.to_string());
+                            self.draft.sort_channels();
+                        } else {
+                            self.draft.enabled_channels.retain(|c| c != channel);
+                        }
+                    }
+                }
+            });
+        }
+        ui.add_space(5.0);
+
+        ui.label(RichText::new("Ranges").strong());
+        eframe::egui::Grid::new("device_ranges").num_columns(2).show(ui, |ui| {
+            ui.label("Accelerometer");
+            ComboBox::from_id_salt("accel_range")
+                .selected_text(format!("±{} g", self.draft.accel_range_g))
+                .show_ui(ui, |ui| {
+                    for range in ACCEL_RANGES_G {
+                        ui.selectable_value(&mut self.draft.accel_range_g, range, format!("±{} g", range));
+                    }
+                });
+            ui.end_row();
+
+            ui.label("Gyroscope");
+            ComboBox::from_id_salt("gyro_range")
+                .selected_text(format!("±{} °/s", self.draft.gyro_range_dps))
+                .show_ui(ui, |ui| {
+                    for range in GYRO_RANGES_DPS {
+                        ui.selectable_value(&mut self.draft.gyro_range_dps, range, format!("±{} °/s", range));
+                    }
+                });
+            ui.end_row();
+
+            let (min, max) = DAC_VREF_LIMITS_V;
+            ui.label("DAC Reference");
+            ui.add(DragValue::new(&mut self.draft.dac_vref).range(min..=max).speed(0.01).suffix(" V"));
+            ui.end_row();
+        });
+    }
+
+    /// Performs the draw step for the settings dialog
+    pub fn draw(&mut self, ctx: &eframe::egui::Context, store: &AppStore) -> () {
+        if !self.open {
+            return;
+        }
+        self.receive(ctx, store);
+
+        let mut open = self.open;
+        eframe::egui::Window::new("Device Settings")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                if self.current.is_none() {
+                    if self.load_requests.is_pending() {
+                        ui.horizontal(|ui| {
+                            ui.spinner();
+                            ui.label("Reading settings from the device...");
+                        });
+                    }
+                    if let Some((_, message)) = &self.message {
+                        ui.label(RichText::new(message).color(Color32::RED));
+                    }
+                    return;
+                }
+
+                self.show_fields(ui);
+                ui.separator();
+
+                let problems = self.draft.validate();
+                for problem in &problems {
+                    ui.label(RichText::new(problem).color(Color32::RED).small());
+                }
+
+                if let Some((success, message)) = &self.message {
+                    ui.label(RichText::new(message).color(if *success { Color32::GREEN } else { Color32::RED }));
+                }
+
+                let busy = self.save_requests.is_pending() || self.load_requests.is_pending();
+                let changed = self.current.as_ref() != Some(&self.draft);
+
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(!busy && changed && problems.is_empty(), eframe::egui::Button::new("Apply")).clicked() {
+                        self.save(ui.ctx(), store);
+                    }
+                    if ui.add_enabled(!busy && changed, eframe::egui::Button::new("Revert")).clicked() {
+                        if let Some(current) = &self.current {
+                            self.draft = current.clone();
+                        }
+                        self.message = None;
+                    }
+                    if busy {
+                        ui.spinner();
+                    }
+                });
+            });
+        self.open = open;
+    }
+}
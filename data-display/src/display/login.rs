@@ -1,146 +1,548 @@
 //! The login manager
 
-extern crate client;
-//use client::api::{auth};
-use client::api::{user};
+use eframe::egui::{Color32, RichText};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::backend::{AuthToken, BackendError};
+use crate::time;
+
+use super::request::RequestChannel;
+use super::store::{AppEvent, AppStore, RememberedLogin};
+
+/// Failed attempts allowed before logins are locked out
+const FREE_ATTEMPTS: u8 = 3;
+/// Lockout after the first attempt past `FREE_ATTEMPTS`, doubled for every further failure
+const BASE_LOCKOUT_SECS: f64 = 5.0;
+const MAX_LOCKOUT_SECS: f64 = 300.0;
+
+/// Minimum length for new passwords
+pub const MIN_PASSWORD_LEN: usize = 10;
+
+/// Check a new password against the strength rules.
+///
+/// Returns each rule's description and whether it is met.
+pub fn password_rules(username: &str, password: &str) -> Vec<(String, bool)> {
+    let username = username.trim().to_lowercase();
+
+    vec![
+        (format!("At least {} characters", MIN_PASSWORD_LEN), password.chars().count() >= MIN_PASSWORD_LEN),
+        ("Upper and lower case letters".to_string(),
+            password.chars().any(|c| c.is_uppercase()) && password.chars().any(|c| c.is_lowercase())),
+        ("At least one number".to_string(), password.chars().any(|c| c.is_ascii_digit())),
+        ("At least one symbol".to_string(), password.chars().any(|c| !c.is_alphanumeric() && !c.is_whitespace())),
+        ("Does not contain the username".to_string(),
+            username.is_empty() || !password.to_lowercase().contains(&username)),
+    ]
+}
+
+/// Draw a password field with a toggle to reveal its contents
+pub fn password_field(ui: &mut eframe::egui::Ui, password: &mut String, revealed: &mut bool) -> eframe::egui::Response {
+    ui.horizontal(|ui| {
+        let response = ui.add(eframe::egui::TextEdit::singleline(password).password(!*revealed));
+        ui.toggle_value(revealed, if *revealed { "Hide" } else { "Show" });
+        response
+    }).inner
+}
+
+/// Draw each password rule with a mark showing whether it is met
+pub fn password_checklist(ui: &mut eframe::egui::Ui, rules: &[(String, bool)]) -> () {
+    for (rule, met) in rules {
+        let (mark, color) = if *met { ("✔", Color32::GREEN) } else { ("✘", Color32::RED) };
+        ui.label(RichText::new(format!("{} {}", mark, rule)).color(color).small());
+    }
+}
+
+/// Which form the window is showing while logged out
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum EntryMode {
+    Login,
+    CreateUser,
+}
 
 /// Handles drawing the login window and facilitates authentication with the backend api.
 pub struct LoginDisplay {
-    /// Pointer received at creation from the display manager to control
-    ///     window drawing based on login state.
-    logged_in: *mut bool,
-    username: *mut String,
+    /// Tracker for failed login attempts, reset on success
+    failed_attempts: u8,
+    /// No login is sent before this time, ms since the unix epoch
+    locked_until: f64,
+    /// Reason the last login attempt failed
+    login_error: Option<String>,
 
-    /// Tracker for failed login attempts
-    _failed_attempts: u8, // unimplemented
+    /// Which form is shown while logged out
+    mode: EntryMode,
+    /// Outcome of the last account creation, and whether it succeeded
+    create_message: Option<(bool, String)>,
 
     /// Username field passed into a widget
     username_str: String,
-    /// Password field passed into a widget
+    /// Password field passed into a widget. Zeroized once submitted.
     password_str: String,
+    /// Repeated password when creating an account. Zeroized once submitted.
+    confirm_str: String,
+    /// Show the password fields in plain text
+    reveal_password: bool,
+    /// Keep the login across page reloads
+    remember: bool,
+
+    /// Results of login requests, tagged with the username that was tried
+    login_requests: RequestChannel<(String, Result<AuthToken, BackendError>)>,
+    /// Results of user creation requests
+    create_requests: RequestChannel<Result<(), BackendError>>,
+    /// Results of checking a remembered login with the server, tagged with the login being checked
+    restore_requests: RequestChannel<(RememberedLogin, Result<String, BackendError>)>,
 }
 
 impl LoginDisplay {
-    pub fn new(logged_in: *mut bool, username: *mut String) -> Self {
+    pub fn new() -> Self {
         LoginDisplay {
-            logged_in: logged_in,
-            username: username,
-            _failed_attempts: 0,
+            failed_attempts: 0,
+            locked_until: 0.0,
+            login_error: None,
+
+            mode: EntryMode::Login,
+            create_message: None,
 
             username_str: String::from(""),
             password_str: String::from(""),
-        }
-    }
+            confirm_str: String::from(""),
+            reveal_password: false,
+            remember: false,
 
-    /// Wrapper for the unsafe operation to dereference the self.logged_in property
-    fn get_logged_in(&self) -> bool {
-        return unsafe { *self.logged_in };
+            login_requests: RequestChannel::new(),
+            create_requests: RequestChannel::new(),
+            restore_requests: RequestChannel::new(),
+        }
     }
 
     /// Attempt to authenticate with the server.
     ///
+    /// The result is handled by `receive_login`.
+    fn login(&mut self, ctx: &eframe::egui::Context, store: &AppStore) -> () {
+        let username = self.username_str.trim().to_string();
+        let password = Zeroizing::new(self.password_str.clone());
+        self.clear_secrets();
+
+        let responder = self.login_requests.issue(ctx);
+        let request = store.backend().login(&username, &password);
+
+        wasm_bindgen_futures::spawn_local(async move {
+            responder.respond((username, request.await));
+        });
+    }
+
     /// If successful, notify the rest of the client to update and
     ///     enable drawing of other windows.
     ///
-    /// If failed, noify user and track number of failed attempts.
-    fn login(&mut self) -> () {
-        // Verify With Server...
+    /// If failed, notify user and track number of failed attempts.
+    fn receive_login(&mut self, store: &AppStore) -> () {
+        let (username, result) = match self.login_requests.poll() {
+            Some(response) => response,
+            None => return,
+        };
+
+        match result {
+            // If successful, show sessions window and set fail count to 0
+            Ok(token) => {
+                self.failed_attempts = 0;
+                self.locked_until = 0.0;
+                self.login_error = None;
+                store.publish(AppEvent::LoggedIn { username, token, remember: self.remember });
+            }
+
+            // If the credentials were wrong, increment fail count and back off
+            Err(BackendError::Status(401)) | Err(BackendError::Status(403)) => {
+                self.failed_attempts = self.failed_attempts.saturating_add(1);
+                self.login_error = Some(format!(
+                    "Incorrect username or password ({} failed attempt{})",
+                    self.failed_attempts,
+                    if self.failed_attempts == 1 { "" } else { "s" }
+                ));
+
+                if self.failed_attempts >= FREE_ATTEMPTS {
+                    let doublings = (self.failed_attempts - FREE_ATTEMPTS).min(16) as i32;
+                    let lockout = (BASE_LOCKOUT_SECS * 2f64.powi(doublings)).min(MAX_LOCKOUT_SECS);
+                    self.locked_until = time::now_ms() + lockout * 1000.0;
+                }
+            }
+
+            // Anything else is not the user's fault and does not count against them
+            Err(e) => {
+                web_sys::console::log_1( &format!("Login failed. {}", e).into() );
+                self.login_error = Some(format!("Could not reach the server. {}", e));
+            }
+        }
+    }
+
+    /// Check a login remembered from an earlier visit with the server.
+    ///
+    /// The result is handled by `receive_restore`.
+    pub fn restore(&mut self, ctx: &eframe::egui::Context, store: &AppStore, saved: RememberedLogin) -> () {
+        let responder = self.restore_requests.issue(ctx);
+        let request = store.backend().validate_token(&AuthToken::new(saved.token.clone()));
 
-        // If successful, show sessions window and set fail count to 0
-        unsafe {
-            *self.logged_in = true;
-            *self.username = self.username_str.trim().to_string();
+        wasm_bindgen_futures::spawn_local(async move {
+            responder.respond((saved, request.await));
+        });
+    }
+
+    /// If the remembered token is still good, log back in and reopen the last session.
+    ///
+    /// Otherwise forget it and leave the user at the login form.
+    fn receive_restore(&mut self, store: &AppStore) -> () {
+        let (saved, result) = match self.restore_requests.poll() {
+            Some(response) => response,
+            None => return,
+        };
+
+        match result {
+            Ok(username) if username == saved.username => {
+                self.username_str = username.clone();
+                self.remember = true;
+                store.publish(AppEvent::LoggedIn { username, token: AuthToken::new(saved.token), remember: true });
+                if !saved.last_session.is_empty() {
+                    store.publish(AppEvent::SessionSelected(saved.last_session));
+                }
+            }
+            Ok(_) => {
+                web_sys::console::log_1( &"Remembered login belongs to another user, discarding it".into() );
+                store.publish(AppEvent::LoggedOut);
+            }
+            Err(e) => {
+                web_sys::console::log_1( &format!("Could not restore remembered login. {}", e).into() );
+                store.publish(AppEvent::LoggedOut);
+            }
         }
+    }
+
+    /// Send a new account to the server. The result is handled by `receive_create_user`.
+    fn create_user(&mut self, ctx: &eframe::egui::Context, store: &AppStore) -> () {
+        let username = self.username_str.trim().to_string();
+        let password = Zeroizing::new(self.password_str.clone());
+        self.clear_secrets();
+        self.create_message = None;
+
+        let responder = self.create_requests.issue(ctx);
+        let request = store.backend().create_user(&username, &password);
 
-        // If fail, increment fail count
+        wasm_bindgen_futures::spawn_local(async move {
+            responder.respond(request.await);
+        });
     }
 
-    /// Clear the client state and disable drawing of other windows
-    fn logout(&mut self) -> () {
-        // Clear stored user and hide sessions window (should sessions window cascade?)
-        unsafe {
-            *self.logged_in = false;
+    /// Show the outcome of account creation and return to the login form on success
+    fn receive_create_user(&mut self) -> () {
+        match self.create_requests.poll() {
+            Some(Ok(())) => {
+                self.mode = EntryMode::Login;
+                self.create_message = Some((true, "Account created. You can now log in.".to_string()));
+            }
+            Some(Err(BackendError::Status(409))) => {
+                self.create_message = Some((false, "That username is already taken.".to_string()));
+            }
+            Some(Err(e)) => {
+                web_sys::console::log_1( &format!("Create user failed. {}", e).into() );
+                self.create_message = Some((false, format!("Could not create account. {}", e)));
+            }
+            None => {}
         }
     }
 
-    /// Helper function to draw window contents when we are not logged in
-    fn show_login_entry(&mut self, ui: &mut eframe::egui::Ui) {
-        let username_widget = eframe::egui::TextEdit::singleline(&mut self.username_str);
-        let passwd_widget = eframe::egui::TextEdit::singleline(&mut self.password_str);
+    /// Overwrite the password fields in memory
+    fn clear_secrets(&mut self) -> () {
+        self.password_str.zeroize();
+        self.confirm_str.zeroize();
+        self.reveal_password = false;
+    }
 
+    /// Return the form to its initial state after a logout.
+    ///
+    /// The lockout is kept so logging out does not reset the failed attempt count.
+    pub fn reset(&mut self) -> () {
+        self.login_requests.cancel();
+        self.create_requests.cancel();
+        self.restore_requests.cancel();
+
+        self.clear_secrets();
+        self.username_str.clear();
+        self.remember = false;
+        self.login_error = None;
+        self.create_message = None;
+        self.mode = EntryMode::Login;
+    }
+
+    /// Clear the client state and disable drawing of other windows.
+    ///
+    /// A remembered login is dropped from storage the next time the app saves.
+    fn logout(&mut self, store: &AppStore) -> () {
+        // Other windows are reset by the display manager once the event is applied
+        store.publish(AppEvent::LoggedOut);
+    }
+
+    /// Helper function to draw window contents when we are not logged in
+    fn show_login_entry(&mut self, ui: &mut eframe::egui::Ui, store: &AppStore) {
         let login_btn = eframe::egui::Button::new("Login");
         let new_btn = eframe::egui::Button::new("Create New User");
 
-        // TODO: Show failed login attempt
+        if store.state().session_expired {
+            ui.label(RichText::new("Your login has expired. Please log in again.").color(Color32::YELLOW));
+        }
+
+        if self.restore_requests.is_pending() {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label("Restoring saved login...");
+            });
+        }
 
         ui.label("Username:");
-        ui.add(username_widget);
+        ui.text_edit_singleline(&mut self.username_str);
 
         ui.label("Password:");
-        ui.add(passwd_widget);
-
-        if ui.add(new_btn).clicked() {
-            let username = self.username_str.trim().to_string();
-            let password = self.password_str.trim().to_string();
-
-            let client = client::get_client();
-
-            wasm_bindgen_futures::spawn_local(async move {
-                web_sys::console::log_1( &format!("User: {} | Pass: {}", username, password).into() );
-                let (status, _val) = user::create_user(&client, &username, &password).await;
-            
-                if status == 201 {
-                    web_sys::console::log_1( &format!("Success. Status: {}", status).into() );
-                } else {
-                    web_sys::console::log_1( &format!("Failed. Status: {}", status).into() );
-                }
-            });
-        };
+        let password_response = password_field(ui, &mut self.password_str, &mut self.reveal_password);
 
-        // authentication currently bypassed
-        if ui.add(login_btn).clicked() {
-            // let username = self.username_str.trim().to_string();
-            // let password = self.password_str.trim().to_string();
-
-            // authentication is currently broken
-
-            // let client = client::get_client();
-            
-            // wasm_bindgen_futures::spawn_local(async move {
-            //     let (status, _val, _val2) = auth::user_login(&client, &username, &password,).await;
-            
-            //     if status == 201 {
-            //         web_sys::console::log_1( &format!("Success. Status: {}", status).into() );
-            //     } else {
-            //         web_sys::console::log_1( &format!("Failed. Status: {}", status).into() );
-            //     }
-            // });
-            
-            self.login();
-        };
+        ui.checkbox(&mut self.remember, "Remember me")
+            .on_hover_text("Stay logged in on this browser. Your password is not stored.");
+
+        if let Some((success, message)) = &self.create_message {
+            ui.label(RichText::new(message).color(if *success { Color32::GREEN } else { Color32::RED }));
+        }
+
+        // Hold off while locked out or while a login is already on its way
+        let remaining_lockout = (self.locked_until - time::now_ms()) / 1000.0;
+        let locked = remaining_lockout > 0.0;
+        let pending = self.login_requests.is_pending() || self.restore_requests.is_pending();
+
+        let submitted = password_response.lost_focus() && ui.input(|i| i.key_pressed(eframe::egui::Key::Enter));
+        let can_login = !locked && !pending && !self.username_str.trim().is_empty() && !self.password_str.is_empty();
+
+        ui.horizontal(|ui| {
+            if ui.add_enabled(can_login, login_btn).clicked() || (submitted && can_login) {
+                self.login(ui.ctx(), store);
+            };
+
+            if ui.add(new_btn).clicked() {
+                self.clear_secrets();
+                self.create_message = None;
+                self.mode = EntryMode::CreateUser;
+            };
+        });
+
+        if let Some(error) = &self.login_error {
+            ui.label(RichText::new(error).color(Color32::RED));
+        }
+
+        if locked {
+            ui.label(RichText::new(format!("Too many failed attempts. Try again in {}s", remaining_lockout.ceil()))
+                .color(Color32::RED));
+
+            // Keep the countdown ticking without user input
+            ui.ctx().request_repaint_after(std::time::Duration::from_millis(250));
+        }
+    }
+
+    /// Helper function to draw the account creation form
+    fn show_create_user(&mut self, ui: &mut eframe::egui::Ui, store: &AppStore) {
+        ui.label(RichText::new("Create New User").heading());
+
+        ui.label("Username:");
+        ui.text_edit_singleline(&mut self.username_str);
+
+        ui.label("Password:");
+        password_field(ui, &mut self.password_str, &mut self.reveal_password);
+
+        ui.label("Confirm Password:");
+        ui.add(eframe::egui::TextEdit::singleline(&mut self.confirm_str).password(!self.reveal_password));
+
+        // Strength checklist
+        let rules = password_rules(&self.username_str, &self.password_str);
+        password_checklist(ui, &rules);
+
+        let matches = !self.password_str.is_empty() && self.password_str == self.confirm_str;
+        if !self.confirm_str.is_empty() && !matches {
+            ui.label(RichText::new("Passwords do not match").color(Color32::RED).small());
+        }
+
+        if let Some((success, message)) = &self.create_message {
+            ui.label(RichText::new(message).color(if *success { Color32::GREEN } else { Color32::RED }));
+        }
+
+        let can_create = !self.username_str.trim().is_empty()
+            && rules.iter().all(|(_, met)| *met)
+            && matches
+            && !self.create_requests.is_pending();
+
+        ui.horizontal(|ui| {
+            if ui.add_enabled(can_create, eframe::egui::Button::new("Create Account")).clicked() {
+                self.create_user(ui.ctx(), store);
+            }
+
+            if ui.button("Back to Login").clicked() {
+                self.clear_secrets();
+                self.create_message = None;
+                self.mode = EntryMode::Login;
+            }
+        });
     }
 
     /// Helper function to draw window contents when we are logged in
-    fn show_logged_in(&mut self, ui: &mut eframe::egui::Ui) {
+    fn show_logged_in(&mut self, ui: &mut eframe::egui::Ui, store: &AppStore) {
         let logout_btn = eframe::egui::Button::new("Logout");
 
-        unsafe {
-            ui.label( format!("Logged in as: {}", *self.username));
-        }
+        ui.label( format!("Logged in as: {}", store.state().username));
 
         if ui.add(logout_btn).clicked() {
-            self.logout();
+            self.logout(store);
         }
     }
 
     /// Performs the draw step for the login window
-    pub fn draw(&mut self, ctx: &eframe::egui::Context) -> () {
+    pub fn draw(&mut self, ctx: &eframe::egui::Context, store: &AppStore) -> () {
+        self.receive_login(store);
+        self.receive_create_user();
+        self.receive_restore(store);
+
         eframe::egui::Window::new("Login Manager").show(ctx, |ui| {
-            if !self.get_logged_in() {
-                self.show_login_entry(ui);
+            if store.state().logged_in {
+                self.show_logged_in(ui, store);
+            } else if self.mode == EntryMode::CreateUser {
+                self.show_create_user(ui, store);
             } else {
-                self.show_logged_in(ui);
+                self.show_login_entry(ui, store);
             }
         });
     }
 }
+
+/// Don't leave credentials behind in freed memory
+impl Drop for LoginDisplay {
+    fn drop(&mut self) {
+        self.clear_secrets();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    /// Descriptions of the rules a password breaks
+    fn broken(username: &str, password: &str) -> Vec<String> {
+        password_rules(username, password).into_iter()
+            .filter(|(_, met)| !met)
+            .map(|(rule, _)| rule)
+            .collect()
+    }
+
+    #[wasm_bindgen_test]
+    fn strong_password_meets_every_rule() {
+        assert!(broken("alice", "Correct-Horse-42").is_empty());
+    }
+
+    #[wasm_bindgen_test]
+    fn each_rule_is_checked() {
+        assert_eq!(broken("alice", "Sh0rt!"), vec![format!("At least {} characters", MIN_PASSWORD_LEN)]);
+        assert_eq!(broken("alice", "lowercase-only-42"), vec!["Upper and lower case letters".to_string()]);
+        assert_eq!(broken("alice", "No-Numbers-Here"), vec!["At least one number".to_string()]);
+        assert_eq!(broken("alice", "NoSymbols1234"), vec!["At least one symbol".to_string()]);
+        assert_eq!(broken("alice", "My-ALICE-pass-1"), vec!["Does not contain the username".to_string()]);
+    }
+
+    #[wasm_bindgen_test]
+    fn spaces_are_not_symbols() {
+        assert_eq!(broken("alice", "No Symbols 1234"), vec!["At least one symbol".to_string()]);
+    }
+
+    #[wasm_bindgen_test]
+    fn blank_username_is_not_matched() {
+        assert!(broken("  ", "Correct-Horse-42").is_empty());
+    }
+
+    /// Remembering a login across reloads, against the mock backend
+    #[cfg(not(feature = "tcp-backend"))]
+    mod remember {
+        use std::collections::HashMap;
+        use std::task::{Context, Poll, Waker};
+
+        use wasm_bindgen_test::wasm_bindgen_test;
+
+        use crate::backend::{self, BackendFuture};
+
+        use super::super::*;
+
+        /// Browser storage for a test, kept across simulated page reloads
+        #[derive(Default)]
+        struct MemoryStorage(HashMap<String, String>);
+
+        impl eframe::Storage for MemoryStorage {
+            fn get_string(&self, key: &str) -> Option<String> {
+                self.0.get(key).cloned()
+            }
+
+            fn set_string(&mut self, key: &str, value: String) {
+                self.0.insert(key.to_string(), value);
+            }
+
+            fn flush(&mut self) {}
+        }
+
+        /// Result of a backend call that answers right away, as the mock backend's do
+        fn resolve<T>(mut future: BackendFuture<T>) -> Result<T, BackendError> {
+            match future.as_mut().poll(&mut Context::from_waker(Waker::noop())) {
+                Poll::Ready(result) => result,
+                Poll::Pending => panic!("backend call did not complete immediately"),
+            }
+        }
+
+        #[wasm_bindgen_test]
+        fn remembered_login_survives_a_reload() {
+            let ctx = eframe::egui::Context::default();
+            let mut storage = MemoryStorage::default();
+
+            // First visit: log in to the mock's demo account with "remember me" ticked
+            let mut store = AppStore::new(backend::connect());
+            let token = resolve(store.backend().login("testuser", "testpassword")).unwrap();
+            store.publish(AppEvent::LoggedIn { username: "testuser".to_string(), token, remember: true });
+            store.publish(AppEvent::SessionSelected("2".to_string()));
+            store.process_events();
+            store.save_login(&mut storage);
+
+            // Reload: a fresh backend and store, with only the storage carried over
+            let mut store = AppStore::new(backend::connect());
+            let saved = AppStore::load_login(&storage).expect("login was not remembered");
+            assert_eq!(saved.last_session, "2");
+
+            let mut login = LoginDisplay::new();
+            let validated = resolve(store.backend().validate_token(&AuthToken::new(saved.token.clone())));
+            login.restore_requests.issue(&ctx).respond((saved, validated));
+            login.receive_restore(&store);
+            store.process_events();
+
+            assert!(store.state().logged_in);
+            assert!(store.state().remember);
+            assert_eq!(store.state().username, "testuser");
+            assert_eq!(store.state().current_session, "2");
+        }
+
+        #[wasm_bindgen_test]
+        fn forgotten_login_is_not_restored() {
+            let mut storage = MemoryStorage::default();
+
+            let mut store = AppStore::new(backend::connect());
+            let token = resolve(store.backend().login("testuser", "testpassword")).unwrap();
+            store.publish(AppEvent::LoggedIn { username: "testuser".to_string(), token, remember: false });
+            store.process_events();
+            store.save_login(&mut storage);
+
+            assert!(AppStore::load_login(&storage).is_none());
+        }
+
+        #[wasm_bindgen_test]
+        fn unknown_tokens_are_not_restored() {
+            let reloaded = backend::connect();
+            let token = AuthToken::new("0".repeat(64));
+
+            assert!(matches!(resolve(reloaded.validate_token(&token)), Err(BackendError::Unauthorized)));
+        }
+    }
+}
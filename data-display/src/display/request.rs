@@ -0,0 +1,141 @@
+//! Request/response channel for async backend calls
+//!
+//! Network calls run inside `wasm_bindgen_futures::spawn_local` futures and
+//!     cannot write into the window that started them. Instead the window
+//!     issues a request from its `RequestChannel`, moves the returned
+//!     `Responder` into the future, and drains the channel in `draw()`.
+//!
+//! Each request is tagged with an id. Only the response to the most recent
+//!     request is handed back; anything that arrives for a superseded or
+//!     cancelled request is dropped.
+//!
+
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+/// Identifier attached to every request issued from a channel
+pub type RequestId = u64;
+
+/// Sending half of a request, moved into the async block performing it
+pub struct Responder<T> {
+    id: RequestId,
+    sender: Sender<(RequestId, T)>,
+    ctx: eframe::egui::Context,
+}
+
+impl<T> Responder<T> {
+    /// Deliver the result and wake the ui so it is drawn on the next frame
+    pub fn respond(self, result: T) -> () {
+        // The window may have been dropped while the request was in flight
+        let _ = self.sender.send((self.id, result));
+        self.ctx.request_repaint();
+    }
+}
+
+/// Receiving half owned by a window. Tracks which request is current.
+pub struct RequestChannel<T> {
+    sender: Sender<(RequestId, T)>,
+    receiver: Receiver<(RequestId, T)>,
+    next_id: RequestId,
+    in_flight: Option<RequestId>,
+}
+
+impl<T> RequestChannel<T> {
+    pub fn new() -> Self {
+        let (sender, receiver) = channel();
+
+        RequestChannel {
+            sender,
+            receiver,
+            next_id: 0,
+            in_flight: None,
+        }
+    }
+
+    /// Start a new request. Any request still in flight is superseded and its
+    ///     response will be dropped.
+    pub fn issue(&mut self, ctx: &eframe::egui::Context) -> Responder<T> {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.in_flight = Some(id);
+
+        Responder {
+            id,
+            sender: self.sender.clone(),
+            ctx: ctx.clone(),
+        }
+    }
+
+    /// True while the latest request has not been answered
+    pub fn is_pending(&self) -> bool {
+        self.in_flight.is_some()
+    }
+
+    /// Forget the request in flight so its response is dropped when it lands
+    pub fn cancel(&mut self) -> () {
+        self.in_flight = None;
+    }
+
+    /// Take the response to the latest request if it has arrived.
+    ///
+    /// Stale responses found in the channel are discarded.
+    pub fn poll(&mut self) -> Option<T> {
+        while let Ok((id, result)) = self.receiver.try_recv() {
+            if Some(id) == self.in_flight {
+                self.in_flight = None;
+                return Some(result);
+            }
+
+            log::debug!("Dropped stale response for request {}", id);
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    fn only_the_latest_response_is_returned() {
+        let ctx = eframe::egui::Context::default();
+        let mut channel = RequestChannel::new();
+
+        let first = channel.issue(&ctx);
+        let second = channel.issue(&ctx);
+        second.respond("second");
+        first.respond("first");
+
+        assert_eq!(channel.poll(), Some("second"));
+        assert!(!channel.is_pending());
+        assert_eq!(channel.poll(), None);
+    }
+
+    #[wasm_bindgen_test]
+    fn superseded_responses_are_dropped() {
+        let ctx = eframe::egui::Context::default();
+        let mut channel = RequestChannel::new();
+
+        let first = channel.issue(&ctx);
+        let _second = channel.issue(&ctx);
+        first.respond("first");
+
+        assert_eq!(channel.poll(), None);
+        assert!(channel.is_pending());
+    }
+
+    #[wasm_bindgen_test]
+    fn cancelled_responses_are_dropped() {
+        let ctx = eframe::egui::Context::default();
+        let mut channel = RequestChannel::new();
+
+        let responder = channel.issue(&ctx);
+        channel.cancel();
+        assert!(!channel.is_pending());
+
+        responder.respond("late");
+        assert_eq!(channel.poll(), None);
+    }
+}
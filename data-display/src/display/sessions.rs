@@ -1,52 +1,89 @@
 //! Data recording session manager
 //!
 
-extern crate client;
-use client::api::{session};
+mod editor;
+mod export;
+mod filter;
+mod operations;
+mod recording;
 
-//use eframe::egui::RichText;
+use eframe::egui::{Color32, RichText};
+use egui_extras::{Column, TableBuilder};
 
-use serde::Deserialize;
+use crate::backend::{AuthToken, BackendError, Session, SessionState};
+use crate::time;
+
+use filter::SortColumn;
+
+use super::request::RequestChannel;
+use super::store::{AppEvent, AppStore};
 
 /// Handles displaying and managing historcal data recording sessions and
 ///     initiates the recording of new data sessions.
 ///
-
-#[allow(dead_code)]
-#[derive(Deserialize)]
-pub struct Row {
-    pub session_id: i64,
-    pub username: String, 
-}
-
-#[derive(Deserialize)]
-pub struct SessionResponse {
-    pub sessions: Vec<Row>,
-}
-
 #[allow(dead_code)]
 pub struct SessionDisplay {
-    username: *mut String, 
     session_id_str: String,
-    sessions: Vec<Row>,
+    sessions: Vec<Session>,
     loaded: bool,
-    current_session: *mut String,
+    session_requests: RequestChannel<Result<Vec<Session>, BackendError>>,
+    create_requests: RequestChannel<Result<(), BackendError>>,
+    /// Start/stop controls for the device recording
+    recording: recording::RecordingPanel,
+    /// Metadata editor behind the Modify link
+    editor: editor::SessionEditor,
+    /// Search, filters and sort order of the list
+    filter: filter::SessionFilter,
+    /// Delete, trim, split and merge dialog behind the Manage link
+    operations: operations::OperationDialog,
+    /// File export behind the Download link
+    export: export::SessionExport,
 }
 
 impl SessionDisplay {
-    pub fn new(username: *mut String, current_session: *mut String) -> Self {  
+    pub fn new() -> Self {  
         SessionDisplay {
-            username,
             session_id_str: String::from(""),
             sessions: Vec::new(),
             loaded: false,
-            current_session,
+            session_requests: RequestChannel::new(),
+            create_requests: RequestChannel::new(),
+            recording: recording::RecordingPanel::new(),
+            editor: editor::SessionEditor::new(),
+            filter: filter::SessionFilter::new(),
+            operations: operations::OperationDialog::new(),
+            export: export::SessionExport::new(),
         }
     }
 
+    /// Cancel pending requests and forget the previous user's sessions
+    pub fn reset(&mut self) -> () {
+        self.session_requests.cancel();
+        self.create_requests.cancel();
+
+        self.session_id_str.clear();
+        self.sessions.clear();
+        self.loaded = false;
+        self.recording.reset();
+        self.editor.reset();
+        self.filter = filter::SessionFilter::new();
+        self.operations.reset();
+        self.export.reset();
+    }
+
     /// Helper function to assign window contents
-    fn show_session_data(&mut self, ui: &mut eframe::egui::Ui) -> () {
-        let account_id = unsafe {&*self.username};
+    fn show_session_data(&mut self, ui: &mut eframe::egui::Ui, store: &AppStore) -> () {
+        let account_id = store.state().username.clone();
+        let token = match &store.state().token {
+            Some(token) => token.clone(),
+            None => return,
+        };
+
+        if self.recording.show(ui, store, &self.sessions) {
+            self.loaded = false;
+        }
+        ui.add_space(5.0);
+        ui.label(RichText::new("Sessions").heading().underline());
 
         // unused in temp solution
         //let session_id_widget = eframe::egui::TextEdit::singleline(&mut self.session_id_str);
@@ -55,73 +92,172 @@ impl SessionDisplay {
         if ui.button("New Session").clicked() {
             // unused in temp solution
             //let id = self.session_id_str.trim().to_string();
-            let client = client::get_client();
+            let responder = self.create_requests.issue(ui.ctx());
+            let request = store.backend().create_session(&token, &account_id);
 
             wasm_bindgen_futures::spawn_local(async move {
-                let (status, _val) = session::create_session(&client, account_id).await;
-            
-                if status == 201 {
-                    web_sys::console::log_1( &format!("New session success. Status: {}", status).into() );
-                } else {
-                    web_sys::console::log_1( &format!("New session failed. Status: {}", status).into() );
-                }
+                responder.respond(request.await);
             });
+        }
 
-            self.loaded = false;
+        // Reload the list once the new session exists on the server
+        match self.create_requests.poll() {
+            Some(Ok(())) => {
+                web_sys::console::log_1( &"New session success.".into() );
+                self.loaded = false;
+            }
+            Some(Err(e)) => store.report_error("New session", &e),
+            None => {}
         }
         
         if !self.loaded {
-            self.load_sessions()
+            self.load_sessions(ui.ctx(), store, &token)
+        }
+
+        match self.session_requests.poll() {
+            Some(Ok(sessions)) => {
+                web_sys::console::log_1( &"Session fetch sucess.".into() );
+                store.publish(AppEvent::SessionsLoaded(sessions.clone()));
+                self.sessions = sessions;
+            }
+            Some(Err(e)) => store.report_error("Session fetch", &e),
+            None => {}
         }
 
-        for row in &self.sessions {
-            ui.horizontal(|ui| {
-                ui.label(&row.session_id.to_string());
-                if ui.link("Download").clicked() {}
-                if ui.link("Modify").clicked() {}
-                if ui.link("View").clicked() {
-                    unsafe {
-                        *self.current_session = row.session_id.to_string();
-                    }
+        self.show_session_table(ui, store);
+    }
+
+    /// Helper function to draw the filtered session list, one row per session.
+    ///     Only the rows scrolled into view are drawn.
+    fn show_session_table(&mut self, ui: &mut eframe::egui::Ui, store: &AppStore) -> () {
+        let headers = [
+            ("ID", Some(SortColumn::Id)),
+            ("Name", Some(SortColumn::Name)),
+            ("Tags", None),
+            ("Started", Some(SortColumn::Started)),
+            ("Ended", Some(SortColumn::Ended)),
+            ("Duration", Some(SortColumn::Duration)),
+            ("Samples", Some(SortColumn::Samples)),
+            ("Device", Some(SortColumn::Device)),
+            ("State", Some(SortColumn::State)),
+            ("", None),
+        ];
+        let recording_session = self.recording.recording_session();
+        let mut modify = None;
+        let mut manage = None;
+        let mut download = None;
+        let mut sort = None;
+
+        self.filter.show(ui, &self.sessions);
+        self.export.show(ui, store);
+        let exporting = self.export.is_pending();
+        let rows = self.filter.apply(&self.sessions);
+        ui.label(RichText::new(format!("{} of {} sessions", rows.len(), self.sessions.len())).small());
+
+        TableBuilder::new(ui)
+            .striped(true)
+            .resizable(true)
+            .max_scroll_height(400.0)
+            .columns(Column::auto(), headers.len())
+            .header(20.0, |mut header| {
+                for (h, column) in headers {
+                    header.col(|ui| {
+                        match column {
+                            Some(column) => {
+                                let text = format!("{}{}", h, self.filter.sort_marker(column));
+                                if ui.add(eframe::egui::Button::new(RichText::new(text).strong()).frame(false)).clicked() {
+                                    sort = Some(column);
+                                }
+                            }
+                            None => {
+                                ui.strong(h);
+                            }
+                        }
+                    });
                 }
+            })
+            .body(|body| {
+                body.rows(20.0, rows.len(), |mut row_ui| {
+                    let row = &self.sessions[rows[row_ui.index()]];
+
+                    row_ui.col(|ui| {
+                        ui.label(row.session_id.to_string());
+                        if recording_session == Some(row.session_id) {
+                            ui.label(RichText::new("●").color(Color32::RED));
+                        }
+                    });
+                    row_ui.col(|ui| {
+                        let name = if row.name.is_empty() { RichText::new("Untitled").weak() } else { RichText::new(&row.name) };
+                        let response = ui.label(name);
+                        if !row.description.is_empty() {
+                            response.on_hover_text(&row.description);
+                        }
+                    });
+                    row_ui.col(|ui| { ui.label(row.tags.join(", ")); });
+                    row_ui.col(|ui| { ui.label(row.started.as_deref().map(time::short_datetime).unwrap_or_default()); });
+                    row_ui.col(|ui| { ui.label(row.ended.as_deref().map(time::short_datetime).unwrap_or_default()); });
+                    row_ui.col(|ui| { ui.label(row.duration_secs().map(time::format_duration).unwrap_or_default()); });
+                    row_ui.col(|ui| { ui.label(row.sample_count.to_string()); });
+                    row_ui.col(|ui| { ui.label(row.device_id.as_deref().unwrap_or("---")); });
+                    row_ui.col(|ui| {
+                        match row.state {
+                            SessionState::Recording => ui.label(RichText::new("live").color(Color32::GREEN)),
+                            SessionState::Finished => ui.label(RichText::new("finished").weak()),
+                        };
+                    });
+                    row_ui.col(|ui| {
+                        if ui.add_enabled(!exporting, eframe::egui::Link::new("Download")).clicked() {
+                            download = Some(row.clone());
+                        }
+                        if ui.link("Modify").clicked() {
+                            modify = Some(row.clone());
+                        }
+                        if ui.link("Manage").clicked() {
+                            manage = Some(row.clone());
+                        }
+                        if ui.link("View").clicked() {
+                            store.publish(AppEvent::SessionSelected(row.session_id.to_string()));
+                        }
+                    });
+                });
             });
+
+        if let Some(column) = sort {
+            self.filter.sort_by(column);
+        }
+        if let Some(session) = modify {
+            self.editor.open(&session);
+        }
+        if let Some(session) = manage {
+            self.operations.open(&session);
+        }
+        if let Some(session) = download {
+            self.export.start(ui.ctx(), store, &session);
         }
     }
 
-    fn load_sessions(&mut self) {
+    /// Request the session list for a user. The response is picked up in `show_session_data`
+    fn load_sessions(&mut self, ctx: &eframe::egui::Context, store: &AppStore, token: &AuthToken) {
         self.loaded = true;
-        let sessions_ptr: *mut Vec<Row> = &mut self.sessions;
-        let account_id = unsafe { &*self.username }.clone();
-        let client = client::get_client();
+        let responder = self.session_requests.issue(ctx);
+        let request = store.backend().sessions_by_user(token, &store.state().username);
 
         wasm_bindgen_futures::spawn_local(async move {
-            let (status, val) = session::view_sessions_by_user(&client, &account_id).await;
-        
-            if status == 200 {
-                web_sys::console::log_1( &format!("Session fetch sucess. Status: {}", status).into() );
-
-                if let Some(val) = val {
-                    match serde_json::from_value::<SessionResponse>(val) {
-                        Ok(parsed) => {
-                            unsafe {
-                                *sessions_ptr = parsed.sessions;
-                            }
-                        }
-                        Err(e) => {
-                            web_sys::console::log_1(&format!("Failed to parse sessions: {}", e).into());
-                        }
-                    }
-                }  
-            } else {
-                web_sys::console::log_1( &format!("Session fetch failed. Status: {}", status).into() );
-            }
+            responder.respond(request.await);
         });
     }
 
     /// Performs the draw step for the sessions window
-    pub fn draw(&mut self, ctx: &eframe::egui::Context) -> () {
+    pub fn draw(&mut self, ctx: &eframe::egui::Context, store: &AppStore) -> () {
         eframe::egui::Window::new("Sessions").show(ctx, |ui| {
-            self.show_session_data(ui);
+            self.show_session_data(ui, store);
         });
+
+        if self.editor.draw(ctx, store) {
+            self.loaded = false;
+        }
+        if self.operations.draw(ctx, store, &self.sessions) {
+            self.loaded = false;
+        }
     }
 }
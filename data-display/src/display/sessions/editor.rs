@@ -0,0 +1,178 @@
+//! Session metadata editor opened from the Modify link in the Sessions window
+//!
+
+use eframe::egui::{Color32, RichText};
+
+use crate::backend::{BackendError, Session, SessionMetadata};
+
+use crate::display::request::RequestChannel;
+use crate::display::store::AppStore;
+
+/// Longest session name accepted, in characters
+const MAX_NAME_LEN: usize = 100;
+
+/// Split comma separated tags, dropping blanks and repeats
+fn parse_tags(tags: &str) -> Vec<String> {
+    let mut parsed: Vec<String> = Vec::new();
+    for tag in tags.split(',').map(str::trim).filter(|tag| !tag.is_empty()) {
+        if !parsed.iter().any(|t| t == tag) {
+            parsed.push(tag.to_string());
+        }
+    }
+    parsed
+}
+
+/// Edits the name, description and tags of one session
+pub struct SessionEditor {
+    /// Session being edited, None while closed
+    session_id: Option<i64>,
+    /// Metadata as it was when the editor opened
+    original: SessionMetadata,
+    name_str: String,
+    description_str: String,
+    /// Tags as typed, comma separated
+    tags_str: String,
+    error: Option<String>,
+
+    save_requests: RequestChannel<Result<(), BackendError>>,
+}
+
+impl SessionEditor {
+    pub fn new() -> Self {
+        SessionEditor {
+            session_id: None,
+            original: SessionMetadata::default(),
+            name_str: String::new(),
+            description_str: String::new(),
+            tags_str: String::new(),
+            error: None,
+
+            save_requests: RequestChannel::new(),
+        }
+    }
+
+    /// Close the editor, dropping any unsaved changes
+    pub fn reset(&mut self) -> () {
+        self.save_requests.cancel();
+
+        self.session_id = None;
+        self.original = SessionMetadata::default();
+        self.name_str.clear();
+        self.description_str.clear();
+        self.tags_str.clear();
+        self.error = None;
+    }
+
+    /// Start editing a session, replacing whatever was open
+    pub fn open(&mut self, session: &Session) -> () {
+        self.reset();
+
+        self.session_id = Some(session.session_id);
+        self.original = session.metadata();
+        self.name_str = session.name.clone();
+        self.description_str = session.description.clone();
+        self.tags_str = session.tags.join(", ");
+    }
+
+    /// Metadata as currently entered
+    fn draft(&self) -> SessionMetadata {
+        SessionMetadata {
+            name: self.name_str.trim().to_string(),
+            description: self.description_str.trim().to_string(),
+            tags: parse_tags(&self.tags_str),
+        }
+    }
+
+    fn save(&mut self, ctx: &eframe::egui::Context, store: &AppStore, session_id: i64) -> () {
+        let token = match &store.state().token {
+            Some(token) => token.clone(),
+            None => return,
+        };
+
+        self.error = None;
+        let responder = self.save_requests.issue(ctx);
+        let request = store.backend().update_session(&token, &session_id.to_string(), &self.draft());
+
+        wasm_bindgen_futures::spawn_local(async move {
+            responder.respond(request.await);
+        });
+    }
+
+    /// Performs the draw step for the editor window.
+    ///
+    /// Returns true once changes are saved and the session list should be reloaded.
+    pub fn draw(&mut self, ctx: &eframe::egui::Context, store: &AppStore) -> bool {
+        let session_id = match self.session_id {
+            Some(session_id) => session_id,
+            None => return false,
+        };
+
+        match self.save_requests.poll() {
+            Some(Ok(())) => {
+                web_sys::console::log_1(&format!("Session {} updated.", session_id).into());
+                self.reset();
+                return true;
+            }
+            Some(Err(e)) => {
+                store.report_error("Session update", &e);
+                self.error = Some(match e {
+                    BackendError::Unsupported => "This server cannot store session details".to_string(),
+                    BackendError::Status(404) => "The session no longer exists".to_string(),
+                    e => format!("Could not save. {}", e),
+                });
+            }
+            None => {}
+        }
+
+        let draft = self.draft();
+        let name_len = draft.name.chars().count();
+        let busy = self.save_requests.is_pending();
+        let mut open = true;
+        let mut cancel = false;
+
+        eframe::egui::Window::new(format!("Modify Session {}", session_id))
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                eframe::egui::Grid::new("session_editor").num_columns(2).show(ui, |ui| {
+                    ui.label("Name");
+                    ui.text_edit_singleline(&mut self.name_str);
+                    ui.end_row();
+
+                    ui.label("Description");
+                    ui.text_edit_multiline(&mut self.description_str);
+                    ui.end_row();
+
+                    ui.label("Tags");
+                    ui.add(eframe::egui::TextEdit::singleline(&mut self.tags_str).hint_text("comma separated"));
+                    ui.end_row();
+                });
+
+                if name_len > MAX_NAME_LEN {
+                    ui.label(RichText::new(format!("Name must be at most {} characters", MAX_NAME_LEN)).color(Color32::RED).small());
+                }
+                if let Some(error) = &self.error {
+                    ui.label(RichText::new(error).color(Color32::RED));
+                }
+
+                ui.horizontal(|ui| {
+                    let can_save = !busy && name_len <= MAX_NAME_LEN && draft != self.original;
+                    if ui.add_enabled(can_save, eframe::egui::Button::new("Save")).clicked() {
+                        self.save(ui.ctx(), store, session_id);
+                    }
+                    if ui.button("Cancel").clicked() {
+                        cancel = true;
+                    }
+                    if busy {
+                        ui.spinner();
+                    }
+                });
+            });
+
+        if !open || cancel {
+            self.reset();
+        }
+
+        false
+    }
+}
@@ -0,0 +1,297 @@
+//! Session export behind the Download link in the Sessions window
+//!
+//! Every datapoint of the session is fetched, flattened into the same columns
+//!     the data window shows, and handed to the browser as a download.
+//!
+
+use eframe::egui::{Color32, ComboBox, RichText};
+use serde_json::{json, Value};
+
+use crate::backend::{BackendError, Datapoint, Session};
+
+use crate::display::data::Row;
+use crate::display::request::RequestChannel;
+use crate::display::store::AppStore;
+
+/// File formats a session can be exported as
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ExportFormat {
+    Csv,
+    JsonLines,
+    GeoJson,
+}
+
+impl ExportFormat {
+    fn label(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "CSV",
+            ExportFormat::JsonLines => "JSON Lines",
+            ExportFormat::GeoJson => "GeoJSON",
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::JsonLines => "jsonl",
+            ExportFormat::GeoJson => "geojson",
+        }
+    }
+
+    fn mime_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv",
+            ExportFormat::JsonLines => "application/jsonl",
+            ExportFormat::GeoJson => "application/geo+json",
+        }
+    }
+}
+
+/// Flatten datapoints into rows, skipping any whose blob does not parse
+fn to_rows(datapoints: &[Datapoint]) -> Vec<Row> {
+    datapoints.iter()
+        .enumerate()
+        .filter_map(|(i, datapoint)| match Row::from_datapoint(i as u32, datapoint) {
+            Ok(row) => Some(row),
+            Err(e) => {
+                web_sys::console::log_1(&format!("Skipped datapoint {} in export. {}", datapoint.id, e).into());
+                None
+            }
+        })
+        .collect()
+}
+
+/// Header row followed by one line per row
+fn encode_csv(rows: &[Row]) -> Result<Vec<u8>, String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for row in rows {
+        writer.serialize(row).map_err(|e| e.to_string())?;
+    }
+
+    writer.into_inner().map_err(|e| e.error().to_string())
+}
+
+/// One JSON object per line
+fn encode_json_lines(rows: &[Row]) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    for row in rows {
+        serde_json::to_writer(&mut bytes, row).map_err(|e| e.to_string())?;
+        bytes.push(b'\n');
+    }
+
+    Ok(bytes)
+}
+
+/// The track as a line, followed by every sample as a point carrying its sensor values
+fn encode_geojson(session: &Session, rows: &[Row]) -> Result<Vec<u8>, String> {
+    let position = |row: &Row| json!([row.longitude, row.latitude, row.altitude]);
+
+    let mut features = vec![json!({
+        "type": "Feature",
+        "geometry": {
+            "type": "LineString",
+            "coordinates": rows.iter().map(position).collect::<Vec<Value>>(),
+        },
+        "properties": {
+            "session_id": session.session_id,
+            "name": session.name,
+        },
+    })];
+
+    for row in rows {
+        let mut properties = serde_json::to_value(row).map_err(|e| e.to_string())?;
+        // Already in the geometry
+        if let Some(properties) = properties.as_object_mut() {
+            for key in ["latitude", "longitude", "altitude"] {
+                properties.remove(key);
+            }
+        }
+
+        features.push(json!({
+            "type": "Feature",
+            "geometry": { "type": "Point", "coordinates": position(row) },
+            "properties": properties,
+        }));
+    }
+
+    let collection = json!({ "type": "FeatureCollection", "features": features });
+    serde_json::to_vec(&collection).map_err(|e| e.to_string())
+}
+
+/// How long the browser gets to start reading a download before its object URL is revoked, ms
+const REVOKE_DELAY_MS: i32 = 10_000;
+
+/// Hand the file to the browser as a download
+fn save_file(file_name: &str, mime_type: &str, bytes: &[u8]) -> Result<String, String> {
+    use wasm_bindgen::closure::Closure;
+    use wasm_bindgen::JsCast;
+
+    let window = web_sys::window().ok_or_else(|| "No window".to_string())?;
+    let document = window.document().ok_or_else(|| "No document".to_string())?;
+
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(bytes));
+    let options = web_sys::BlobPropertyBag::new();
+    options.set_type(mime_type);
+    let blob = web_sys::Blob::new_with_u8_array_sequence_and_options(&parts, &options)
+        .map_err(|e| format!("{:?}", e))?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob).map_err(|e| format!("{:?}", e))?;
+
+    let anchor = document.create_element("a")
+        .map_err(|e| format!("{:?}", e))?
+        .dyn_into::<web_sys::HtmlAnchorElement>()
+        .map_err(|_| "Could not create a download link".to_string())?;
+    anchor.set_href(&url);
+    anchor.set_download(file_name);
+    anchor.click();
+
+    // The download starts after click() returns, revoking now can cancel it
+    let revoke = Closure::once_into_js(move || {
+        let _ = web_sys::Url::revoke_object_url(&url);
+    });
+    let _ = window.set_timeout_with_callback_and_timeout_and_arguments_0(revoke.unchecked_ref(), REVOKE_DELAY_MS);
+    Ok(file_name.to_string())
+}
+
+/// Fetches sessions and saves them in the picked format, one at a time
+pub struct SessionExport {
+    format: ExportFormat,
+    /// Outcome of the last export, and whether it succeeded
+    message: Option<(bool, String)>,
+
+    /// Datapoints of the session being exported, tagged with the session and format asked for
+    data_requests: RequestChannel<(Session, ExportFormat, Result<Vec<Datapoint>, BackendError>)>,
+}
+
+impl SessionExport {
+    pub fn new() -> Self {
+        SessionExport {
+            format: ExportFormat::Csv,
+            message: None,
+
+            data_requests: RequestChannel::new(),
+        }
+    }
+
+    /// Cancel the export in flight. The picked format is kept.
+    pub fn reset(&mut self) -> () {
+        self.data_requests.cancel();
+        self.message = None;
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.data_requests.is_pending()
+    }
+
+    /// Fetch every datapoint of a session. The file is saved once they arrive.
+    pub fn start(&mut self, ctx: &eframe::egui::Context, store: &AppStore, session: &Session) -> () {
+        let token = match &store.state().token {
+            Some(token) => token.clone(),
+            None => return,
+        };
+
+        self.message = None;
+        let session = session.clone();
+        let format = self.format;
+        let responder = self.data_requests.issue(ctx);
+        let request = store.backend().datapoints_by_session(&token, &session.session_id.to_string());
+
+        wasm_bindgen_futures::spawn_local(async move {
+            responder.respond((session, format, request.await));
+        });
+    }
+
+    /// Encode and save a fetched session
+    fn save(&mut self, session: &Session, format: ExportFormat, datapoints: &[Datapoint]) -> () {
+        let rows = to_rows(datapoints);
+        let encoded = match format {
+            ExportFormat::Csv => encode_csv(&rows),
+            ExportFormat::JsonLines => encode_json_lines(&rows),
+            ExportFormat::GeoJson => encode_geojson(session, &rows),
+        };
+
+        let file_name = format!("session_{}.{}", session.session_id, format.extension());
+        self.message = Some(match encoded.and_then(|bytes| save_file(&file_name, format.mime_type(), &bytes)) {
+            Ok(saved) => (true, format!("Saved {} rows to {}", rows.len(), saved)),
+            Err(e) => {
+                web_sys::console::log_1(&format!("Export of session {} failed. {}", session.session_id, e).into());
+                (false, format!("Export failed. {}", e))
+            }
+        });
+    }
+
+    /// Draw the format picker and the outcome of the last export
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui, store: &AppStore) -> () {
+        match self.data_requests.poll() {
+            Some((session, format, Ok(datapoints))) => self.save(&session, format, &datapoints),
+            Some((_, _, Err(e))) => {
+                store.report_error("Session export", &e);
+                self.message = Some((false, format!("Could not fetch the session. {}", e)));
+            }
+            None => {}
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Download as");
+            ComboBox::from_id_salt("export_format")
+                .selected_text(self.format.label())
+                .show_ui(ui, |ui| {
+                    for format in [ExportFormat::Csv, ExportFormat::JsonLines, ExportFormat::GeoJson] {
+                        ui.selectable_value(&mut self.format, format, format.label());
+                    }
+                });
+
+            if self.data_requests.is_pending() {
+                ui.spinner();
+            }
+            if let Some((success, message)) = &self.message {
+                ui.label(RichText::new(message).color(if *success { Color32::GREEN } else { Color32::RED }).small());
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+    use crate::display::testing::fix;
+
+    fn rows() -> Vec<Row> {
+        vec![fix(0, 0.0, 45.5, -123.0, 10.0), fix(1, 1.0, 45.6, -123.1, 12.5)]
+    }
+
+    #[wasm_bindgen_test]
+    fn csv_has_a_header_and_the_table_columns() {
+        let text = String::from_utf8(encode_csv(&rows()).unwrap()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "id,timestamp,latitude,longitude,altitude,accel_x,accel_y,accel_z,gyro_x,gyro_y,gyro_z,dac_1,dac_2,dac_3,dac_4");
+        assert_eq!(lines[1], "0,2025-01-18T00:00:00.000000,45.5,-123.0,10.0,0.0,0.0,0.0,0.0,0.0,0.0,0.0,0.0,0.0,0.0");
+    }
+
+    #[wasm_bindgen_test]
+    fn json_lines_has_one_object_per_row() {
+        let text = String::from_utf8(encode_json_lines(&rows()).unwrap()).unwrap();
+        let lines: Vec<Value> = text.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+
+        assert_eq!(lines.len(), 2);
+        assert!(text.ends_with('\n'));
+        assert_eq!(lines[1]["id"], json!(1));
+        assert_eq!(lines[1]["altitude"], json!(12.5));
+    }
+
+    #[wasm_bindgen_test]
+    fn geojson_positions_are_lon_lat_alt() {
+        let session = Session { session_id: 7, name: "Ride".to_string(), ..Default::default() };
+        let collection: Value = serde_json::from_slice(&encode_geojson(&session, &rows()).unwrap()).unwrap();
+        let features = collection["features"].as_array().unwrap();
+
+        assert_eq!(features.len(), 3);
+        assert_eq!(features[0]["geometry"]["coordinates"], json!([[-123.0, 45.5, 10.0], [-123.1, 45.6, 12.5]]));
+        assert_eq!(features[0]["properties"]["session_id"], json!(7));
+        assert_eq!(features[2]["geometry"]["coordinates"], json!([-123.1, 45.6, 12.5]));
+        assert!(features[2]["properties"].get("latitude").is_none());
+    }
+}
@@ -0,0 +1,308 @@
+//! Search, filters and sort order for the session list
+//!
+
+use std::cmp::Ordering;
+
+use eframe::egui::{Color32, ComboBox, RichText};
+
+use crate::backend::{Session, SessionState};
+use crate::time;
+
+/// Columns the session list can be sorted by
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SortColumn {
+    Id,
+    Name,
+    Started,
+    Ended,
+    Duration,
+    Samples,
+    Device,
+    State,
+}
+
+/// Parse a `YYYY-MM-DD` date into ms since the unix epoch at its start.
+///
+/// Blank text is no bound. Returns Err if the text is not a date.
+fn parse_date(date: &str) -> Result<Option<f64>, ()> {
+    let date = date.trim();
+    if date.is_empty() {
+        return Ok(None);
+    }
+
+    time::parse_datetime(&format!("{}T00:00:00", date)).map(Some).ok_or(())
+}
+
+/// Narrows and orders the session list
+pub struct SessionFilter {
+    /// Text matched against names, tags and descriptions
+    query: String,
+    /// First and last day a session may start on, as typed
+    from_str: String,
+    to_str: String,
+    /// Only sessions recorded by this device, None for any
+    device: Option<String>,
+    /// Only sessions in this state, None for any
+    state: Option<SessionState>,
+
+    sort: SortColumn,
+    ascending: bool,
+}
+
+impl SessionFilter {
+    pub fn new() -> Self {
+        SessionFilter {
+            query: String::new(),
+            from_str: String::new(),
+            to_str: String::new(),
+            device: None,
+            state: None,
+
+            // Newest first
+            sort: SortColumn::Id,
+            ascending: false,
+        }
+    }
+
+    /// Clear the search and filters. The sort order is kept.
+    pub fn clear(&mut self) -> () {
+        self.query.clear();
+        self.from_str.clear();
+        self.to_str.clear();
+        self.device = None;
+        self.state = None;
+    }
+
+    /// Sort by a column, flipping the direction if it is already the sort column
+    pub fn sort_by(&mut self, column: SortColumn) -> () {
+        if self.sort == column {
+            self.ascending = !self.ascending;
+        } else {
+            self.sort = column;
+            self.ascending = true;
+        }
+    }
+
+    /// Arrow to show next to a column header
+    pub fn sort_marker(&self, column: SortColumn) -> &'static str {
+        match (self.sort == column, self.ascending) {
+            (false, _) => "",
+            (true, true) => " ⬆",
+            (true, false) => " ⬇",
+        }
+    }
+
+    /// Whether a session passes the search and filters
+    fn matches(&self, session: &Session, from_ms: Option<f64>, to_ms: Option<f64>) -> bool {
+        let query = self.query.trim().to_lowercase();
+        if !query.is_empty() {
+            let found = session.name.to_lowercase().contains(&query)
+                || session.description.to_lowercase().contains(&query)
+                || session.tags.iter().any(|tag| tag.to_lowercase().contains(&query));
+            if !found {
+                return false;
+            }
+        }
+
+        if self.state.is_some_and(|state| state != session.state) {
+            return false;
+        }
+        if self.device.is_some() && self.device != session.device_id {
+            return false;
+        }
+
+        // Sessions with nothing recorded have no start and only show without a date range
+        if from_ms.is_some() || to_ms.is_some() {
+            let started = match session.started.as_deref().and_then(time::parse_datetime) {
+                Some(started) => started,
+                None => return false,
+            };
+            if from_ms.is_some_and(|from| started < from) {
+                return false;
+            }
+            // The end date is inclusive
+            if to_ms.is_some_and(|to| started >= to + 86_400_000.0) {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    fn compare(&self, a: &Session, b: &Session) -> Ordering {
+        let ordering = match self.sort {
+            SortColumn::Id => a.session_id.cmp(&b.session_id),
+            SortColumn::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+            SortColumn::Started => a.started.cmp(&b.started),
+            SortColumn::Ended => a.ended.cmp(&b.ended),
+            SortColumn::Duration => a.duration_secs().unwrap_or(0.0).total_cmp(&b.duration_secs().unwrap_or(0.0)),
+            SortColumn::Samples => a.sample_count.cmp(&b.sample_count),
+            SortColumn::Device => a.device_id.cmp(&b.device_id),
+            SortColumn::State => (a.state == SessionState::Finished).cmp(&(b.state == SessionState::Finished)),
+        };
+
+        // Ties fall back to the id so the order is stable between frames
+        let ordering = ordering.then(a.session_id.cmp(&b.session_id));
+        if self.ascending { ordering } else { ordering.reverse() }
+    }
+
+    /// Indices of the sessions that pass the filters, in sort order
+    pub fn apply(&self, sessions: &[Session]) -> Vec<usize> {
+        let from_ms = parse_date(&self.from_str).ok().flatten();
+        let to_ms = parse_date(&self.to_str).ok().flatten();
+
+        let mut rows: Vec<usize> = (0..sessions.len())
+            .filter(|&i| self.matches(&sessions[i], from_ms, to_ms))
+            .collect();
+        rows.sort_by(|&a, &b| self.compare(&sessions[a], &sessions[b]));
+        rows
+    }
+
+    /// Draw the search box and filter controls
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui, sessions: &[Session]) -> () {
+        ui.horizontal(|ui| {
+            ui.label("Search");
+            ui.add(eframe::egui::TextEdit::singleline(&mut self.query).hint_text("name, tag or description").desired_width(180.0));
+            if ui.button("Clear").clicked() {
+                self.clear();
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Started");
+            ui.add(eframe::egui::TextEdit::singleline(&mut self.from_str).hint_text("YYYY-MM-DD").desired_width(80.0));
+            ui.label("to");
+            ui.add(eframe::egui::TextEdit::singleline(&mut self.to_str).hint_text("YYYY-MM-DD").desired_width(80.0));
+
+            let mut devices: Vec<&str> = sessions.iter().filter_map(|s| s.device_id.as_deref()).collect();
+            devices.sort();
+            devices.dedup();
+
+            ComboBox::from_id_salt("session_device_filter")
+                .selected_text(self.device.clone().unwrap_or_else(|| "Any device".to_string()))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.device, None, "Any device");
+                    for device in devices {
+                        ui.selectable_value(&mut self.device, Some(device.to_string()), device);
+                    }
+                });
+
+            let state_text = match self.state {
+                None => "Any state",
+                Some(SessionState::Recording) => "Live",
+                Some(SessionState::Finished) => "Finished",
+            };
+            ComboBox::from_id_salt("session_state_filter")
+                .selected_text(state_text)
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.state, None, "Any state");
+                    ui.selectable_value(&mut self.state, Some(SessionState::Recording), "Live");
+                    ui.selectable_value(&mut self.state, Some(SessionState::Finished), "Finished");
+                });
+        });
+
+        for (label, date) in [("Start", &self.from_str), ("End", &self.to_str)] {
+            if parse_date(date).is_err() {
+                ui.label(RichText::new(format!("{} date must be YYYY-MM-DD", label)).color(Color32::RED).small());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    fn session(session_id: i64, name: &str, started: Option<&str>) -> Session {
+        Session {
+            session_id,
+            name: name.to_string(),
+            state: SessionState::Finished,
+            started: started.map(|s| s.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn sessions() -> Vec<Session> {
+        vec![
+            Session { tags: vec!["Walk".to_string()], ..session(1, "Campus loop", Some("2025-01-17T10:00:00.000000")) },
+            Session { description: "Bike ride to the coast".to_string(), ..session(2, "Coast", Some("2025-01-18T23:59:59.000000")) },
+            Session { state: SessionState::Recording, device_id: Some("sim-0001".to_string()), ..session(3, "Live demo", Some("2025-01-19T00:00:00.000000")) },
+            session(4, "Empty", None),
+        ]
+    }
+
+    #[wasm_bindgen_test]
+    fn newest_first_by_default() {
+        assert_eq!(SessionFilter::new().apply(&sessions()), vec![3, 2, 1, 0]);
+    }
+
+    #[wasm_bindgen_test]
+    fn search_matches_names_tags_and_descriptions() {
+        let mut filter = SessionFilter::new();
+
+        filter.query = " WALK ".to_string();
+        assert_eq!(filter.apply(&sessions()), vec![0]);
+        filter.query = "coast".to_string();
+        assert_eq!(filter.apply(&sessions()), vec![1]);
+        filter.query = "demo".to_string();
+        assert_eq!(filter.apply(&sessions()), vec![2]);
+        filter.query = "nothing".to_string();
+        assert!(filter.apply(&sessions()).is_empty());
+    }
+
+    #[wasm_bindgen_test]
+    fn state_and_device_filters() {
+        let mut filter = SessionFilter::new();
+
+        filter.state = Some(SessionState::Recording);
+        assert_eq!(filter.apply(&sessions()), vec![2]);
+
+        filter.state = None;
+        filter.device = Some("sim-0001".to_string());
+        assert_eq!(filter.apply(&sessions()), vec![2]);
+    }
+
+    #[wasm_bindgen_test]
+    fn date_range_includes_the_end_day() {
+        let mut filter = SessionFilter::new();
+        filter.from_str = "2025-01-18".to_string();
+        filter.to_str = "2025-01-18".to_string();
+
+        // Empty sessions have no start, so they drop out too
+        assert_eq!(filter.apply(&sessions()), vec![1]);
+
+        filter.to_str.clear();
+        assert_eq!(filter.apply(&sessions()), vec![2, 1]);
+    }
+
+    #[wasm_bindgen_test]
+    fn unparseable_dates_are_ignored() {
+        let mut filter = SessionFilter::new();
+        filter.from_str = "18/01/2025".to_string();
+
+        assert_eq!(filter.apply(&sessions()).len(), 4);
+    }
+
+    #[wasm_bindgen_test]
+    fn sorting_flips_on_the_same_column() {
+        let mut filter = SessionFilter::new();
+
+        filter.sort_by(SortColumn::Name);
+        assert_eq!(filter.apply(&sessions()), vec![0, 1, 3, 2]);
+        filter.sort_by(SortColumn::Name);
+        assert_eq!(filter.apply(&sessions()), vec![2, 3, 1, 0]);
+    }
+
+    #[wasm_bindgen_test]
+    fn clearing_keeps_the_sort_order() {
+        let mut filter = SessionFilter::new();
+        filter.sort_by(SortColumn::Name);
+        filter.query = "coast".to_string();
+        filter.clear();
+
+        assert_eq!(filter.apply(&sessions()), vec![0, 1, 3, 2]);
+    }
+}
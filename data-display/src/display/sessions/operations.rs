@@ -0,0 +1,398 @@
+//! Delete, trim, split and merge dialog opened from the Manage link in the Sessions window
+//!
+//! Every operation is previewed first. Running it is only possible once the
+//!     preview for exactly the entered values has come back.
+//!
+
+use eframe::egui::{Color32, ComboBox, RichText};
+
+use crate::backend::{BackendError, OperationOutcome, Session, SessionOperation, SessionState};
+use crate::time;
+
+use crate::display::request::RequestChannel;
+use crate::display::store::{AppEvent, AppStore};
+
+/// Operations offered by the dialog
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum OperationKind {
+    Delete,
+    Trim,
+    Split,
+    Merge,
+}
+
+impl OperationKind {
+    fn label(&self) -> &'static str {
+        match self {
+            OperationKind::Delete => "Delete",
+            OperationKind::Trim => "Trim",
+            OperationKind::Split => "Split",
+            OperationKind::Merge => "Merge",
+        }
+    }
+}
+
+/// Turn a timestamp as typed (`YYYY-MM-DD HH:MM:SS`, optionally with a
+///     fraction of a second) into the backend format
+fn input_datetime(text: &str) -> Result<String, String> {
+    let datetime = text.trim().replacen(' ', "T", 1);
+    match time::parse_datetime(&datetime) {
+        Some(_) => Ok(datetime),
+        None => Err(format!("\"{}\" is not a time, use YYYY-MM-DD HH:MM:SS", text.trim())),
+    }
+}
+
+/// Session label for pickers and messages
+fn describe(session: &Session) -> String {
+    if session.name.is_empty() {
+        format!("Session {}", session.session_id)
+    } else {
+        format!("Session {} ({})", session.session_id, session.name)
+    }
+}
+
+/// Previews and runs lifecycle operations on one session
+pub struct OperationDialog {
+    /// Session the dialog was opened for, None while closed
+    session: Option<Session>,
+    kind: OperationKind,
+    /// Trim range and split point, as typed
+    start_str: String,
+    end_str: String,
+    at_str: String,
+    /// Session to merge with
+    merge_with: Option<i64>,
+    /// Operation the last preview was for, and what it would change
+    preview: Option<(SessionOperation, OperationOutcome)>,
+    error: Option<String>,
+
+    preview_requests: RequestChannel<(SessionOperation, Result<OperationOutcome, BackendError>)>,
+    run_requests: RequestChannel<(SessionOperation, Result<OperationOutcome, BackendError>)>,
+}
+
+impl OperationDialog {
+    pub fn new() -> Self {
+        OperationDialog {
+            session: None,
+            kind: OperationKind::Delete,
+            start_str: String::new(),
+            end_str: String::new(),
+            at_str: String::new(),
+            merge_with: None,
+            preview: None,
+            error: None,
+
+            preview_requests: RequestChannel::new(),
+            run_requests: RequestChannel::new(),
+        }
+    }
+
+    /// Close the dialog and cancel anything in flight
+    pub fn reset(&mut self) -> () {
+        self.preview_requests.cancel();
+        self.run_requests.cancel();
+
+        self.session = None;
+        self.kind = OperationKind::Delete;
+        self.start_str.clear();
+        self.end_str.clear();
+        self.at_str.clear();
+        self.merge_with = None;
+        self.preview = None;
+        self.error = None;
+    }
+
+    /// Open the dialog for a session. The trim range starts out as the whole
+    ///     session and the split point as its midpoint.
+    pub fn open(&mut self, session: &Session) -> () {
+        self.reset();
+
+        let started = session.started.as_deref().and_then(time::parse_datetime);
+        let ended = session.ended.as_deref().and_then(time::parse_datetime);
+        // Keep the fraction so trimming to the defaults keeps the last datapoint
+        self.start_str = session.started.as_deref().map(|s| s.replacen('T', " ", 1)).unwrap_or_default();
+        self.end_str = session.ended.as_deref().map(|s| s.replacen('T', " ", 1)).unwrap_or_default();
+        if let (Some(started), Some(ended)) = (started, ended) {
+            self.at_str = time::short_datetime(&time::format_datetime((started + ended) / 2.0));
+        }
+        self.session = Some(session.clone());
+    }
+
+    /// Build the operation from the entered values
+    fn operation(&self, session: &Session, sessions: &[Session]) -> Result<SessionOperation, String> {
+        let session_id = session.session_id;
+
+        match self.kind {
+            OperationKind::Delete => Ok(SessionOperation::Delete { session_id }),
+            OperationKind::Trim => Ok(SessionOperation::Trim {
+                session_id,
+                start: input_datetime(&self.start_str)?,
+                end: input_datetime(&self.end_str)?,
+            }),
+            OperationKind::Split => Ok(SessionOperation::Split { session_id, at: input_datetime(&self.at_str)? }),
+            OperationKind::Merge => {
+                let other = self.merge_with
+                    .and_then(|id| sessions.iter().find(|s| s.session_id == id))
+                    .ok_or_else(|| "Pick a session to merge with".to_string())?;
+
+                // The earlier session keeps its id and receives the later one
+                if other.started.is_some() && session.started.is_some() && other.started < session.started {
+                    Ok(SessionOperation::Merge { first: other.session_id, second: session_id })
+                } else {
+                    Ok(SessionOperation::Merge { first: session_id, second: other.session_id })
+                }
+            }
+        }
+    }
+
+    /// Send an operation to be previewed, or run if `run` is set
+    fn issue(&mut self, ctx: &eframe::egui::Context, store: &AppStore, operation: SessionOperation, run: bool) -> () {
+        let token = match &store.state().token {
+            Some(token) => token.clone(),
+            None => return,
+        };
+
+        let (responder, request) = if run {
+            (self.run_requests.issue(ctx), store.backend().run_operation(&token, &operation))
+        } else {
+            (self.preview_requests.issue(ctx), store.backend().preview_operation(&token, &operation))
+        };
+
+        wasm_bindgen_futures::spawn_local(async move {
+            responder.respond((operation, request.await));
+        });
+    }
+
+    /// Explain why the backend refused an operation
+    fn failure(&self, store: &AppStore, error: BackendError) -> String {
+        store.report_error(self.kind.label(), &error);
+
+        match error {
+            BackendError::Unsupported => "This server cannot change session contents".to_string(),
+            BackendError::Status(409) => "A session involved is still recording".to_string(),
+            BackendError::Status(404) => "The session no longer exists".to_string(),
+            BackendError::Status(422) => match self.kind {
+                OperationKind::Trim => "The start must not be after the end".to_string(),
+                OperationKind::Split => "The split point must leave datapoints in both parts".to_string(),
+                OperationKind::Merge => "Only back to back sessions from the same device can be merged".to_string(),
+                OperationKind::Delete => "The session cannot be deleted".to_string(),
+            },
+            e => format!("{} failed. {}", self.kind.label(), e),
+        }
+    }
+
+    /// Apply any responses that have arrived.
+    ///
+    /// Returns true once an operation has run and the session list should be reloaded.
+    fn receive(&mut self, store: &AppStore) -> bool {
+        match self.preview_requests.poll() {
+            Some((operation, Ok(outcome))) => self.preview = Some((operation, outcome)),
+            Some((_, Err(e))) => {
+                self.preview = None;
+                self.error = Some(self.failure(store, e));
+            }
+            None => {}
+        }
+
+        match self.run_requests.poll() {
+            Some((operation, Ok(outcome))) => {
+                web_sys::console::log_1(&format!("{:?} affected {} datapoints.", operation, outcome.affected).into());
+                if let Some(created) = outcome.created {
+                    web_sys::console::log_1(&format!("Split off session {}.", created).into());
+                }
+
+                let removed = match operation {
+                    SessionOperation::Delete { session_id } => vec![session_id],
+                    SessionOperation::Merge { second, .. } => vec![second],
+                    _ => Vec::new(),
+                };
+                store.publish(AppEvent::SessionsChanged { removed });
+                self.reset();
+                true
+            }
+            Some((_, Err(e))) => {
+                self.preview = None;
+                self.error = Some(self.failure(store, e));
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Describe what a previewed operation will do
+    fn show_preview(&self, ui: &mut eframe::egui::Ui, operation: &SessionOperation, outcome: &OperationOutcome) -> () {
+        let text = match operation {
+            SessionOperation::Delete { session_id } => {
+                format!("Session {} and its {} datapoints will be deleted.", session_id, outcome.affected)
+            }
+            SessionOperation::Trim { .. } => {
+                format!("{} datapoints will be removed, {} kept.", outcome.affected, outcome.remaining)
+            }
+            SessionOperation::Split { .. } => {
+                format!("{} datapoints will move into a new session, {} stay.", outcome.affected, outcome.remaining)
+            }
+            SessionOperation::Merge { first, second } => format!(
+                "{} datapoints will move from session {} into session {}, {} in total. Session {} will be removed.",
+                outcome.affected, second, first, outcome.remaining, second
+            ),
+        };
+
+        ui.label(RichText::new(text).color(Color32::YELLOW));
+    }
+
+    /// Performs the draw step for the dialog.
+    ///
+    /// Returns true once an operation has run and the session list should be reloaded.
+    pub fn draw(&mut self, ctx: &eframe::egui::Context, store: &AppStore, sessions: &[Session]) -> bool {
+        let session = match &self.session {
+            Some(session) => session.clone(),
+            None => return false,
+        };
+
+        if self.receive(store) {
+            return true;
+        }
+
+        let busy = self.preview_requests.is_pending() || self.run_requests.is_pending();
+        let mut open = true;
+
+        eframe::egui::Window::new(format!("Manage {}", describe(&session)))
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                if session.state == SessionState::Recording {
+                    ui.label(RichText::new("Stop the recording before changing this session").color(Color32::YELLOW));
+                    return;
+                }
+
+                let before = (self.kind, self.start_str.clone(), self.end_str.clone(), self.at_str.clone(), self.merge_with);
+
+                ui.add_enabled_ui(!busy, |ui| {
+                    ui.horizontal(|ui| {
+                        for kind in [OperationKind::Delete, OperationKind::Trim, OperationKind::Split, OperationKind::Merge] {
+                            ui.selectable_value(&mut self.kind, kind, kind.label());
+                        }
+                    });
+                    ui.separator();
+
+                    match self.kind {
+                        OperationKind::Delete => {
+                            ui.label("Remove the session and every datapoint in it.");
+                        }
+                        OperationKind::Trim => {
+                            ui.label("Keep only the datapoints recorded in this range.");
+                            ui.horizontal(|ui| {
+                                ui.add(eframe::egui::TextEdit::singleline(&mut self.start_str).hint_text("YYYY-MM-DD HH:MM:SS").desired_width(190.0));
+                                ui.label("to");
+                                ui.add(eframe::egui::TextEdit::singleline(&mut self.end_str).hint_text("YYYY-MM-DD HH:MM:SS").desired_width(190.0));
+                            });
+                        }
+                        OperationKind::Split => {
+                            ui.label("Move the datapoints recorded from this time on into a new session.");
+                            ui.add(eframe::egui::TextEdit::singleline(&mut self.at_str).hint_text("YYYY-MM-DD HH:MM:SS").desired_width(140.0));
+                        }
+                        OperationKind::Merge => {
+                            ui.label("Combine with the session recorded just before or after it on the same device.");
+                            let candidates: Vec<&Session> = sessions.iter()
+                                .filter(|s| s.session_id != session.session_id && s.state == SessionState::Finished)
+                                .filter(|s| session.device_id.is_some() && s.device_id == session.device_id)
+                                .collect();
+
+                            let selected = self.merge_with
+                                .and_then(|id| candidates.iter().find(|s| s.session_id == id))
+                                .map_or("Pick a session".to_string(), |s| describe(s));
+                            ComboBox::from_id_salt("merge_with")
+                                .selected_text(selected)
+                                .show_ui(ui, |ui| {
+                                    for candidate in &candidates {
+                                        ui.selectable_value(&mut self.merge_with, Some(candidate.session_id), describe(candidate));
+                                    }
+                                });
+                            if session.device_id.is_none() {
+                                ui.label(RichText::new("The recording device of this session is unknown").small());
+                            } else if candidates.is_empty() {
+                                ui.label(RichText::new("No other finished sessions from this device").small());
+                            }
+                        }
+                    }
+                });
+
+                // A preview only counts for the values it was made with
+                let after = (self.kind, self.start_str.clone(), self.end_str.clone(), self.at_str.clone(), self.merge_with);
+                if before != after {
+                    self.preview = None;
+                    self.error = None;
+                }
+
+                let operation = self.operation(&session, sessions);
+                ui.add_space(5.0);
+
+                if let Err(problem) = &operation {
+                    ui.label(RichText::new(problem).color(Color32::RED).small());
+                }
+                if let Some((previewed, outcome)) = &self.preview {
+                    self.show_preview(ui, previewed, outcome);
+                }
+                if let Some(error) = &self.error {
+                    ui.label(RichText::new(error).color(Color32::RED));
+                }
+
+                ui.horizontal(|ui| {
+                    let operation = operation.ok();
+                    let previewed = operation.is_some() && self.preview.as_ref().map(|(op, _)| op) == operation.as_ref();
+
+                    if ui.add_enabled(!busy && operation.is_some(), eframe::egui::Button::new("Preview")).clicked() {
+                        self.error = None;
+                        if let Some(operation) = operation.clone() {
+                            self.issue(ui.ctx(), store, operation, false);
+                        }
+                    }
+
+                    let run_text = RichText::new(self.kind.label()).color(Color32::RED);
+                    if ui.add_enabled(!busy && previewed, eframe::egui::Button::new(run_text)).clicked() {
+                        if let Some(operation) = operation {
+                            self.issue(ui.ctx(), store, operation, true);
+                        }
+                    }
+
+                    if busy {
+                        ui.spinner();
+                    }
+                });
+            });
+
+        if !open {
+            self.reset();
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    fn default_trim_covers_the_whole_session() {
+        let session = Session {
+            session_id: 3,
+            started: Some("2025-01-18T18:30:29.252148".to_string()),
+            ended: Some("2025-01-18T18:45:02.918004".to_string()),
+            ..Default::default()
+        };
+        let mut dialog = OperationDialog::new();
+        dialog.open(&session);
+        dialog.kind = OperationKind::Trim;
+
+        match dialog.operation(&session, &[]) {
+            Ok(SessionOperation::Trim { start, end, .. }) => {
+                assert_eq!(Some(start), session.started);
+                assert_eq!(Some(end), session.ended);
+            }
+            other => panic!("expected a trim, got {:?}", other),
+        }
+    }
+}
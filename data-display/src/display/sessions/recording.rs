@@ -0,0 +1,259 @@
+//! Recording controls shown at the top of the Sessions window
+//!
+
+use eframe::egui::{Color32, ComboBox, RichText};
+
+use crate::backend::{BackendError, BackendFuture, RecordingState, RecordingStatus, Session};
+use crate::time;
+
+use crate::display::request::RequestChannel;
+use crate::display::store::AppStore;
+
+/// Time between recording status polls, ms
+const POLL_INTERVAL_MS: f64 = 1000.0;
+
+/// Format seconds as `HH:MM:SS`
+fn clock(secs: f64) -> String {
+    let secs = secs.max(0.0) as u64;
+    format!("{:02}:{:02}:{:02}", secs / 3600, (secs / 60) % 60, secs % 60)
+}
+
+/// Starts, pauses, resumes and stops the device recording and shows its progress
+pub struct RecordingPanel {
+    /// Latest status from the backend
+    status: Option<RecordingStatus>,
+    /// When `status` arrived, ms since the unix epoch. Elapsed time is counted on from here.
+    status_ms: f64,
+    /// When the last poll was sent, ms since the unix epoch
+    last_poll: f64,
+    /// Set when the backend has no recording control. Polling stops.
+    unavailable: Option<String>,
+    /// Session to record into, None for a new one
+    target_session: Option<i64>,
+    /// Why the last command failed
+    error: Option<String>,
+
+    status_requests: RequestChannel<Result<RecordingStatus, BackendError>>,
+    /// Results of start, pause, resume and stop, tagged with the command name
+    command_requests: RequestChannel<(&'static str, Result<RecordingStatus, BackendError>)>,
+}
+
+impl RecordingPanel {
+    pub fn new() -> Self {
+        RecordingPanel {
+            status: None,
+            status_ms: 0.0,
+            last_poll: 0.0,
+            unavailable: None,
+            target_session: None,
+            error: None,
+
+            status_requests: RequestChannel::new(),
+            command_requests: RequestChannel::new(),
+        }
+    }
+
+    /// Cancel pending requests and forget the previous user's recording
+    pub fn reset(&mut self) -> () {
+        self.status_requests.cancel();
+        self.command_requests.cancel();
+
+        self.status = None;
+        self.status_ms = 0.0;
+        self.last_poll = 0.0;
+        self.unavailable = None;
+        self.target_session = None;
+        self.error = None;
+    }
+
+    /// Session currently being recorded into, paused or not
+    pub fn recording_session(&self) -> Option<i64> {
+        self.status.as_ref().and_then(|status| status.session_id)
+    }
+
+    fn poll_status(&mut self, ctx: &eframe::egui::Context, store: &AppStore) -> () {
+        let token = match &store.state().token {
+            Some(token) => token.clone(),
+            None => return,
+        };
+
+        self.last_poll = time::now_ms();
+        let responder = self.status_requests.issue(ctx);
+        let request = store.backend().recording_status(&token);
+
+        wasm_bindgen_futures::spawn_local(async move {
+            responder.respond(request.await);
+        });
+    }
+
+    /// Send a command. Polling waits until it is answered so the two don't race.
+    fn send(&mut self, ctx: &eframe::egui::Context, name: &'static str, request: BackendFuture<RecordingStatus>) -> () {
+        self.error = None;
+        self.status_requests.cancel();
+
+        let responder = self.command_requests.issue(ctx);
+        wasm_bindgen_futures::spawn_local(async move {
+            responder.respond((name, request.await));
+        });
+    }
+
+    fn set_status(&mut self, status: RecordingStatus) -> () {
+        self.status_ms = time::now_ms();
+        self.status = Some(status);
+    }
+
+    /// Apply any responses that have arrived.
+    ///
+    /// Returns true if a command succeeded or the recording changed elsewhere,
+    ///     so the session list should be reloaded.
+    fn receive(&mut self, store: &AppStore) -> bool {
+        let mut changed = false;
+
+        match self.status_requests.poll() {
+            Some(Ok(status)) => {
+                // A recording started or stopped on the device changes which session is live
+                changed = self.status.as_ref().is_some_and(|previous| {
+                    (previous.state == RecordingState::Idle) != (status.state == RecordingState::Idle)
+                        || previous.session_id != status.session_id
+                });
+                self.set_status(status);
+            }
+            Some(Err(BackendError::Unsupported)) => {
+                self.unavailable = Some("This server does not support recording control".to_string());
+            }
+            Some(Err(e)) => store.report_error("Recording status", &e),
+            None => {}
+        }
+
+        match self.command_requests.poll() {
+            // Starting can create a session, and any command changes which one is recording
+            Some((_, Ok(status))) => {
+                self.set_status(status);
+                true
+            }
+            Some((name, Err(e))) => {
+                store.report_error(name, &e);
+                self.error = Some(match e {
+                    BackendError::Status(409) => format!("{} failed, the recording changed elsewhere", name),
+                    e => format!("{} failed. {}", name, e),
+                });
+                // Show what the device is actually doing rather than what was asked for
+                self.last_poll = 0.0;
+                changed
+            }
+            None => changed,
+        }
+    }
+
+    /// Draw the recording indicator and controls.
+    ///
+    /// Returns true if the session list should be reloaded.
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui, store: &AppStore, sessions: &[Session]) -> bool {
+        let busy = self.command_requests.is_pending();
+        let due = time::now_ms() - self.last_poll >= POLL_INTERVAL_MS;
+        if due && !busy && self.unavailable.is_none() && !self.status_requests.is_pending() {
+            self.poll_status(ui.ctx(), store);
+        }
+        let reload = self.receive(store);
+
+        ui.label(RichText::new("Recording").heading().underline());
+
+        if let Some(reason) = &self.unavailable {
+            ui.label(RichText::new(reason).color(Color32::YELLOW).small());
+            return reload;
+        }
+
+        let token = match &store.state().token {
+            Some(token) => token.clone(),
+            None => return reload,
+        };
+        let backend = store.backend();
+        let state = self.status.as_ref().map_or(RecordingState::Idle, |status| status.state);
+
+        ui.group(|ui| {
+            // Indicator
+            match &self.status {
+                Some(status) if status.state != RecordingState::Idle => {
+                    let mut elapsed = status.elapsed_secs;
+                    if status.state == RecordingState::Recording {
+                        elapsed += (time::now_ms() - self.status_ms) / 1000.0;
+                    }
+
+                    let (marker, color) = match status.state {
+                        RecordingState::Paused => ("⏸ PAUSED", Color32::YELLOW),
+                        _ => ("● REC", Color32::RED),
+                    };
+                    ui.horizontal(|ui| {
+                        ui.label(RichText::new(marker).color(color).strong());
+                        ui.label(clock(elapsed));
+                        ui.label(format!("{} samples", status.sample_count));
+                        if let Some(session_id) = status.session_id {
+                            ui.label(format!("Session {}", session_id));
+                        }
+                    });
+
+                    // Keep the clock ticking
+                    ui.ctx().request_repaint_after(std::time::Duration::from_millis(500));
+                }
+                Some(_) => {
+                    ui.label("Not recording");
+                }
+                None => {
+                    ui.label("---");
+                }
+            }
+
+            // Controls
+            ui.add_enabled_ui(!busy && self.status.is_some(), |ui| {
+                ui.horizontal(|ui| {
+                    match state {
+                        RecordingState::Idle => {
+                            let selected = match self.target_session {
+                                Some(session_id) => format!("Session {}", session_id),
+                                None => "New session".to_string(),
+                            };
+                            ComboBox::from_id_salt("recording_target")
+                                .selected_text(selected)
+                                .show_ui(ui, |ui| {
+                                    ui.selectable_value(&mut self.target_session, None, "New session");
+                                    for session in sessions {
+                                        ui.selectable_value(
+                                            &mut self.target_session,
+                                            Some(session.session_id),
+                                            format!("Session {}", session.session_id),
+                                        );
+                                    }
+                                });
+
+                            if ui.button(RichText::new("⏺ Start").color(Color32::RED)).clicked() {
+                                let target = self.target_session.map(|id| id.to_string());
+                                let request = backend.start_recording(&token, target.as_deref());
+                                self.send(ui.ctx(), "Start", request);
+                            }
+                        }
+                        RecordingState::Recording => {
+                            if ui.button("⏸ Pause").clicked() {
+                                self.send(ui.ctx(), "Pause", backend.pause_recording(&token));
+                            }
+                        }
+                        RecordingState::Paused => {
+                            if ui.button("⏵ Resume").clicked() {
+                                self.send(ui.ctx(), "Resume", backend.resume_recording(&token));
+                            }
+                        }
+                    }
+
+                    if state != RecordingState::Idle && ui.button("⏹ Stop").clicked() {
+                        self.send(ui.ctx(), "Stop", backend.stop_recording(&token));
+                    }
+                });
+            });
+
+            if let Some(error) = &self.error {
+                ui.label(RichText::new(error).color(Color32::RED));
+            }
+        });
+
+        reload
+    }
+}
@@ -0,0 +1,213 @@
+//! Shared application state store
+//!
+//! The display manager owns a single `AppStore`. Windows read the current
+//!     state through it and request changes by publishing `AppEvent`s, which
+//!     are applied once per frame after every window has been drawn.
+//!
+
+use std::rc::Rc;
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+use serde::{Deserialize, Serialize};
+
+use crate::backend::{AuthToken, Backend, BackendError, Session, SessionState};
+use crate::link::DeviceLink;
+
+/// eframe storage key the remembered login is kept under
+const LOGIN_KEY: &str = "remembered_login";
+
+/// Login kept in browser storage when "remember me" is ticked.
+///
+/// Only the token is stored, never the password.
+#[derive(Serialize, Deserialize)]
+pub struct RememberedLogin {
+    pub username: String,
+    pub token: String,
+    /// Session open in the data window when the app was last saved
+    pub last_session: String,
+}
+
+/// State shared between all windows
+#[derive(Default)]
+pub struct AppState {
+    pub logged_in: bool,
+    pub username: String,
+    /// Token from the last successful login, attached to every backend call
+    pub token: Option<AuthToken>,
+    /// Set when the user was sent back to the login window because their token expired
+    pub session_expired: bool,
+    /// Keep the login in browser storage across page reloads
+    pub remember: bool,
+    pub current_session: String,
+    /// The user's sessions as last listed by the Sessions window
+    pub sessions: Vec<Session>,
+    /// Bumped whenever session contents change, so windows holding their data reload it
+    pub sessions_revision: u64,
+}
+
+impl AppState {
+    /// State of a session in the last listing, None if it is not listed (yet)
+    pub fn session_state(&self, session_id: &str) -> Option<SessionState> {
+        self.sessions.iter()
+            .find(|s| s.session_id.to_string() == session_id)
+            .map(|s| s.state)
+    }
+}
+
+/// Changes a window can request to the shared state
+#[derive(Debug)]
+pub enum AppEvent {
+    /// A user has authenticated and received a token. `remember` keeps the login across reloads.
+    LoggedIn { username: String, token: AuthToken, remember: bool },
+    /// The current user has logged out
+    LoggedOut,
+    /// The backend rejected the current token
+    SessionExpired,
+    /// A session was picked for viewing in the data window
+    SessionSelected(String),
+    /// The session list was fetched from the backend
+    SessionsLoaded(Vec<Session>),
+    /// Datapoints were removed or moved between sessions. `removed` sessions no longer exist.
+    SessionsChanged { removed: Vec<i64> },
+}
+
+/// Owner of the shared state, the backend and the receiving end of the event channel
+pub struct AppStore {
+    state: AppState,
+    backend: Rc<dyn Backend>,
+    link: Rc<DeviceLink>,
+    sender: Sender<AppEvent>,
+    receiver: Receiver<AppEvent>,
+}
+
+impl AppStore {
+    pub fn new(backend: Rc<dyn Backend>) -> Self {
+        let (sender, receiver) = channel();
+
+        AppStore {
+            state: AppState::default(),
+            backend,
+            link: Rc::new(DeviceLink::new()),
+            sender,
+            receiver,
+        }
+    }
+
+    /// Read-only view of the current state
+    pub fn state(&self) -> &AppState {
+        &self.state
+    }
+
+    /// Shared handle to the backend windows send requests through
+    pub fn backend(&self) -> Rc<dyn Backend> {
+        self.backend.clone()
+    }
+
+    /// Shared handle to the direct link to the device
+    pub fn link(&self) -> Rc<DeviceLink> {
+        self.link.clone()
+    }
+
+    /// Log a failed backend call. An expired token sends the user back to the login window.
+    pub fn report_error(&self, context: &str, error: &BackendError) -> () {
+        web_sys::console::log_1(&format!("{} failed. {}", context, error).into());
+
+        if let BackendError::Unauthorized = error {
+            self.publish(AppEvent::SessionExpired);
+        }
+    }
+
+    /// Queue an event to be applied at the end of the frame
+    pub fn publish(&self, event: AppEvent) -> () {
+        // The receiver lives as long as the store, so this cannot fail
+        let _ = self.sender.send(event);
+    }
+
+    /// Handle for publishing events from inside async blocks
+    #[allow(dead_code)]
+    pub fn publisher(&self) -> Sender<AppEvent> {
+        self.sender.clone()
+    }
+
+    /// Apply every queued event to the state, in the order they were published.
+    ///
+    /// Returns true if any event was applied.
+    pub fn process_events(&mut self) -> bool {
+        let mut changed = false;
+
+        while let Ok(event) = self.receiver.try_recv() {
+            changed = true;
+
+            match event {
+                AppEvent::LoggedIn { username, token, remember } => {
+                    self.state.logged_in = true;
+                    self.state.username = username;
+                    self.state.token = Some(token);
+                    self.state.remember = remember;
+                    self.state.session_expired = false;
+                }
+                AppEvent::LoggedOut => {
+                    self.end_login();
+                    self.state.username.clear();
+                }
+                AppEvent::SessionExpired => {
+                    // Several windows may notice in the same frame
+                    if self.state.logged_in {
+                        self.state.session_expired = true;
+                    }
+                    // Username is kept so the login form can say who was logged out
+                    self.end_login();
+                }
+                AppEvent::SessionSelected(session) => {
+                    // Ignore selections that race a logout in the same frame
+                    if self.state.logged_in {
+                        self.state.current_session = session;
+                    }
+                }
+                AppEvent::SessionsLoaded(sessions) => {
+                    if self.state.logged_in {
+                        self.state.sessions = sessions;
+                    }
+                }
+                AppEvent::SessionsChanged { removed } => {
+                    self.state.sessions_revision += 1;
+                    if removed.iter().any(|id| id.to_string() == self.state.current_session) {
+                        self.state.current_session.clear();
+                    }
+                }
+            }
+        }
+
+        changed
+    }
+
+    /// Drop the token and everything cached for the user that was logged in
+    fn end_login(&mut self) -> () {
+        self.state.logged_in = false;
+        self.state.token = None;
+        self.state.remember = false;
+        self.state.current_session.clear();
+        self.state.sessions.clear();
+    }
+
+    /// Read the remembered login, if any, from eframe storage
+    pub fn load_login(storage: &dyn eframe::Storage) -> Option<RememberedLogin> {
+        eframe::get_value::<Option<RememberedLogin>>(storage, LOGIN_KEY).flatten()
+    }
+
+    /// Write the remembered login to eframe storage, or clear it if the user
+    ///     is logged out or did not ask to be remembered
+    pub fn save_login(&self, storage: &mut dyn eframe::Storage) -> () {
+        let login = match &self.state.token {
+            Some(token) if self.state.logged_in && self.state.remember => Some(RememberedLogin {
+                username: self.state.username.clone(),
+                token: token.as_str().to_string(),
+                last_session: self.state.current_session.clone(),
+            }),
+            _ => None,
+        };
+
+        eframe::set_value(storage, LOGIN_KEY, &login);
+        storage.flush();
+    }
+}
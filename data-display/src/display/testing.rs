@@ -0,0 +1,21 @@
+//! Fixtures shared by the unit tests
+//!
+
+use crate::time;
+
+use super::data::Row;
+
+/// 2025-01-18T00:00:00 UTC
+pub const DAY_MS: f64 = 1_737_158_400_000.0;
+
+/// A row with a GPS fix `secs` into the day
+pub fn fix(id: u32, secs: f64, latitude: f64, longitude: f64, altitude: f64) -> Row {
+    Row {
+        id,
+        timestamp: time::format_datetime(DAY_MS + secs * 1000.0),
+        latitude,
+        longitude,
+        altitude,
+        ..Default::default()
+    }
+}
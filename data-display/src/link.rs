@@ -0,0 +1,330 @@
+//! Direct WebSocket link to the data acquisition unit
+//!
+//! When the device is reachable on the local network the client connects to
+//!     it straight over a WebSocket and receives datapoints and status reports
+//!     as they are produced. Whenever the link is down everything falls back
+//!     to polling the server (the proxy), and the link keeps retrying in the
+//!     background with an increasing delay.
+//!
+//! Browsers cannot attach headers to a WebSocket, so the client authenticates
+//!     with its first message, carrying the secret of one of the user's
+//!     device keys: `{"type": "auth", "key": "..."}`. The device answers
+//!     `{"type": "ready"}` and starts streaming, or closes the socket with
+//!     code 4001 if it does not know the key.
+//!
+//! The device sends one JSON object per message, tagged by `type`:
+//! - `{"type": "datapoint", "session_id": 1, "datetime": ..., "id": ..., "data_blob": {...}}`
+//! - `{"type": "status", ...}` with the fields of `DeviceStatus`
+//!
+//! Pages served over https may not open `ws://` sockets, so the direct link
+//!     only works from an http deployment unless the device serves `wss://`.
+//!
+
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use serde::Deserialize;
+use serde_json::json;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::JsCast;
+use web_sys::{CloseEvent, Event, MessageEvent, WebSocket};
+
+use crate::backend::{ConnectionType, Datapoint, DeviceStatus};
+use crate::time;
+
+/// Port the device serves its WebSocket on
+pub const DIRECT_WS_PORT: u16 = 8765;
+/// Give up on a connection attempt after this long, ms
+const CONNECT_TIMEOUT_MS: f64 = 3000.0;
+/// Delay before the first retry, doubled for every further failure, s
+const BASE_RETRY_SECS: f64 = 5.0;
+const MAX_RETRY_SECS: f64 = 60.0;
+/// Datapoints held for the data window before the oldest are dropped
+const MAX_BUFFERED: usize = 10_000;
+/// Close code the device uses when it does not know the key it was sent
+const KEY_REJECTED_CODE: u16 = 4001;
+
+/// Messages the device sends over the link
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum DirectMessage {
+    /// The key was accepted, streaming starts
+    Ready,
+    Datapoint {
+        session_id: i64,
+        #[serde(flatten)]
+        datapoint: Datapoint,
+    },
+    Status(DeviceStatus),
+}
+
+/// Where the device is and the key to authenticate with
+#[derive(Clone, PartialEq)]
+pub struct LinkTarget {
+    pub url: String,
+    /// Secret of one of the user's device keys
+    pub key: String,
+}
+
+/// Where the link is in its lifecycle
+#[derive(Clone, PartialEq)]
+pub enum LinkState {
+    /// No device address or key to connect with
+    Idle,
+    /// Waiting for the socket to open and the device to accept the key.
+    ///     Holds when the attempt started, ms since the unix epoch.
+    Connecting(f64),
+    Open,
+    /// The last attempt failed or the link dropped, for the given reason
+    Failed(String),
+}
+
+/// State written by the socket callbacks
+struct Shared {
+    state: LinkState,
+    datapoints: VecDeque<(i64, Datapoint)>,
+    status: Option<DeviceStatus>,
+}
+
+/// An open socket and the callbacks it holds on to
+struct Socket {
+    ws: WebSocket,
+    _on_open: Closure<dyn FnMut(Event)>,
+    _on_message: Closure<dyn FnMut(MessageEvent)>,
+    _on_error: Closure<dyn FnMut(Event)>,
+    _on_close: Closure<dyn FnMut(CloseEvent)>,
+}
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        // Detach the callbacks before they are freed
+        self.ws.set_onopen(None);
+        self.ws.set_onmessage(None);
+        self.ws.set_onerror(None);
+        self.ws.set_onclose(None);
+        let _ = self.ws.close();
+    }
+}
+
+/// Connection to the device, shared by the windows through the store
+pub struct DeviceLink {
+    /// Device to connect to, None until its address and a key are known
+    target: RefCell<Option<LinkTarget>>,
+    socket: RefCell<Option<Socket>>,
+    shared: Rc<RefCell<Shared>>,
+    /// Consecutive failed attempts, drives the retry delay
+    failures: Cell<u32>,
+    /// No new attempt before this time, ms since the unix epoch
+    retry_at: Cell<f64>,
+}
+
+impl DeviceLink {
+    pub fn new() -> Self {
+        DeviceLink {
+            target: RefCell::new(None),
+            socket: RefCell::new(None),
+            shared: Rc::new(RefCell::new(Shared {
+                state: LinkState::Idle,
+                datapoints: VecDeque::new(),
+                status: None,
+            })),
+            failures: Cell::new(0),
+            retry_at: Cell::new(0.0),
+        }
+    }
+
+    /// Url to reach a device at the given address, if known.
+    ///
+    /// `DIRECT_WS_URL` set at compile time overrides it, e.g. to point at a local stand-in.
+    pub fn url_for(address: Option<&str>) -> Option<String> {
+        match option_env!("DIRECT_WS_URL") {
+            Some(url) if !url.is_empty() => Some(url.to_string()),
+            _ => address.map(|address| format!("ws://{}:{}", address, DIRECT_WS_PORT)),
+        }
+    }
+
+    /// Point the link at a device. A different target or key drops the current connection.
+    pub fn set_target(&self, target: Option<LinkTarget>) -> () {
+        if *self.target.borrow() == target {
+            return;
+        }
+
+        self.socket.borrow_mut().take();
+        self.shared.borrow_mut().state = LinkState::Idle;
+        self.failures.set(0);
+        self.retry_at.set(0.0);
+        *self.target.borrow_mut() = target;
+    }
+
+    /// Close the link and forget the device and anything it sent
+    pub fn disconnect(&self) -> () {
+        self.set_target(None);
+
+        let mut shared = self.shared.borrow_mut();
+        shared.datapoints.clear();
+        shared.status = None;
+    }
+
+    /// Open, time out and retry the connection as needed. Called once per frame.
+    pub fn tick(&self, ctx: &eframe::egui::Context) -> () {
+        let now = time::now_ms();
+        let state = self.state();
+
+        match state {
+            LinkState::Open => self.failures.set(0),
+            LinkState::Connecting(started) if now - started > CONNECT_TIMEOUT_MS => {
+                self.fail("Timed out".to_string());
+            }
+            LinkState::Failed(_) if self.socket.borrow().is_some() => {
+                // A callback reported the failure, schedule the retry
+                self.socket.borrow_mut().take();
+                let delay = (BASE_RETRY_SECS * 2f64.powi(self.failures.get().min(8) as i32)).min(MAX_RETRY_SECS);
+                self.failures.set(self.failures.get() + 1);
+                self.retry_at.set(now + delay * 1000.0);
+            }
+            LinkState::Idle | LinkState::Failed(_) if now >= self.retry_at.get() => {
+                if let Some(target) = self.target.borrow().clone() {
+                    self.connect(ctx, &target);
+                }
+            }
+            _ => {}
+        }
+
+        // Keep timeouts and retries running without user input
+        if self.target.borrow().is_some() {
+            ctx.request_repaint_after(std::time::Duration::from_secs(1));
+        }
+    }
+
+    /// Mark the current attempt as failed. The retry is scheduled by the next `tick`.
+    fn fail(&self, reason: String) -> () {
+        web_sys::console::log_1(&format!("Direct link failed. {}", reason).into());
+        self.shared.borrow_mut().state = LinkState::Failed(reason);
+    }
+
+    fn connect(&self, ctx: &eframe::egui::Context, target: &LinkTarget) -> () {
+        let ws = match WebSocket::new(&target.url) {
+            Ok(ws) => ws,
+            Err(e) => {
+                self.fail(format!("{:?}", e));
+                // Nothing was opened, so schedule the retry here
+                self.retry_at.set(time::now_ms() + BASE_RETRY_SECS * 1000.0);
+                return;
+            }
+        };
+
+        self.shared.borrow_mut().state = LinkState::Connecting(time::now_ms());
+
+        // The link counts as open once the device has accepted the key
+        let socket = ws.clone();
+        let auth = json!({ "type": "auth", "key": target.key }).to_string();
+        let on_open = Closure::<dyn FnMut(Event)>::new(move |_: Event| {
+            if let Err(e) = socket.send_with_str(&auth) {
+                web_sys::console::log_1(&format!("Direct link could not authenticate. {:?}", e).into());
+            }
+        });
+
+        let shared = self.shared.clone();
+        let repaint = ctx.clone();
+        let on_message = Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+            let text = match event.data().as_string() {
+                Some(text) => text,
+                None => return,
+            };
+
+            let mut shared = shared.borrow_mut();
+            match serde_json::from_str::<DirectMessage>(&text) {
+                Ok(DirectMessage::Ready) => shared.state = LinkState::Open,
+                Ok(DirectMessage::Datapoint { session_id, datapoint }) => {
+                    shared.datapoints.push_back((session_id, datapoint));
+                    while shared.datapoints.len() > MAX_BUFFERED {
+                        shared.datapoints.pop_front();
+                    }
+                }
+                Ok(DirectMessage::Status(status)) => shared.status = Some(status),
+                Err(e) => {
+                    web_sys::console::log_1(&format!("Ignored direct link message. {}", e).into());
+                    return;
+                }
+            }
+            repaint.request_repaint();
+        });
+
+        let shared = self.shared.clone();
+        let repaint = ctx.clone();
+        let on_error = Closure::<dyn FnMut(Event)>::new(move |_: Event| {
+            shared.borrow_mut().state = LinkState::Failed("Connection error".to_string());
+            repaint.request_repaint();
+        });
+
+        let shared = self.shared.clone();
+        let repaint = ctx.clone();
+        let on_close = Closure::<dyn FnMut(CloseEvent)>::new(move |event: CloseEvent| {
+            let mut shared = shared.borrow_mut();
+            // An error has already said why
+            if event.code() == KEY_REJECTED_CODE {
+                shared.state = LinkState::Failed("Device key rejected".to_string());
+            } else if !matches!(shared.state, LinkState::Failed(_)) {
+                shared.state = LinkState::Failed(format!("Closed ({})", event.code()));
+            }
+            repaint.request_repaint();
+        });
+
+        ws.set_onopen(Some(on_open.as_ref().unchecked_ref()));
+        ws.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+        ws.set_onerror(Some(on_error.as_ref().unchecked_ref()));
+        ws.set_onclose(Some(on_close.as_ref().unchecked_ref()));
+
+        *self.socket.borrow_mut() = Some(Socket {
+            ws,
+            _on_open: on_open,
+            _on_message: on_message,
+            _on_error: on_error,
+            _on_close: on_close,
+        });
+    }
+
+    pub fn state(&self) -> LinkState {
+        self.shared.borrow().state.clone()
+    }
+
+    /// Direct while the socket is open, otherwise data comes through the server
+    pub fn mode(&self) -> ConnectionType {
+        if self.shared.borrow().state == LinkState::Open {
+            ConnectionType::Direct
+        } else {
+            ConnectionType::Proxy
+        }
+    }
+
+    /// One line summary of the link for the Device window
+    pub fn describe(&self) -> String {
+        let target = self.target.borrow().as_ref().map(|target| target.url.clone()).unwrap_or_default();
+
+        match self.state() {
+            LinkState::Idle if target.is_empty() => "Direct link: no device address or key".to_string(),
+            LinkState::Idle => format!("Direct link: waiting to connect to {}", target),
+            LinkState::Connecting(_) => format!("Direct link: connecting to {}", target),
+            LinkState::Open => format!("Direct link: connected to {}", target),
+            LinkState::Failed(reason) => {
+                let retry_secs = (self.retry_at.get() - time::now_ms()) / 1000.0;
+                format!("Direct link: {}, retrying in {}", reason, time::format_duration(retry_secs))
+            }
+        }
+    }
+
+    /// Take every datapoint received so far, keeping those recorded into `session_id`
+    pub fn take_datapoints(&self, session_id: i64) -> Vec<Datapoint> {
+        self.shared.borrow_mut().datapoints
+            .drain(..)
+            .filter(|(id, _)| *id == session_id)
+            .map(|(_, datapoint)| datapoint)
+            .collect()
+    }
+
+    /// Take the latest status report, if a new one arrived
+    pub fn take_status(&self) -> Option<DeviceStatus> {
+        self.shared.borrow_mut().status.take()
+    }
+}
@@ -1,6 +1,9 @@
 //! \<CRATE DESCRIPTION HERE>
 
+mod backend;
 mod display;
+mod link;
+mod time;
 
 const CANVAS_ID: &str = "canvasId";
 
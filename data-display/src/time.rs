@@ -0,0 +1,171 @@
+//! Wall-clock helpers
+//!
+//! Timestamps exchanged with the backend are ISO 8601 strings without a
+//!     timezone, e.g. `2025-01-18T18:30:29.252148`, and are always UTC.
+//!
+
+/// Milliseconds since the unix epoch
+pub fn now_ms() -> f64 {
+    #[cfg(target_arch = "wasm32")]
+    {
+        js_sys::Date::now()
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs_f64() * 1000.0)
+            .unwrap_or(0.0)
+    }
+}
+
+/// Format milliseconds since the unix epoch as a backend timestamp
+pub fn format_datetime(ms: f64) -> String {
+    let micros = (ms * 1000.0).round() as i64;
+    let secs = micros.div_euclid(1_000_000);
+    let frac = micros.rem_euclid(1_000_000);
+
+    let days = secs.div_euclid(86_400);
+    let secs_of_day = secs.rem_euclid(86_400);
+    let (year, month, day) = civil_from_days(days);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        (secs_of_day / 60) % 60,
+        secs_of_day % 60,
+        frac
+    )
+}
+
+/// Parse a backend timestamp into milliseconds since the unix epoch.
+///
+/// The fractional part is optional. Returns None if the string is not in the expected format.
+pub fn parse_datetime(datetime: &str) -> Option<f64> {
+    let (date, time) = datetime.split_once('T')?;
+
+    let mut date_parts = date.splitn(3, '-');
+    let year = date_parts.next()?.parse::<i64>().ok()?;
+    let month = date_parts.next()?.parse::<u32>().ok()?;
+    let day = date_parts.next()?.parse::<u32>().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let (clock, frac) = match time.split_once('.') {
+        Some((clock, frac)) => (clock, frac),
+        None => (time, ""),
+    };
+    let mut clock_parts = clock.splitn(3, ':');
+    let hours = clock_parts.next()?.parse::<i64>().ok()?;
+    let minutes = clock_parts.next()?.parse::<i64>().ok()?;
+    let seconds = clock_parts.next()?.parse::<i64>().ok()?;
+    let frac_secs = if frac.is_empty() { 0.0 } else { format!("0.{}", frac).parse::<f64>().ok()? };
+
+    let secs = days_from_civil(year, month, day) * 86_400 + hours * 3600 + minutes * 60 + seconds;
+    Some((secs as f64 + frac_secs) * 1000.0)
+}
+
+/// Shorten a backend timestamp to the second for display
+pub fn short_datetime(datetime: &str) -> String {
+    datetime.split('.').next().unwrap_or(datetime).replace('T', " ")
+}
+
+/// Describe a span of seconds compactly, e.g. `45s`, `3m 12s` or `2h 5m`
+pub fn format_duration(secs: f64) -> String {
+    let secs = secs.max(0.0).round() as u64;
+
+    if secs < 60 {
+        format!("{}s", secs)
+    } else if secs < 3600 {
+        format!("{}m {}s", secs / 60, secs % 60)
+    } else {
+        format!("{}h {}m", secs / 3600, (secs / 60) % 60)
+    }
+}
+
+/// Convert days since the unix epoch to a (year, month, day) date.
+///
+/// Howard Hinnant's `civil_from_days` algorithm.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+/// Convert a (year, month, day) date to days since the unix epoch.
+///
+/// Howard Hinnant's `days_from_civil` algorithm.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    era * 146_097 + doe - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    fn datetimes_are_formatted_in_utc() {
+        assert_eq!(format_datetime(0.0), "1970-01-01T00:00:00.000000");
+        assert_eq!(format_datetime(1_737_225_029_252.148), "2025-01-18T18:30:29.252148");
+        assert_eq!(format_datetime(1_709_251_199_999.999), "2024-02-29T23:59:59.999999");
+    }
+
+    #[wasm_bindgen_test]
+    fn datetime_round_trip() {
+        let datetime = "2025-01-18T18:30:29.252148";
+        let ms = parse_datetime(datetime).unwrap();
+
+        assert!((ms - 1_737_225_029_252.148).abs() < 1e-3);
+        assert_eq!(format_datetime(ms), datetime);
+    }
+
+    #[wasm_bindgen_test]
+    fn datetime_round_trip_across_leap_day() {
+        for datetime in ["1970-01-01T00:00:00.000000", "2024-02-29T23:59:59.999999", "2100-03-01T00:00:00.000001"] {
+            assert_eq!(format_datetime(parse_datetime(datetime).unwrap()), datetime);
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn fraction_is_optional() {
+        assert_eq!(parse_datetime("2025-01-18T18:30:29"), Some(1_737_225_029_000.0));
+        assert_eq!(parse_datetime("2025-01-18T18:30:29.5"), Some(1_737_225_029_500.0));
+    }
+
+    #[wasm_bindgen_test]
+    fn bad_datetimes_are_rejected() {
+        for datetime in ["", "2025-01-18", "2025-01-18 18:30:29", "2025-13-01T00:00:00", "2025-01-32T00:00:00", "2025-01-18T18:30", "2025-01-18T18:30:xx", "2025-01-18T18:30:29.abc"] {
+            assert_eq!(parse_datetime(datetime), None, "{:?} should not parse", datetime);
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn durations_are_compact() {
+        assert_eq!(format_duration(45.0), "45s");
+        assert_eq!(format_duration(192.0), "3m 12s");
+        assert_eq!(format_duration(7500.0), "2h 5m");
+        assert_eq!(format_duration(-3.0), "0s");
+    }
+}
@@ -0,0 +1,102 @@
+#stand-in for the device's direct WebSocket link, streams random datapoints and status reports to clients that send its key
+#usage: python wsdevice.py [session_id] [port] [key]    (requires: pip install websockets)
+#a random key is generated and printed if none is given
+import asyncio
+import hmac
+import json
+import random
+import secrets
+import sys
+from datetime import datetime, timezone
+
+import websockets
+
+session_id = int(sys.argv[1]) if len(sys.argv) > 1 else 1
+port = int(sys.argv[2]) if len(sys.argv) > 2 else 8765
+key = sys.argv[3] if len(sys.argv) > 3 else secrets.token_hex(16)
+auth_timeout = 5
+#close code the client reads as a rejected key
+key_rejected = 4001
+sample_rate_hz = 10
+status_interval = 2
+
+def timestamp():
+    #backend timestamps are UTC without a timezone suffix
+    return datetime.now(timezone.utc).replace(tzinfo=None).isoformat(timespec="microseconds")
+
+def datapoint(id):
+    return {
+        "type": "datapoint",
+        "session_id": session_id,
+        "datetime": timestamp(),
+        "id": id,
+        "data_blob": {
+            "lat": 44.5672 + random.uniform(-0.0005, 0.0005),
+            "lon": -123.2786 + random.uniform(-0.0005, 0.0005),
+            "alt": random.uniform(70, 74),
+            "accel_x": random.uniform(-1, 1),
+            "accel_y": random.uniform(-1, 1),
+            "accel_z": random.uniform(9, 10.5),
+            "gyro_x": random.uniform(-5, 5),
+            "gyro_y": random.uniform(-5, 5),
+            "gyro_z": random.uniform(-5, 5),
+            "dac_1": random.uniform(0, 5),
+            "dac_2": random.uniform(0, 5),
+            "dac_3": random.uniform(0, 5),
+            "dac_4": random.uniform(0, 5),
+        },
+    }
+
+def status(battery):
+    return {
+        "type": "status",
+        "last_seen": timestamp(),
+        "connection": "direct",
+        "ipv4": "127.0.0.1",
+        "ipv6": "::1",
+        "cellular_dbm": random.uniform(-95, -75),
+        "cpu_temp_c": random.uniform(50, 60),
+        "cpu_load_pct": random.uniform(20, 60),
+        "battery_temp_c": random.uniform(28, 32),
+        "battery_pct": battery,
+    }
+
+async def authenticate(websocket):
+    #the first message must carry the device key
+    try:
+        message = json.loads(await asyncio.wait_for(websocket.recv(), auth_timeout))
+    except (asyncio.TimeoutError, ValueError, websockets.ConnectionClosed):
+        return False
+    sent = message.get("key") if isinstance(message, dict) and message.get("type") == "auth" else None
+    return isinstance(sent, str) and hmac.compare_digest(sent.encode(), key.encode())
+
+async def stream(websocket):
+    if not await authenticate(websocket):
+        print("client rejected")
+        await websocket.close(key_rejected, "unknown device key")
+        return
+    await websocket.send(json.dumps({"type": "ready"}))
+    print("client connected")
+    id = 1000000
+    battery = 100.0
+    ticks_per_status = sample_rate_hz * status_interval
+    tick = 0
+    try:
+        while True:
+            await websocket.send(json.dumps(datapoint(id)))
+            id += 1
+            if tick % ticks_per_status == 0:
+                await websocket.send(json.dumps(status(battery)))
+                battery = max(battery - 0.02, 0)
+            tick += 1
+            await asyncio.sleep(1 / sample_rate_hz)
+    except websockets.ConnectionClosed:
+        print("client disconnected")
+
+async def main():
+    async with websockets.serve(stream, "localhost", port):
+        print(f"streaming session {session_id} on ws://localhost:{port}")
+        print(f"device key: {key}")
+        await asyncio.Future()
+
+asyncio.run(main())